@@ -11,8 +11,11 @@ interface RunRustProps {
 interface RustApiResponse {
   stdout?: string;
   stderr?: string;
+  anonymous_session?: string;
 }
 
+const ANONYMOUS_SESSION_KEY = "rust_anonymous_session";
+
 export async function RunRust({
   setOutput,
   setIsRunning,
@@ -23,9 +26,15 @@ export async function RunRust({
   setStatus("idle");
   setOutput("");
   try {
-    const data: RustApiResponse = await api.post("/run", {
-      code,
-    });
+    const session = localStorage.getItem(ANONYMOUS_SESSION_KEY);
+    const data: RustApiResponse = await api.post(
+      "/run",
+      { code },
+      session ? { headers: { "X-Anonymous-Session": session } } : undefined,
+    );
+    if (data.anonymous_session) {
+      localStorage.setItem(ANONYMOUS_SESSION_KEY, data.anonymous_session);
+    }
     if (data.stderr) {
       setStatus("error");
       setOutput(data.stderr);
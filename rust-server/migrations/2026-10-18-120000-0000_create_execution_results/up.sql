@@ -0,0 +1,19 @@
+CREATE TABLE execution_results (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    -- Saving a notebook deletes and re-inserts its blocks, so the check waits
+    -- for the end of the transaction instead of cascading.
+    block_id UUID NOT NULL REFERENCES blocks(id) DEFERRABLE INITIALLY DEFERRED,
+    notebook_id UUID NOT NULL REFERENCES notebooks(id) ON DELETE CASCADE,
+    user_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    stdout TEXT NOT NULL DEFAULT '',
+    stderr TEXT NOT NULL DEFAULT '',
+    exit_code INTEGER,
+    termination JSONB,
+    duration_ms BIGINT NOT NULL,
+    toolchain_version TEXT NOT NULL,
+    code_hash VARCHAR(64) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_execution_results_block ON execution_results(block_id, created_at DESC);
+CREATE INDEX idx_execution_results_notebook ON execution_results(notebook_id);
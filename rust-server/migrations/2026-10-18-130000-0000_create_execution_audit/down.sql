@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE execution_audit;
+DROP TABLE audit_code_bodies;
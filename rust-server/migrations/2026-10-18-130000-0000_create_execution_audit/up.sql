@@ -0,0 +1,28 @@
+-- Submitted code, stored once per distinct body. `last_seen_at` keeps the
+-- retention job from deleting a body a new record is about to reference.
+CREATE TABLE audit_code_bodies (
+    code_hash VARCHAR(64) PRIMARY KEY,
+    code TEXT NOT NULL,
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE execution_audit (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    anonymous_session UUID,
+    client_ip VARCHAR(45) NOT NULL,
+    user_agent TEXT NOT NULL,
+    endpoint VARCHAR(16) NOT NULL,
+    code_hash VARCHAR(64) NOT NULL REFERENCES audit_code_bodies(code_hash),
+    verdict VARCHAR(32) NOT NULL,
+    violations JSONB NOT NULL DEFAULT '[]',
+    termination JSONB,
+    stats JSONB,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_execution_audit_created ON execution_audit(created_at DESC);
+CREATE INDEX idx_execution_audit_user ON execution_audit(user_id, created_at DESC);
+CREATE INDEX idx_execution_audit_ip ON execution_audit(client_ip, created_at DESC);
+CREATE INDEX idx_execution_audit_verdict ON execution_audit(verdict, created_at DESC);
+CREATE INDEX idx_execution_audit_code ON execution_audit(code_hash);
@@ -0,0 +1,87 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use hyper::HeaderMap;
+
+use crate::{
+    controllers::{jwt::extract_admin_claims_from_header, utils::get_conn},
+    file::cache::PurgeSummary,
+    models::{
+        audit::{
+            AuditCodeBody, AuditRecord, AuditSearchQuery, find_audit_code, search_audit_records,
+        },
+        error::ApiError,
+        state::AppState,
+    },
+};
+
+#[utoipa::path(
+    delete,
+    path = "/admin/run-cache",
+    responses((status = OK), (status = 401, body = ApiError), (status = 403, body = ApiError))
+)]
+pub async fn api_purge_build_cache(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<PurgeSummary>, ApiError> {
+    let admin = extract_admin_claims_from_header(&headers).await?.1;
+
+    let summary = state.build_cache.purge().await;
+    println!(
+        "LOG: Cache de compilação limpo por {} ({} entradas, {} bytes)",
+        admin.email, summary.entries, summary.bytes
+    );
+
+    Ok(Json(summary))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/audit",
+    responses((status = OK), (status = 401, body = ApiError), (status = 403, body = ApiError))
+)]
+pub async fn api_search_audit(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(query): Query<AuditSearchQuery>,
+) -> Result<Json<Vec<AuditRecord>>, ApiError> {
+    extract_admin_claims_from_header(&headers).await?;
+
+    let conn = &mut get_conn(&state.pool)
+        .await
+        .map_err(|e| ApiError::DatabaseConnection(e.1.0.to_string()))?;
+
+    let records = search_audit_records(conn, &query)
+        .await
+        .map_err(ApiError::Database)?;
+
+    Ok(Json(records))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/audit/code/{code_hash}",
+    responses((status = OK), (status = 401, body = ApiError), (status = 403, body = ApiError))
+)]
+pub async fn api_get_audit_code(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(code_hash): Path<String>,
+) -> Result<Json<AuditCodeBody>, ApiError> {
+    extract_admin_claims_from_header(&headers).await?;
+
+    let conn = &mut get_conn(&state.pool)
+        .await
+        .map_err(|e| ApiError::DatabaseConnection(e.1.0.to_string()))?;
+
+    match find_audit_code(conn, &code_hash)
+        .await
+        .map_err(ApiError::Database)?
+    {
+        Some(body) => Ok(Json(body)),
+        None => Err(ApiError::Request("Código não encontrado".to_string())),
+    }
+}
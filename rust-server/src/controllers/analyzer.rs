@@ -0,0 +1,193 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use tokio::process::{Child, Command};
+use tokio::sync::OnceCell;
+
+use crate::controllers::utils::{get_parsed_var_from_env, get_var_from_env};
+use crate::http::identity::WorkspaceOwner;
+use crate::models::error::ApiError;
+use crate::sec::sandbox::{SandboxConfig, apply_sandbox, load_sandbox_config};
+
+const BUSY_RETRY_SECS: u64 = 30;
+
+// rust-analyzer runs a thread pool and keeps the sysroot sources open, far
+// beyond what the limits for user programs allow.
+const ANALYZER_MAX_PROCESSES: u64 = 512;
+const ANALYZER_MAX_OPEN_FILES: u64 = 4096;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AnalyzerConfig {
+    /// Binary to run; looked up in the default toolchain's `bin` first.
+    pub program: String,
+    pub max_running: usize,
+    pub max_per_owner: usize,
+    /// Seconds without a message from the editor before the analyzer stops.
+    pub idle_timeout_secs: u64,
+    /// How often the notebook's module blocks are written to the workspace.
+    pub sync_interval_secs: u64,
+    pub memory_bytes: u64,
+    pub cpu_seconds: u64,
+}
+
+impl AnalyzerConfig {
+    pub fn from_env() -> Self {
+        Self {
+            program: get_var_from_env("RUST_ANALYZER_BIN")
+                .unwrap_or_else(|_| "rust-analyzer".to_string()),
+            max_running: get_parsed_var_from_env("LSP_MAX_ANALYZERS", 4),
+            max_per_owner: get_parsed_var_from_env::<usize>("LSP_MAX_PER_USER", 2).max(1),
+            idle_timeout_secs: get_parsed_var_from_env::<u64>("LSP_IDLE_SECONDS", 600).max(1),
+            sync_interval_secs: get_parsed_var_from_env::<u64>("LSP_SYNC_SECONDS", 10).max(1),
+            memory_bytes: get_parsed_var_from_env::<u64>("LSP_MEMORY_MB", 4096) * 1024 * 1024,
+            cpu_seconds: get_parsed_var_from_env("LSP_CPU_SECONDS", 3600),
+        }
+    }
+}
+
+#[derive(Default)]
+struct AnalyzerState {
+    running: HashMap<WorkspaceOwner, usize>,
+    running_total: usize,
+    started: u64,
+    rejected: u64,
+    idle_shutdowns: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnalyzerMetrics {
+    pub running: usize,
+    pub max_running: usize,
+    pub started: u64,
+    pub rejected: u64,
+    pub idle_shutdowns: u64,
+}
+
+/// Caps how many rust-analyzer processes run at once, overall and per owner.
+/// Analyzers are long-lived, so there is no queue: a request over the cap is
+/// turned away.
+pub struct AnalyzerPool {
+    config: AnalyzerConfig,
+    state: Mutex<AnalyzerState>,
+}
+
+pub struct AnalyzerSlot {
+    pool: Arc<AnalyzerPool>,
+    owner: WorkspaceOwner,
+}
+
+impl Drop for AnalyzerSlot {
+    fn drop(&mut self) {
+        let mut state = self.pool.state.lock().unwrap();
+        state.running_total = state.running_total.saturating_sub(1);
+        if let Some(count) = state.running.get_mut(&self.owner) {
+            *count -= 1;
+            if *count == 0 {
+                state.running.remove(&self.owner);
+            }
+        }
+    }
+}
+
+impl AnalyzerPool {
+    pub fn new(config: AnalyzerConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(AnalyzerState::default()),
+        }
+    }
+
+    pub fn config(&self) -> &AnalyzerConfig {
+        &self.config
+    }
+
+    pub fn acquire(self: &Arc<Self>, owner: WorkspaceOwner) -> Result<AnalyzerSlot, ApiError> {
+        let mut state = self.state.lock().unwrap();
+        let owner_running = state.running.get(&owner).copied().unwrap_or(0);
+        if state.running_total >= self.config.max_running
+            || owner_running >= self.config.max_per_owner
+        {
+            state.rejected += 1;
+            return Err(ApiError::AnalyzersBusy {
+                retry_after_secs: BUSY_RETRY_SECS,
+            });
+        }
+
+        *state.running.entry(owner).or_insert(0) += 1;
+        state.running_total += 1;
+        state.started += 1;
+        Ok(AnalyzerSlot {
+            pool: self.clone(),
+            owner,
+        })
+    }
+
+    pub fn record_idle_shutdown(&self) {
+        self.state.lock().unwrap().idle_shutdowns += 1;
+    }
+
+    pub fn metrics(&self) -> AnalyzerMetrics {
+        let state = self.state.lock().unwrap();
+        AnalyzerMetrics {
+            running: state.running_total,
+            max_running: self.config.max_running,
+            started: state.started,
+            rejected: state.rejected,
+            idle_shutdowns: state.idle_shutdowns,
+        }
+    }
+
+    /// Starts rust-analyzer on `workspace` inside the sandbox, speaking LSP
+    /// over its stdin and stdout.
+    pub async fn spawn(&self, workspace: &Path) -> std::io::Result<Child> {
+        let sysroot = default_sysroot().await;
+        let sandbox = SandboxConfig {
+            memory_bytes: self.config.memory_bytes,
+            cpu_seconds: self.config.cpu_seconds,
+            max_processes: ANALYZER_MAX_PROCESSES,
+            max_open_files: ANALYZER_MAX_OPEN_FILES,
+            ..load_sandbox_config().clone()
+        }
+        .exposing(sysroot);
+
+        let mut path = String::new();
+        if let Some(sysroot) = sysroot {
+            path.push_str(&format!("{}:", sysroot.join("bin").display()));
+        }
+        path.push_str("/usr/local/bin:/usr/bin:/bin");
+
+        let mut command = Command::new(&self.config.program);
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true);
+        apply_sandbox(&mut command, &sandbox, workspace)?;
+        command.env("PATH", path).env("CARGO_NET_OFFLINE", "true");
+
+        command.spawn()
+    }
+}
+
+/// Sysroot of the server's default toolchain, so the analyzer and the cargo
+/// it runs do not depend on rustup proxies outside the sandbox's PATH.
+pub async fn default_sysroot() -> Option<&'static PathBuf> {
+    static SYSROOT: OnceCell<Option<PathBuf>> = OnceCell::const_new();
+    SYSROOT
+        .get_or_init(|| async {
+            let output = Command::new("rustc")
+                .args(["--print", "sysroot"])
+                .output()
+                .await
+                .ok()?;
+            if !output.status.success() {
+                return None;
+            }
+            let sysroot = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            Some(PathBuf::from(sysroot))
+        })
+        .await
+        .as_ref()
+}
@@ -0,0 +1,445 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc};
+use uuid::Uuid;
+
+use crate::CodeResponse;
+use crate::controllers::utils::get_parsed_var_from_env;
+use crate::http::identity::WorkspaceOwner;
+use crate::models::error::ApiError;
+use crate::models::execution::{ExecutionContext, ExecutionEvent, ExecutionPhase};
+use crate::sec::sandbox::Termination;
+
+/// Output kept for replaying to late subscribers; status events are always
+/// kept.
+const MAX_EVENT_LOG_BYTES: usize = 1024 * 1024;
+const SUBSCRIBER_BUFFER: usize = 256;
+const MIN_SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobConfig {
+    pub retention_secs: u64,
+    pub max_active_per_owner: usize,
+    pub max_retained_per_owner: usize,
+}
+
+impl JobConfig {
+    pub fn from_env() -> Self {
+        Self {
+            retention_secs: get_parsed_var_from_env::<u64>("JOB_RETENTION_MINUTES", 15) * 60,
+            max_active_per_owner: get_parsed_var_from_env::<usize>("JOB_MAX_ACTIVE_PER_USER", 4)
+                .max(1),
+            max_retained_per_owner: get_parsed_var_from_env::<usize>(
+                "JOB_MAX_RETAINED_PER_USER",
+                20,
+            )
+            .max(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    Run,
+    Project,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            JobStatus::Succeeded | JobStatus::Failed | JobStatus::Cancelled
+        )
+    }
+}
+
+/// Why a job ended without a response, in the shape of the API's errors.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobError {
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobView {
+    pub id: Uuid,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phase: Option<ExecutionPhase>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue_position: Option<usize>,
+    pub created_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    /// When the finished job is forgotten.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<JobError>,
+    /// Set once output stopped being kept for replay.
+    pub events_truncated: bool,
+}
+
+struct Job {
+    owner: WorkspaceOwner,
+    kind: JobKind,
+    status: JobStatus,
+    phase: Option<ExecutionPhase>,
+    queue_position: Option<usize>,
+    created_at: DateTime<Utc>,
+    started_at: Option<DateTime<Utc>>,
+    finished_at: Option<DateTime<Utc>>,
+    finished: Option<Instant>,
+    result: Option<serde_json::Value>,
+    error: Option<JobError>,
+    /// Serialized events, replayed to each new subscriber.
+    events: Vec<Arc<str>>,
+    event_bytes: usize,
+    events_truncated: bool,
+    /// Dropped when the job finishes, which ends every subscription.
+    live: Option<broadcast::Sender<Arc<str>>>,
+    cancel: tokio_util::sync::CancellationToken,
+}
+
+impl Job {
+    fn view(&self, id: Uuid, with_result: bool, retention: Duration) -> JobView {
+        JobView {
+            id,
+            kind: self.kind,
+            status: self.status,
+            phase: self.phase,
+            queue_position: self
+                .queue_position
+                .filter(|_| self.status == JobStatus::Queued),
+            created_at: self.created_at,
+            started_at: self.started_at,
+            finished_at: self.finished_at,
+            expires_at: self
+                .finished_at
+                .and_then(|at| chrono::Duration::from_std(retention).ok().map(|d| at + d)),
+            result: self.result.clone().filter(|_| with_result),
+            error: self.error.clone(),
+            events_truncated: self.events_truncated,
+        }
+    }
+
+    fn publish(&mut self, event: &ExecutionEvent) {
+        let json: Arc<str> = match serde_json::to_string(event) {
+            Ok(json) => json.into(),
+            Err(e) => {
+                eprintln!("ERRO: Falha ao serializar evento de job: {}", e);
+                return;
+            }
+        };
+
+        let is_output = matches!(event, ExecutionEvent::Output { .. });
+        if is_output && self.event_bytes + json.len() > MAX_EVENT_LOG_BYTES {
+            self.events_truncated = true;
+        } else {
+            self.event_bytes += json.len();
+            self.events.push(json.clone());
+        }
+
+        if let Some(live) = &self.live {
+            let _ = live.send(json);
+        }
+    }
+}
+
+/// Events so far, and the live ones after them unless the job is over.
+pub struct JobSubscription {
+    pub replay: Vec<Arc<str>>,
+    pub live: Option<broadcast::Receiver<Arc<str>>>,
+}
+
+#[derive(Default)]
+struct JobState {
+    jobs: HashMap<Uuid, Job>,
+    submitted: u64,
+    cancelled: u64,
+    expired: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobMetrics {
+    pub active: usize,
+    pub retained: usize,
+    pub submitted: u64,
+    pub cancelled: u64,
+    pub expired: u64,
+}
+
+/// Runs submitted code in the background and keeps its status, events and
+/// result for polling, subscribing and cancelling. Each job is visible only
+/// to the user or anonymous session that submitted it, and finished ones are
+/// forgotten after the retention period.
+pub struct JobRegistry {
+    config: JobConfig,
+    state: Mutex<JobState>,
+}
+
+impl JobRegistry {
+    pub fn new(config: JobConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(JobState::default()),
+        }
+    }
+
+    fn retention(&self) -> Duration {
+        Duration::from_secs(self.config.retention_secs)
+    }
+
+    /// Starts `run` with a context whose events and cancellation belong to a
+    /// new job.
+    pub fn spawn<F, Fut>(
+        self: &Arc<Self>,
+        owner: WorkspaceOwner,
+        kind: JobKind,
+        run: F,
+    ) -> Result<JobView, ApiError>
+    where
+        F: FnOnce(ExecutionContext) -> Fut,
+        Fut: Future<Output = Result<CodeResponse, ApiError>> + Send + 'static,
+    {
+        let (tx, mut rx) = mpsc::unbounded_channel::<ExecutionEvent>();
+        let ctx = ExecutionContext::streaming(tx);
+        let id = Uuid::new_v4();
+
+        let view = {
+            let mut state = self.state.lock().unwrap();
+            let active = state
+                .jobs
+                .values()
+                .filter(|job| job.owner == owner && !job.status.is_finished())
+                .count();
+            if active >= self.config.max_active_per_owner {
+                return Err(ApiError::TooManyJobs {
+                    limit: self.config.max_active_per_owner,
+                });
+            }
+            self.forget_oldest(&mut state, owner);
+
+            let job = Job {
+                owner,
+                kind,
+                status: JobStatus::Queued,
+                phase: None,
+                queue_position: None,
+                created_at: Utc::now(),
+                started_at: None,
+                finished_at: None,
+                finished: None,
+                result: None,
+                error: None,
+                events: vec![],
+                event_bytes: 0,
+                events_truncated: false,
+                live: Some(broadcast::channel(SUBSCRIBER_BUFFER).0),
+                cancel: ctx.cancel.clone(),
+            };
+            let view = job.view(id, false, self.retention());
+            state.jobs.insert(id, job);
+            state.submitted += 1;
+            view
+        };
+
+        println!("LOG: Job {} criado para {}", id, owner.key());
+
+        let running = tokio::spawn(run(ctx));
+        let registry = self.clone();
+        tokio::spawn(async move {
+            while let Some(event) = rx.recv().await {
+                registry.record(id, event);
+            }
+            let outcome = match running.await {
+                Ok(outcome) => outcome,
+                Err(e) => Err(ApiError::Request(format!("execution aborted: {}", e))),
+            };
+            registry.finish(id, outcome);
+        });
+
+        Ok(view)
+    }
+
+    /// Makes room for one more job by dropping the owner's oldest finished
+    /// ones.
+    fn forget_oldest(&self, state: &mut JobState, owner: WorkspaceOwner) {
+        let mut finished: Vec<(Uuid, DateTime<Utc>)> = state
+            .jobs
+            .iter()
+            .filter(|(_, job)| job.owner == owner && job.status.is_finished())
+            .map(|(id, job)| (*id, job.created_at))
+            .collect();
+        let retained = state.jobs.values().filter(|job| job.owner == owner).count();
+        let excess = (retained + 1).saturating_sub(self.config.max_retained_per_owner);
+
+        finished.sort_by_key(|(_, created_at)| *created_at);
+        for (id, _) in finished.into_iter().take(excess) {
+            state.jobs.remove(&id);
+        }
+    }
+
+    fn record(&self, id: Uuid, event: ExecutionEvent) {
+        let mut state = self.state.lock().unwrap();
+        let Some(job) = state.jobs.get_mut(&id) else {
+            return;
+        };
+
+        match &event {
+            ExecutionEvent::Phase { phase, .. } => {
+                job.phase = Some(*phase);
+                if matches!(phase, ExecutionPhase::Compiling | ExecutionPhase::Running)
+                    && job.status == JobStatus::Queued
+                {
+                    job.status = JobStatus::Running;
+                    job.started_at = Some(Utc::now());
+                }
+            }
+            ExecutionEvent::QueuePosition { position, .. } => {
+                job.queue_position = Some(*position);
+            }
+            _ => {}
+        }
+        job.publish(&event);
+    }
+
+    fn finish(&self, id: Uuid, outcome: Result<CodeResponse, ApiError>) {
+        let mut state = self.state.lock().unwrap();
+        let Some(job) = state.jobs.get_mut(&id) else {
+            return;
+        };
+
+        let final_event = match outcome {
+            Ok(response) => {
+                job.status = match response.termination {
+                    Some(Termination::Cancelled) => JobStatus::Cancelled,
+                    _ => JobStatus::Succeeded,
+                };
+                job.result = serde_json::to_value(&response).ok();
+                ExecutionEvent::Result {
+                    response: Box::new(response),
+                }
+            }
+            Err(e) => {
+                job.status = JobStatus::Failed;
+                job.error = Some(JobError {
+                    code: e.error_code(),
+                    message: e.to_string(),
+                    retry_after: e.retry_after_secs(),
+                });
+                ExecutionEvent::Error {
+                    message: e.to_string(),
+                    retry_after: e.retry_after_secs(),
+                }
+            }
+        };
+        job.finished_at = Some(Utc::now());
+        job.finished = Some(Instant::now());
+        job.publish(&final_event);
+        job.live = None;
+
+        println!("LOG: Job {} finalizado ({:?})", id, job.status);
+    }
+
+    pub fn get(&self, id: Uuid, owner: WorkspaceOwner) -> Option<JobView> {
+        let state = self.state.lock().unwrap();
+        let job = state.jobs.get(&id).filter(|job| job.owner == owner)?;
+        Some(job.view(id, true, self.retention()))
+    }
+
+    /// The owner's jobs, newest first and without their results.
+    pub fn list(&self, owner: WorkspaceOwner) -> Vec<JobView> {
+        let state = self.state.lock().unwrap();
+        let mut jobs: Vec<JobView> = state
+            .jobs
+            .iter()
+            .filter(|(_, job)| job.owner == owner)
+            .map(|(id, job)| job.view(*id, false, self.retention()))
+            .collect();
+        jobs.sort_by_key(|job| std::cmp::Reverse(job.created_at));
+        jobs
+    }
+
+    /// Stops the job's build or program; it finishes as cancelled shortly
+    /// after.
+    pub fn cancel(&self, id: Uuid, owner: WorkspaceOwner) -> Option<JobView> {
+        let mut state = self.state.lock().unwrap();
+        let job = state.jobs.get(&id).filter(|job| job.owner == owner)?;
+        let view = job.view(id, false, self.retention());
+        if !job.status.is_finished() && !job.cancel.is_cancelled() {
+            job.cancel.cancel();
+            state.cancelled += 1;
+            println!("LOG: Job {} cancelado pelo cliente", id);
+        }
+        Some(view)
+    }
+
+    pub fn subscribe(&self, id: Uuid, owner: WorkspaceOwner) -> Option<JobSubscription> {
+        let state = self.state.lock().unwrap();
+        let job = state.jobs.get(&id).filter(|job| job.owner == owner)?;
+        Some(JobSubscription {
+            replay: job.events.clone(),
+            live: job.live.as_ref().map(|live| live.subscribe()),
+        })
+    }
+
+    fn purge_expired(&self) {
+        let retention = self.retention();
+        let mut state = self.state.lock().unwrap();
+        let before = state.jobs.len();
+        state
+            .jobs
+            .retain(|_, job| job.finished.is_none_or(|at| at.elapsed() < retention));
+        let expired = before - state.jobs.len();
+        state.expired += expired as u64;
+        if expired > 0 {
+            println!("LOG: {} jobs expirados removidos", expired);
+        }
+    }
+
+    pub async fn run(self: Arc<Self>) {
+        let interval = (self.retention() / 4).max(MIN_SWEEP_INTERVAL);
+        loop {
+            tokio::time::sleep(interval).await;
+            self.purge_expired();
+        }
+    }
+
+    pub fn metrics(&self) -> JobMetrics {
+        let state = self.state.lock().unwrap();
+        JobMetrics {
+            active: state
+                .jobs
+                .values()
+                .filter(|job| !job.status.is_finished())
+                .count(),
+            retained: state.jobs.len(),
+            submitted: state.submitted,
+            cancelled: state.cancelled,
+            expired: state.expired,
+        }
+    }
+}
@@ -1,12 +1,18 @@
 use std::env;
 
-use crate::models::{error::ApiError, jwt::Claims, user::UserAuthInfo};
+use crate::models::{
+    error::ApiError,
+    jwt::{AnonymousClaims, Claims},
+    user::{UserAuthInfo, UserRole},
+};
 use axum::{body::Body, extract::Request, middleware::Next, response::Response};
 use dotenvy::dotenv;
 use hyper::{HeaderMap, StatusCode};
 use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation, decode};
+use uuid::Uuid;
 
 const JWT_EXP_HOURS: i64 = 24 * 7;
+const ANONYMOUS_SESSION_EXP_HOURS: i64 = 24;
 
 pub async fn jwt_auth(req: Request<Body>, next: Next) -> Result<Response, ApiError> {
     let _ = extract_claims_from_header(req.headers()).await?;
@@ -127,6 +133,18 @@ pub fn extract_token_from_ws(headers: &HeaderMap) -> Option<String> {
         })
 }
 
+pub async fn extract_admin_claims_from_header(
+    headers: &HeaderMap,
+) -> Result<(String, Claims), ApiError> {
+    let (token, claims) = extract_claims_from_header(headers).await?;
+
+    if claims.role != UserRole::Admin {
+        return Err(ApiError::AdminRequired);
+    }
+
+    Ok((token, claims))
+}
+
 pub async fn extract_claims_from_ws_headers(
     headers: &HeaderMap,
 ) -> Result<(String, Claims), ApiError> {
@@ -145,3 +163,38 @@ pub async fn extract_claims_from_ws_headers(
 
     Ok((token, decoded.claims))
 }
+
+pub fn generate_anonymous_session(sid: Uuid) -> Result<String, ApiError> {
+    let expiration = chrono::Utc::now()
+        .checked_add_signed(chrono::Duration::hours(ANONYMOUS_SESSION_EXP_HOURS))
+        .expect("Invalid timestamp")
+        .timestamp() as usize;
+
+    let claims = AnonymousClaims {
+        sid,
+        exp: expiration,
+    };
+
+    let secret = get_jwt_secret_from_env()?;
+
+    match jsonwebtoken::encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret.as_ref()),
+    ) {
+        Ok(token) => Ok(token),
+        Err(e) => Err(ApiError::CreateToken(e.to_string())),
+    }
+}
+
+pub fn decode_anonymous_session(token: &str) -> Result<AnonymousClaims, ApiError> {
+    let secret = get_jwt_secret_from_env()?;
+
+    decode::<AnonymousClaims>(
+        token,
+        &DecodingKey::from_secret(secret.as_bytes()),
+        &Validation::new(Algorithm::HS256),
+    )
+    .map(|data| data.claims)
+    .map_err(|_| ApiError::InvalidAuthorizationToken)
+}
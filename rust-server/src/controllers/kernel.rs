@@ -0,0 +1,904 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::{Mutex as AsyncMutex, mpsc};
+use uuid::Uuid;
+
+use crate::controllers::analyzer::default_sysroot;
+use crate::controllers::utils::{get_parsed_var_from_env, get_var_from_env};
+use crate::file::stats::{ExecutionStats, wait_for_cpu_time};
+use crate::file::workspace::WorkspaceLease;
+use crate::http::identity::WorkspaceOwner;
+use crate::models::error::ApiError;
+use crate::models::execution::{ExecutionContext, ExecutionEvent, OutputStream};
+use crate::sec::sandbox::{SandboxConfig, Termination, apply_sandbox, load_sandbox_config};
+
+const BUSY_RETRY_SECS: u64 = 30;
+const MIN_SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+const MAX_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+// Every input is compiled by cargo and rustc, which run inside the kernel's
+// sandbox alongside evcxr and its child process.
+const KERNEL_MAX_PROCESSES: u64 = 512;
+const KERNEL_MAX_OPEN_FILES: u64 = 4096;
+const KERNEL_MAX_FILE_SIZE_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Exit code of a native program that panicked.
+const PANIC_EXIT_CODE: i32 = 101;
+
+/// How long a new kernel has to answer its first command.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long an interrupted input has to finish before the kernel is stopped.
+const INTERRUPT_GRACE: Duration = Duration::from_secs(10);
+/// evcxr relays the program's stderr from another thread, so some of it can
+/// arrive just after the input is reported done.
+const STDERR_GRACE: Duration = Duration::from_millis(50);
+
+// In IDE mode evcxr reads one input per line, with U+2028 standing for the
+// newlines inside it, and ends the output of each with one of these markers.
+const LINE_SEPARATOR: &str = "\u{2028}";
+const SUCCESS_MARKER: char = '\u{91}';
+const FAILURE_MARKER: char = '\u{92}';
+const PROMPT: &str = ">> ";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KernelConfig {
+    /// evcxr binary to run.
+    pub program: String,
+    pub max_running: usize,
+    pub max_per_owner: usize,
+    /// Seconds without a run before the kernel is stopped and its state lost.
+    pub idle_timeout_secs: u64,
+    /// Wall time one block may take, compilation included, before it is
+    /// interrupted.
+    pub eval_timeout_secs: u64,
+    /// Address space each process of the kernel may use.
+    pub memory_bytes: u64,
+    /// CPU time each process of the kernel may use over its lifetime.
+    pub cpu_seconds: u64,
+}
+
+impl KernelConfig {
+    pub fn from_env() -> Self {
+        Self {
+            program: get_var_from_env("KERNEL_BIN").unwrap_or_else(|_| "evcxr".to_string()),
+            max_running: get_parsed_var_from_env("KERNEL_MAX_RUNNING", 8),
+            max_per_owner: get_parsed_var_from_env::<usize>("KERNEL_MAX_PER_USER", 2).max(1),
+            idle_timeout_secs: get_parsed_var_from_env::<u64>("KERNEL_IDLE_SECONDS", 900).max(1),
+            eval_timeout_secs: get_parsed_var_from_env::<u64>("KERNEL_EVAL_TIMEOUT_SECONDS", 60)
+                .max(1),
+            memory_bytes: get_parsed_var_from_env::<u64>("KERNEL_MEMORY_MB", 2048) * 1024 * 1024,
+            cpu_seconds: get_parsed_var_from_env("KERNEL_CPU_SECONDS", 1800),
+        }
+    }
+}
+
+/// Kernels are per notebook, and each owner gets their own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KernelKey {
+    pub owner: WorkspaceOwner,
+    pub notebook_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KernelView {
+    pub notebook_id: Option<Uuid>,
+    pub started_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+    /// Blocks run since the kernel started; 1 after a run means the block
+    /// saw none of the earlier state.
+    pub executions: u64,
+    pub busy: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KernelMetrics {
+    pub running: usize,
+    pub max_running: usize,
+    pub started: u64,
+    pub rejected: u64,
+    pub restarts: u64,
+    pub interrupts: u64,
+    pub idle_shutdowns: u64,
+    pub crashed: u64,
+}
+
+pub struct KernelRun {
+    pub stdout: String,
+    pub stderr: String,
+    pub termination: Termination,
+    pub stats: ExecutionStats,
+}
+
+enum KernelOutput {
+    Stdout(String),
+    Stderr(String),
+    Done { success: bool },
+}
+
+struct KernelSession {
+    child: Child,
+    stdin: ChildStdin,
+    output: mpsc::UnboundedReceiver<KernelOutput>,
+}
+
+/// A sandboxed evcxr process keeping the variables, functions and imports of
+/// the blocks run in it. Blocks run one at a time, in the order they arrive.
+pub struct Kernel {
+    notebook_id: Option<Uuid>,
+    pid: Option<u32>,
+    started_at: DateTime<Utc>,
+    last_used: Mutex<(Instant, DateTime<Utc>)>,
+    executions: AtomicU64,
+    busy: AtomicBool,
+    alive: AtomicBool,
+    eval_timeout: Duration,
+    cpu_seconds: u64,
+    max_output_bytes: usize,
+    session: AsyncMutex<KernelSession>,
+    _lease: WorkspaceLease,
+}
+
+impl Kernel {
+    pub fn view(&self) -> KernelView {
+        KernelView {
+            notebook_id: self.notebook_id,
+            started_at: self.started_at,
+            last_used_at: self.last_used.lock().unwrap().1,
+            executions: self.executions.load(Ordering::Relaxed),
+            busy: self.busy.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::Relaxed)
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last_used.lock().unwrap().0.elapsed()
+    }
+
+    fn touch(&self) {
+        *self.last_used.lock().unwrap() = (Instant::now(), Utc::now());
+    }
+
+    /// Stops whatever the kernel is compiling or running. evcxr survives and
+    /// keeps the functions, types and imports defined so far, but restarts
+    /// its child process, so variables are lost.
+    pub fn interrupt(&self) -> bool {
+        self.pid.is_some_and(sys::kill_descendants)
+    }
+
+    /// Runs one block, streaming its output to `ctx` as it arrives.
+    pub async fn evaluate(&self, code: &str, ctx: &ExecutionContext) -> KernelRun {
+        let mut session = tokio::select! {
+            session = self.session.lock() => session,
+            _ = ctx.cancel.cancelled() => {
+                return KernelRun::failure(String::new(), Termination::Cancelled);
+            }
+        };
+        if !self.is_alive() {
+            return KernelRun::failure(
+                "Erro: O kernel foi encerrado; execute o bloco novamente.".into(),
+                Termination::SandboxFailure {
+                    detail: "kernel encerrado".into(),
+                },
+            );
+        }
+
+        self.busy.store(true, Ordering::Relaxed);
+        self.touch();
+        let run = self.evaluate_in(&mut session, code, ctx).await;
+        self.executions.fetch_add(1, Ordering::Relaxed);
+        self.busy.store(false, Ordering::Relaxed);
+        self.touch();
+        run
+    }
+
+    async fn evaluate_in(
+        &self,
+        session: &mut KernelSession,
+        code: &str,
+        ctx: &ExecutionContext,
+    ) -> KernelRun {
+        // Output left over from an earlier input belongs to nobody now.
+        while session.output.try_recv().is_ok() {}
+
+        let started = Instant::now();
+        let input = format!("{}\n", to_kernel_input(code));
+        if let Err(e) = session.stdin.write_all(input.as_bytes()).await {
+            self.alive.store(false, Ordering::Relaxed);
+            return KernelRun::failure(
+                format!("Erro ao enviar o bloco ao kernel: {}", e),
+                Termination::SandboxFailure {
+                    detail: e.to_string(),
+                },
+            );
+        }
+
+        let mut collected = CollectedOutput::new(self.max_output_bytes);
+        let mut deadline = tokio::time::Instant::now() + self.eval_timeout;
+        let mut stopped_by: Option<Termination> = None;
+
+        let termination = loop {
+            tokio::select! {
+                output = session.output.recv() => match output {
+                    Some(KernelOutput::Stdout(text)) => collected.push(OutputStream::Stdout, text, ctx),
+                    Some(KernelOutput::Stderr(text)) => collected.push(OutputStream::Stderr, text, ctx),
+                    Some(KernelOutput::Done { success }) => {
+                        break stopped_by.take().unwrap_or(Termination::Exited {
+                            code: if success { 0 } else { 1 },
+                        });
+                    }
+                    None => {
+                        self.alive.store(false, Ordering::Relaxed);
+                        let cpu_time = match self.pid {
+                            Some(pid) => wait_for_cpu_time(pid).await,
+                            None => None,
+                        };
+                        let cpu_limit_reached =
+                            cpu_time.is_some_and(|t| t.as_secs() >= self.cpu_seconds);
+                        break match session.child.wait().await {
+                            Ok(status) => {
+                                Termination::from_status(&status, &collected.stderr, cpu_limit_reached)
+                            }
+                            Err(e) => Termination::SandboxFailure { detail: e.to_string() },
+                        };
+                    }
+                },
+                _ = tokio::time::sleep_until(deadline) => {
+                    if stopped_by.is_some() {
+                        eprintln!("AVISO: Kernel não respondeu à interrupção; encerrando.");
+                        self.alive.store(false, Ordering::Relaxed);
+                        let _ = session.child.start_kill();
+                        break stopped_by.take().unwrap_or(Termination::Timeout);
+                    }
+                    self.interrupt();
+                    stopped_by = Some(Termination::Timeout);
+                    deadline = tokio::time::Instant::now() + INTERRUPT_GRACE;
+                },
+                _ = ctx.cancel.cancelled(), if stopped_by.is_none() => {
+                    self.interrupt();
+                    stopped_by = Some(Termination::Cancelled);
+                    deadline = tokio::time::Instant::now() + INTERRUPT_GRACE;
+                },
+            }
+        };
+
+        while let Ok(Some(output)) = tokio::time::timeout(STDERR_GRACE, session.output.recv()).await
+        {
+            if let KernelOutput::Stderr(text) = output {
+                collected.push(OutputStream::Stderr, text, ctx);
+            }
+        }
+
+        // evcxr reports both as a finished input: a panic keeps the
+        // variables, running out of memory loses them with the child process.
+        let termination = match termination {
+            Termination::Exited { code }
+                if code != 0 && collected.stderr.contains("memory allocation of") =>
+            {
+                Termination::MemoryLimit
+            }
+            Termination::Exited { .. } if collected.stderr.contains("panicked at") => {
+                Termination::Exited {
+                    code: PANIC_EXIT_CODE,
+                }
+            }
+            other => other,
+        };
+
+        let mut stderr = collected.stderr;
+        if let Some(msg) = termination.describe() {
+            if !stderr.is_empty() && !stderr.ends_with('\n') {
+                stderr.push('\n');
+            }
+            stderr.push_str(&msg);
+        }
+
+        let mut stats = ExecutionStats::from_termination(&termination);
+        stats.wall_time_ms = Some(started.elapsed().as_millis() as u64);
+        stats.stdout_truncated = collected.stdout_truncated;
+        stats.stderr_truncated = collected.stderr_truncated;
+
+        KernelRun {
+            stdout: collected.stdout,
+            stderr,
+            termination,
+            stats,
+        }
+    }
+}
+
+impl KernelRun {
+    fn failure(stderr: String, termination: Termination) -> Self {
+        let stderr = match termination.describe() {
+            Some(msg) if stderr.is_empty() => msg,
+            _ => stderr,
+        };
+        Self {
+            stdout: String::new(),
+            stderr,
+            stats: ExecutionStats::from_termination(&termination),
+            termination,
+        }
+    }
+}
+
+struct CollectedOutput {
+    stdout: String,
+    stderr: String,
+    stdout_truncated: bool,
+    stderr_truncated: bool,
+    max_bytes: usize,
+}
+
+impl CollectedOutput {
+    fn new(max_bytes: usize) -> Self {
+        Self {
+            stdout: String::new(),
+            stderr: String::new(),
+            stdout_truncated: false,
+            stderr_truncated: false,
+            max_bytes,
+        }
+    }
+
+    fn push(&mut self, stream: OutputStream, text: String, ctx: &ExecutionContext) {
+        let (buffer, truncated) = match stream {
+            OutputStream::Stdout => (&mut self.stdout, &mut self.stdout_truncated),
+            OutputStream::Stderr => (&mut self.stderr, &mut self.stderr_truncated),
+        };
+        if *truncated {
+            return;
+        }
+
+        let room = self.max_bytes.saturating_sub(buffer.len());
+        let text = if text.len() > room {
+            *truncated = true;
+            let mut end = room;
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            text[..end].to_string()
+        } else {
+            text
+        };
+        if text.is_empty() {
+            return;
+        }
+        buffer.push_str(&text);
+        ctx.emit(ExecutionEvent::output(stream, text));
+    }
+}
+
+fn to_kernel_input(code: &str) -> String {
+    code.replace("\r\n", "\n")
+        .trim_end()
+        .replace('\n', LINE_SEPARATOR)
+}
+
+#[derive(Default)]
+struct KernelState {
+    kernels: HashMap<KernelKey, Arc<Kernel>>,
+    started: u64,
+    rejected: u64,
+    restarts: u64,
+    interrupts: u64,
+    idle_shutdowns: u64,
+    crashed: u64,
+}
+
+/// Keeps one kernel per notebook and owner, capped overall and per owner.
+/// Like analyzers, kernels are long-lived, so there is no queue: starting one
+/// over the cap is turned away. Kernels unused for the idle timeout are
+/// stopped by `run`.
+pub struct KernelManager {
+    config: KernelConfig,
+    state: Mutex<KernelState>,
+    /// Starts are serialized so two runs of a notebook never start two
+    /// kernels, and the caps hold while a start is in progress.
+    starting: AsyncMutex<()>,
+}
+
+impl KernelManager {
+    pub fn new(config: KernelConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(KernelState::default()),
+            starting: AsyncMutex::new(()),
+        }
+    }
+
+    pub fn config(&self) -> &KernelConfig {
+        &self.config
+    }
+
+    pub fn get(&self, key: KernelKey) -> Option<Arc<Kernel>> {
+        self.state
+            .lock()
+            .unwrap()
+            .kernels
+            .get(&key)
+            .filter(|kernel| kernel.is_alive())
+            .cloned()
+    }
+
+    /// The notebook's kernel, started in `workspace` if it has none.
+    pub async fn acquire(
+        &self,
+        key: KernelKey,
+        workspace: &Path,
+        lease: WorkspaceLease,
+    ) -> Result<Arc<Kernel>, ApiError> {
+        let _starting = self.starting.lock().await;
+        if let Some(kernel) = self.get(key) {
+            return Ok(kernel);
+        }
+        self.start(key, workspace, lease).await
+    }
+
+    /// Replaces the notebook's kernel with a new one, dropping all its state.
+    pub async fn restart(
+        &self,
+        key: KernelKey,
+        workspace: &Path,
+        lease: WorkspaceLease,
+    ) -> Result<Arc<Kernel>, ApiError> {
+        let _starting = self.starting.lock().await;
+        if self.remove(key).is_some() {
+            self.state.lock().unwrap().restarts += 1;
+        }
+        self.start(key, workspace, lease).await
+    }
+
+    /// Interrupts the block the notebook's kernel is running, if any.
+    pub fn interrupt(&self, key: KernelKey) -> Option<Arc<Kernel>> {
+        let kernel = self.get(key)?;
+        if kernel.interrupt() {
+            self.state.lock().unwrap().interrupts += 1;
+        }
+        Some(kernel)
+    }
+
+    pub fn shutdown(&self, key: KernelKey) -> Option<Arc<Kernel>> {
+        self.remove(key)
+    }
+
+    fn remove(&self, key: KernelKey) -> Option<Arc<Kernel>> {
+        let kernel = self.state.lock().unwrap().kernels.remove(&key)?;
+        kernel.alive.store(false, Ordering::Relaxed);
+        Some(kernel)
+    }
+
+    async fn start(
+        &self,
+        key: KernelKey,
+        workspace: &Path,
+        lease: WorkspaceLease,
+    ) -> Result<Arc<Kernel>, ApiError> {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.kernels.retain(|_, kernel| kernel.is_alive());
+            let owner_running = state
+                .kernels
+                .keys()
+                .filter(|k| k.owner == key.owner)
+                .count();
+            if state.kernels.len() >= self.config.max_running
+                || owner_running >= self.config.max_per_owner
+            {
+                state.rejected += 1;
+                return Err(ApiError::KernelsBusy {
+                    retry_after_secs: BUSY_RETRY_SECS,
+                });
+            }
+        }
+
+        let kernel = match self.spawn(key, workspace, lease).await {
+            Ok(kernel) => Arc::new(kernel),
+            Err(e) => {
+                eprintln!("ERRO: Falha ao iniciar kernel: {}", e);
+                return Err(ApiError::KernelUnavailable);
+            }
+        };
+
+        println!(
+            "LOG: Kernel iniciado para {} (PID: {:?})",
+            key.owner.key(),
+            kernel.pid
+        );
+        let mut state = self.state.lock().unwrap();
+        state.kernels.insert(key, kernel.clone());
+        state.started += 1;
+        Ok(kernel)
+    }
+
+    /// Starts evcxr on `workspace` inside the sandbox and waits until it
+    /// answers, so a kernel that cannot start is reported right away.
+    async fn spawn(
+        &self,
+        key: KernelKey,
+        workspace: &Path,
+        lease: WorkspaceLease,
+    ) -> std::io::Result<Kernel> {
+        let base = load_sandbox_config();
+        let sysroot = default_sysroot().await;
+        let sandbox = SandboxConfig {
+            memory_bytes: self.config.memory_bytes,
+            cpu_seconds: self.config.cpu_seconds,
+            max_processes: KERNEL_MAX_PROCESSES,
+            max_open_files: KERNEL_MAX_OPEN_FILES,
+            max_file_size_bytes: KERNEL_MAX_FILE_SIZE_BYTES,
+            allow_socketpair: true,
+            ..base.clone()
+        }
+        .exposing(sysroot);
+
+        let mut path = String::new();
+        if let Some(sysroot) = sysroot {
+            path.push_str(&format!("{}:", sysroot.join("bin").display()));
+        }
+        path.push_str("/usr/local/bin:/usr/bin:/bin");
+
+        let mut command = Command::new(&self.config.program);
+        command
+            // Blocks build without optimizations, like a default debug build.
+            .args(["--disable-readline", "--ide-mode", "--opt", "0"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        apply_sandbox(&mut command, &sandbox, workspace)?;
+        command
+            .env("PATH", path)
+            .env("CARGO_NET_OFFLINE", "true")
+            .env("EVCXR_TMPDIR", workspace.join("evcxr"));
+
+        let mut child = command.spawn()?;
+        let pid = child.id();
+        let stdin = child.stdin.take().ok_or(std::io::ErrorKind::BrokenPipe)?;
+        let (tx, output) = mpsc::unbounded_channel();
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(forward_output(stdout, OutputFilter::stdout(), tx.clone()));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(forward_output(stderr, OutputFilter::stderr(), tx));
+        }
+
+        let mut session = KernelSession {
+            child,
+            stdin,
+            output,
+        };
+        // Also a handshake: evcxr answers once it is ready for input.
+        session
+            .stdin
+            .write_all(b":preserve_vars_on_panic 1\n")
+            .await?;
+        let ready = tokio::time::timeout(STARTUP_TIMEOUT, async {
+            while let Some(output) = session.output.recv().await {
+                if let KernelOutput::Done { success } = output {
+                    return success;
+                }
+            }
+            false
+        })
+        .await;
+        if ready != Ok(true) {
+            return Err(std::io::Error::other("o kernel não respondeu ao iniciar"));
+        }
+
+        let now = Utc::now();
+        Ok(Kernel {
+            notebook_id: key.notebook_id,
+            pid,
+            started_at: now,
+            last_used: Mutex::new((Instant::now(), now)),
+            executions: AtomicU64::new(0),
+            busy: AtomicBool::new(false),
+            alive: AtomicBool::new(true),
+            eval_timeout: Duration::from_secs(self.config.eval_timeout_secs),
+            cpu_seconds: sandbox.cpu_seconds,
+            max_output_bytes: base.max_output_bytes,
+            session: AsyncMutex::new(session),
+            _lease: lease,
+        })
+    }
+
+    /// Drops kernels that exited on their own or sat idle past the timeout;
+    /// dropping one kills its process.
+    fn sweep(&self) {
+        let idle_timeout = Duration::from_secs(self.config.idle_timeout_secs);
+        let mut state = self.state.lock().unwrap();
+        let mut idle = 0;
+        let mut crashed = 0;
+        state.kernels.retain(|key, kernel| {
+            if !kernel.is_alive() {
+                crashed += 1;
+                return false;
+            }
+            if !kernel.busy.load(Ordering::Relaxed) && kernel.idle_for() >= idle_timeout {
+                println!(
+                    "LOG: Kernel de {} encerrado por inatividade",
+                    key.owner.key()
+                );
+                kernel.alive.store(false, Ordering::Relaxed);
+                idle += 1;
+                return false;
+            }
+            true
+        });
+        state.idle_shutdowns += idle;
+        state.crashed += crashed;
+    }
+
+    pub async fn run(self: Arc<Self>) {
+        let interval = (Duration::from_secs(self.config.idle_timeout_secs) / 4)
+            .clamp(MIN_SWEEP_INTERVAL, MAX_SWEEP_INTERVAL);
+        loop {
+            tokio::time::sleep(interval).await;
+            self.sweep();
+        }
+    }
+
+    pub fn metrics(&self) -> KernelMetrics {
+        let state = self.state.lock().unwrap();
+        KernelMetrics {
+            running: state.kernels.len(),
+            max_running: self.config.max_running,
+            started: state.started,
+            rejected: state.rejected,
+            restarts: state.restarts,
+            interrupts: state.interrupts,
+            idle_shutdowns: state.idle_shutdowns,
+            crashed: state.crashed,
+        }
+    }
+}
+
+async fn forward_output(
+    mut stream: impl AsyncRead + Unpin,
+    mut filter: OutputFilter,
+    tx: mpsc::UnboundedSender<KernelOutput>,
+) {
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => n,
+        };
+        for output in filter.feed(&buf[..n]) {
+            if tx.send(output).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FilterMode {
+    /// Before the first prompt, which follows evcxr's greeting.
+    Banner,
+    /// Right after a marker, where the next prompt is expected.
+    Prompt,
+    Text,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum EscapeState {
+    None,
+    Escape,
+    Csi,
+}
+
+/// Turns evcxr's raw output into plain text: colour codes and prompts are
+/// dropped, and on stdout the end-of-input markers are picked out. Input may
+/// be split anywhere, even inside a character or an escape sequence.
+struct OutputFilter {
+    is_stdout: bool,
+    pending: Vec<u8>,
+    escape: EscapeState,
+    mode: FilterMode,
+    prompt_matched: usize,
+}
+
+impl OutputFilter {
+    fn stdout() -> Self {
+        Self {
+            is_stdout: true,
+            pending: vec![],
+            escape: EscapeState::None,
+            mode: FilterMode::Banner,
+            prompt_matched: 0,
+        }
+    }
+
+    fn stderr() -> Self {
+        Self {
+            is_stdout: false,
+            mode: FilterMode::Text,
+            ..Self::stdout()
+        }
+    }
+
+    fn feed(&mut self, bytes: &[u8]) -> Vec<KernelOutput> {
+        self.pending.extend_from_slice(bytes);
+        let mut decoded = String::new();
+        let mut consumed = 0;
+        loop {
+            match std::str::from_utf8(&self.pending[consumed..]) {
+                Ok(valid) => {
+                    decoded.push_str(valid);
+                    consumed = self.pending.len();
+                    break;
+                }
+                Err(e) => {
+                    let valid_up_to = consumed + e.valid_up_to();
+                    decoded.push_str(
+                        std::str::from_utf8(&self.pending[consumed..valid_up_to]).unwrap_or(""),
+                    );
+                    match e.error_len() {
+                        // An incomplete character waits for the next read.
+                        None => {
+                            consumed = valid_up_to;
+                            break;
+                        }
+                        Some(len) => {
+                            decoded.push(char::REPLACEMENT_CHARACTER);
+                            consumed = valid_up_to + len;
+                        }
+                    }
+                }
+            }
+        }
+        self.pending.drain(..consumed);
+
+        let mut outputs = vec![];
+        let mut text = String::new();
+        for c in decoded.chars() {
+            match self.escape {
+                EscapeState::Escape => {
+                    self.escape = if c == '[' {
+                        EscapeState::Csi
+                    } else {
+                        EscapeState::None
+                    };
+                    continue;
+                }
+                EscapeState::Csi => {
+                    if ('@'..='~').contains(&c) {
+                        self.escape = EscapeState::None;
+                    }
+                    continue;
+                }
+                EscapeState::None if c == '\u{1b}' => {
+                    self.escape = EscapeState::Escape;
+                    continue;
+                }
+                EscapeState::None => {}
+            }
+
+            if self.is_stdout && (c == SUCCESS_MARKER || c == FAILURE_MARKER) {
+                self.flush_prompt(&mut text);
+                if !text.is_empty() {
+                    outputs.push(KernelOutput::Stdout(std::mem::take(&mut text)));
+                }
+                outputs.push(KernelOutput::Done {
+                    success: c == SUCCESS_MARKER,
+                });
+                self.mode = FilterMode::Prompt;
+                continue;
+            }
+
+            match self.mode {
+                FilterMode::Text => text.push(c),
+                FilterMode::Banner | FilterMode::Prompt => {
+                    if PROMPT[self.prompt_matched..].starts_with(c) {
+                        self.prompt_matched += c.len_utf8();
+                        if self.prompt_matched == PROMPT.len() {
+                            self.prompt_matched = 0;
+                            self.mode = FilterMode::Text;
+                        }
+                    } else if self.mode == FilterMode::Banner {
+                        self.prompt_matched = usize::from(PROMPT.starts_with(c));
+                    } else {
+                        self.flush_prompt(&mut text);
+                        self.mode = FilterMode::Text;
+                        text.push(c);
+                    }
+                }
+            }
+        }
+
+        if !text.is_empty() {
+            outputs.push(if self.is_stdout {
+                KernelOutput::Stdout(text)
+            } else {
+                KernelOutput::Stderr(text)
+            });
+        }
+        outputs
+    }
+
+    /// Gives back the start of a prompt that turned out to be output.
+    fn flush_prompt(&mut self, text: &mut String) {
+        if self.mode == FilterMode::Prompt {
+            text.push_str(&PROMPT[..self.prompt_matched]);
+        }
+        self.prompt_matched = 0;
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::collections::HashMap;
+
+    /// Kills every process below the program started in the sandbox, leaving
+    /// the program itself running. Returns whether there was any.
+    pub fn kill_descendants(root_pid: u32) -> bool {
+        let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+        let Ok(entries) = std::fs::read_dir("/proc") else {
+            return false;
+        };
+        for entry in entries.flatten() {
+            let Some(pid) = entry
+                .file_name()
+                .to_str()
+                .and_then(|n| n.parse::<u32>().ok())
+            else {
+                continue;
+            };
+            if let Some(ppid) = parent_pid(pid) {
+                children.entry(ppid).or_default().push(pid);
+            }
+        }
+
+        // With isolation the spawned process is the sandbox's reaper, a copy
+        // of the server, and the program is its only child.
+        let reaper_exe = std::fs::read_link(format!("/proc/{}/exe", root_pid)).ok();
+        let program = if reaper_exe.is_some() && reaper_exe == std::env::current_exe().ok() {
+            match children.get(&root_pid).and_then(|c| c.first()) {
+                Some(pid) => *pid,
+                None => return false,
+            }
+        } else {
+            root_pid
+        };
+
+        let mut pending = children.get(&program).cloned().unwrap_or_default();
+        let mut killed = false;
+        while let Some(pid) = pending.pop() {
+            if let Some(grandchildren) = children.get(&pid) {
+                pending.extend(grandchildren);
+            }
+            killed |= unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) } == 0;
+        }
+        killed
+    }
+
+    fn parent_pid(pid: u32) -> Option<u32> {
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+        // Fields after the parenthesised command name: state, then ppid.
+        stat.rsplit_once(')')?
+            .1
+            .split_whitespace()
+            .nth(1)?
+            .parse()
+            .ok()
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    pub fn kill_descendants(_root_pid: u32) -> bool {
+        false
+    }
+}
@@ -0,0 +1,323 @@
+use chrono::{NaiveDate, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::controllers::utils::get_parsed_var_from_env;
+use crate::http::identity::{RequestIdentity, WorkspaceOwner};
+use crate::models::error::ApiError;
+
+const PRUNE_EVERY: u64 = 1024;
+const IDLE_ENTRY: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TierLimits {
+    /// Requests that can be made back to back before the rate applies.
+    pub burst: u32,
+    /// Sustained requests per minute; 0 disables the rate limit.
+    pub per_minute: u32,
+    /// Build and run time allowed per UTC day; 0 disables the quota.
+    pub daily_build_minutes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LimiterConfig {
+    pub user: TierLimits,
+    pub anonymous: TierLimits,
+    /// Policy violations within `ban_window_secs` that trigger a ban; 0
+    /// disables bans.
+    pub ban_threshold: usize,
+    pub ban_window_secs: u64,
+    pub ban_duration_secs: u64,
+    /// Build time set aside for each admitted execution until it finishes, so
+    /// parallel runs cannot overdraw the daily quota.
+    pub build_reserve_secs: u64,
+}
+
+impl LimiterConfig {
+    pub fn from_env() -> Self {
+        Self {
+            user: TierLimits {
+                burst: get_parsed_var_from_env::<u32>("RATE_USER_BURST", 10).max(1),
+                per_minute: get_parsed_var_from_env("RATE_USER_PER_MINUTE", 30),
+                daily_build_minutes: get_parsed_var_from_env("QUOTA_USER_BUILD_MINUTES", 120),
+            },
+            anonymous: TierLimits {
+                burst: get_parsed_var_from_env::<u32>("RATE_ANON_BURST", 5).max(1),
+                per_minute: get_parsed_var_from_env("RATE_ANON_PER_MINUTE", 10),
+                daily_build_minutes: get_parsed_var_from_env("QUOTA_ANON_BUILD_MINUTES", 15),
+            },
+            ban_threshold: get_parsed_var_from_env("BAN_VIOLATION_THRESHOLD", 5),
+            ban_window_secs: get_parsed_var_from_env("BAN_WINDOW_SECONDS", 600),
+            ban_duration_secs: get_parsed_var_from_env("BAN_DURATION_SECONDS", 1800),
+            build_reserve_secs: get_parsed_var_from_env("QUOTA_BUILD_RESERVE_SECONDS", 60),
+        }
+    }
+}
+
+/// Who a limit applies to. Anonymous sessions cost nothing to replace, so
+/// anonymous clients are counted by IP address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum LimitKey {
+    User(Uuid),
+    Client(IpAddr),
+}
+
+impl LimitKey {
+    fn of(identity: &RequestIdentity) -> Self {
+        match identity.owner {
+            WorkspaceOwner::User(id) => LimitKey::User(id),
+            WorkspaceOwner::Anonymous(_) => LimitKey::Client(identity.client_ip),
+        }
+    }
+}
+
+struct ClientState {
+    tokens: f64,
+    refilled_at: Instant,
+    build_day: NaiveDate,
+    build_used: Duration,
+    build_reserved: Duration,
+    violations: VecDeque<Instant>,
+    banned_until: Option<Instant>,
+    last_seen: Instant,
+}
+
+impl ClientState {
+    fn new(limits: &TierLimits) -> Self {
+        let now = Instant::now();
+        Self {
+            tokens: limits.burst as f64,
+            refilled_at: now,
+            build_day: Utc::now().date_naive(),
+            build_used: Duration::ZERO,
+            build_reserved: Duration::ZERO,
+            violations: VecDeque::new(),
+            banned_until: None,
+            last_seen: now,
+        }
+    }
+
+    fn refill(&mut self, limits: &TierLimits, now: Instant) {
+        let per_second = limits.per_minute as f64 / 60.0;
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * per_second).min(limits.burst as f64);
+        self.refilled_at = now;
+    }
+
+    fn build_used_today(&mut self) -> Duration {
+        let today = Utc::now().date_naive();
+        if self.build_day != today {
+            self.build_day = today;
+            self.build_used = Duration::ZERO;
+        }
+        self.build_used
+    }
+}
+
+#[derive(Default)]
+struct LimiterState {
+    clients: HashMap<LimitKey, ClientState>,
+    checks: u64,
+    rejected: u64,
+    bans: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LimiterMetrics {
+    pub tracked_clients: usize,
+    pub banned_clients: usize,
+    pub rejected: u64,
+    pub bans: u64,
+}
+
+/// Build time reserved by `admit`, released when dropped unless settled by
+/// `charge_build_time`.
+pub struct BuildReservation<'a> {
+    limiter: &'a RateLimiter,
+    key: LimitKey,
+    estimate: Duration,
+}
+
+impl Drop for BuildReservation<'_> {
+    fn drop(&mut self) {
+        let mut state = self.limiter.state.lock().unwrap();
+        if let Some(client) = state.clients.get_mut(&self.key) {
+            client.build_reserved = client.build_reserved.saturating_sub(self.estimate);
+        }
+    }
+}
+
+/// Per-client throttling of the execution endpoints: a token bucket on
+/// requests, a daily budget of build time and temporary bans for clients that
+/// keep submitting code the policy rejects.
+pub struct RateLimiter {
+    config: LimiterConfig,
+    state: Mutex<LimiterState>,
+}
+
+impl RateLimiter {
+    pub fn new(config: LimiterConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(LimiterState::default()),
+        }
+    }
+
+    fn limits(&self, key: &LimitKey) -> &TierLimits {
+        match key {
+            LimitKey::User(_) => &self.config.user,
+            LimitKey::Client(_) => &self.config.anonymous,
+        }
+    }
+
+    /// Takes a token for a new execution and reserves build time for it, or
+    /// says when the client may retry.
+    pub fn admit(&self, identity: &RequestIdentity) -> Result<BuildReservation<'_>, ApiError> {
+        let key = LimitKey::of(identity);
+        let limits = self.limits(&key);
+        let now = Instant::now();
+
+        let mut state = self.state.lock().unwrap();
+        state.checks += 1;
+        if state.checks.is_multiple_of(PRUNE_EVERY) {
+            state.clients.retain(|_, client| {
+                now.duration_since(client.last_seen) < IDLE_ENTRY
+                    || client.banned_until.is_some_and(|until| until > now)
+            });
+        }
+
+        let client = state
+            .clients
+            .entry(key)
+            .or_insert_with(|| ClientState::new(limits));
+        client.last_seen = now;
+
+        let rejection = if let Some(until) = client.banned_until.filter(|until| *until > now) {
+            Some(ApiError::TemporarilyBanned {
+                retry_after_secs: until.duration_since(now).as_secs().max(1),
+            })
+        } else if limits.daily_build_minutes > 0
+            && client.build_used_today() + client.build_reserved
+                >= Duration::from_secs(limits.daily_build_minutes * 60)
+        {
+            Some(ApiError::BuildQuotaExceeded {
+                limit_minutes: limits.daily_build_minutes,
+                retry_after_secs: secs_until_utc_midnight(),
+            })
+        } else if limits.per_minute > 0 {
+            client.refill(limits, now);
+            if client.tokens >= 1.0 {
+                client.tokens -= 1.0;
+                None
+            } else {
+                let per_second = limits.per_minute as f64 / 60.0;
+                Some(ApiError::RateLimited {
+                    limit: limits.per_minute,
+                    retry_after_secs: ((1.0 - client.tokens) / per_second).ceil().max(1.0) as u64,
+                })
+            }
+        } else {
+            None
+        };
+
+        match rejection {
+            Some(error) => {
+                state.rejected += 1;
+                eprintln!("LOG: Execução recusada para {:?}: {}", key, error);
+                Err(error)
+            }
+            None => {
+                let estimate = Duration::from_secs(self.config.build_reserve_secs);
+                client.build_reserved += estimate;
+                Ok(BuildReservation {
+                    limiter: self,
+                    key,
+                    estimate,
+                })
+            }
+        }
+    }
+
+    /// Counts time spent holding an execution slot against the daily quota,
+    /// in place of the time reserved for it.
+    pub fn charge_build_time(&self, reservation: BuildReservation<'_>, elapsed: Duration) {
+        let key = reservation.key;
+        let limits = self.limits(&key);
+        // Releases the reserved time.
+        drop(reservation);
+
+        let mut state = self.state.lock().unwrap();
+        let client = state
+            .clients
+            .entry(key)
+            .or_insert_with(|| ClientState::new(limits));
+        client.build_used_today();
+        client.build_used += elapsed;
+    }
+
+    /// Records code rejected by the policy, banning the client once it has
+    /// done so `ban_threshold` times within the window.
+    pub fn record_violation(&self, identity: &RequestIdentity) {
+        if self.config.ban_threshold == 0 {
+            return;
+        }
+
+        let key = LimitKey::of(identity);
+        let limits = self.limits(&key);
+        let now = Instant::now();
+        let window = Duration::from_secs(self.config.ban_window_secs);
+
+        let mut state = self.state.lock().unwrap();
+        let client = state
+            .clients
+            .entry(key)
+            .or_insert_with(|| ClientState::new(limits));
+
+        while client
+            .violations
+            .front()
+            .is_some_and(|at| now.duration_since(*at) > window)
+        {
+            client.violations.pop_front();
+        }
+        client.violations.push_back(now);
+
+        if client.violations.len() >= self.config.ban_threshold {
+            client.violations.clear();
+            client.banned_until = Some(now + Duration::from_secs(self.config.ban_duration_secs));
+            state.bans += 1;
+            eprintln!(
+                "LOG: {:?} bloqueado por {} s após violações repetidas da política",
+                key, self.config.ban_duration_secs
+            );
+        }
+    }
+
+    pub fn metrics(&self) -> LimiterMetrics {
+        let state = self.state.lock().unwrap();
+        let now = Instant::now();
+        LimiterMetrics {
+            tracked_clients: state.clients.len(),
+            banned_clients: state
+                .clients
+                .values()
+                .filter(|c| c.banned_until.is_some_and(|until| until > now))
+                .count(),
+            rejected: state.rejected,
+            bans: state.bans,
+        }
+    }
+}
+
+fn secs_until_utc_midnight() -> u64 {
+    let now = Utc::now();
+    let midnight = (now.date_naive() + chrono::Days::new(1))
+        .and_hms_opt(0, 0, 0)
+        .expect("midnight is a valid time")
+        .and_utc();
+    (midnight - now).num_seconds().max(1) as u64
+}
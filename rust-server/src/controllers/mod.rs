@@ -1,7 +1,13 @@
+pub mod admin;
+pub mod analyzer;
 pub mod email;
+pub mod jobs;
 pub mod jwt;
+pub mod kernel;
+pub mod limiter;
 pub mod notebook;
 pub mod oauth;
+pub mod scheduler;
 pub mod sync;
 pub mod team;
 pub mod team_invitation;
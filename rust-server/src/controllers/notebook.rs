@@ -13,6 +13,9 @@ use crate::{
     models::{
         self,
         error::ApiError,
+        execution_result::{
+            ExecutionResultResponse, ResultHistoryQuery, code_hash, find_results_by_block,
+        },
         notebook::{
             NewBlock, NewNotebook, Notebook, NotebookResponse, PublicNotebookResponse, SearchQuery,
             SearchResult, SyncNotebookRequest, UpdateNotebookTitle, UpdateNotebookVisibility,
@@ -234,6 +237,49 @@ pub async fn api_get_single_notebook_with_blocks(
     }
 }
 
+pub async fn api_get_block_results(
+    State(state): State<Arc<AppState>>,
+    Path((notebook_id, block_id)): Path<(Uuid, Uuid)>,
+    Query(params): Query<ResultHistoryQuery>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, Json<Vec<ExecutionResultResponse>>), ApiError> {
+    let id: Option<Uuid> = match extract_claims_from_header(&headers).await {
+        Ok(data) => Some(data.1.id),
+        Err(_) => None,
+    };
+
+    let conn = &mut get_conn(&state.pool)
+        .await
+        .map_err(|e| ApiError::DatabaseConnection(e.1.0.to_string()))?;
+
+    let notebook = models::notebook::find_notebook_by_id(conn, &notebook_id).await?;
+    if !notebook.is_public {
+        is_notebook_owner(conn, id, &notebook_id).await?;
+    }
+
+    let block = match models::notebook::find_block_by_id(conn, &notebook_id, &block_id).await {
+        Ok(Some(block)) => block,
+        Ok(None) => return Err(ApiError::Request("Bloco não encontrado".to_string())),
+        Err(e) => return Err(ApiError::Database(e)),
+    };
+
+    let limit = params.limit.unwrap_or(20).clamp(1, 100);
+    let current_hash = code_hash(&block.content);
+
+    match find_results_by_block(conn, &notebook_id, &block_id, limit).await {
+        Ok(results) => Ok((
+            StatusCode::OK,
+            Json(
+                results
+                    .into_iter()
+                    .map(|r| r.into_response(&current_hash))
+                    .collect(),
+            ),
+        )),
+        Err(e) => Err(ApiError::Database(e)),
+    }
+}
+
 pub async fn api_save_notebook_content(
     State(state): State<Arc<AppState>>,
     Path(notebook_id): Path<Uuid>,
@@ -0,0 +1,328 @@
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::{oneshot, watch};
+
+use crate::controllers::utils::get_parsed_var_from_env;
+use crate::http::identity::WorkspaceOwner;
+use crate::models::error::ApiError;
+use crate::models::execution::{ExecutionContext, ExecutionEvent};
+
+const WAIT_SAMPLES: usize = 256;
+const DEFAULT_SERVICE_MS: u64 = 5_000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SchedulerConfig {
+    pub max_concurrent: usize,
+    pub max_per_owner: usize,
+    pub max_queue: usize,
+    pub max_queued_per_owner: usize,
+}
+
+impl SchedulerConfig {
+    pub fn from_env() -> Self {
+        Self {
+            max_concurrent: get_parsed_var_from_env::<usize>("SCHED_MAX_CONCURRENT", 2).max(1),
+            max_per_owner: get_parsed_var_from_env::<usize>("SCHED_MAX_PER_USER", 1).max(1),
+            max_queue: get_parsed_var_from_env("SCHED_MAX_QUEUE", 32),
+            max_queued_per_owner: get_parsed_var_from_env("SCHED_MAX_QUEUED_PER_USER", 2),
+        }
+    }
+}
+
+struct Waiter {
+    id: u64,
+    owner: WorkspaceOwner,
+    enqueued_at: Instant,
+    grant: oneshot::Sender<()>,
+    position: watch::Sender<usize>,
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    next_id: u64,
+    running: HashMap<WorkspaceOwner, usize>,
+    running_total: usize,
+    queue: VecDeque<Waiter>,
+    completed: u64,
+    rejected: u64,
+    waits_ms: VecDeque<u64>,
+    service_ms: VecDeque<u64>,
+}
+
+impl SchedulerState {
+    fn running_for(&self, owner: &WorkspaceOwner) -> usize {
+        self.running.get(owner).copied().unwrap_or(0)
+    }
+
+    fn release(&mut self, owner: &WorkspaceOwner) {
+        self.running_total = self.running_total.saturating_sub(1);
+        if let Some(count) = self.running.get_mut(owner) {
+            *count -= 1;
+            if *count == 0 {
+                self.running.remove(owner);
+            }
+        }
+    }
+
+    /// Each waiter's place in the order `dispatch` will serve them: fewest
+    /// running first, counting the waiters served ahead as running.
+    fn queue_positions(&self) -> Vec<usize> {
+        let mut running = self.running.clone();
+        let mut positions = vec![0; self.queue.len()];
+        for position in 1..=self.queue.len() {
+            let Some((index, owner)) = self
+                .queue
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| positions[*index] == 0)
+                .min_by_key(|(index, w)| (running.get(&w.owner).copied().unwrap_or(0), *index))
+                .map(|(index, w)| (index, w.owner))
+            else {
+                break;
+            };
+            positions[index] = position;
+            *running.entry(owner).or_insert(0) += 1;
+        }
+        positions
+    }
+
+    fn record(samples: &mut VecDeque<u64>, value: u64) {
+        if samples.len() == WAIT_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(value);
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SchedulerMetrics {
+    pub queue_depth: usize,
+    pub running: usize,
+    pub max_concurrent: usize,
+    pub max_queue: usize,
+    pub completed: u64,
+    pub rejected: u64,
+    pub avg_wait_ms: u64,
+    pub p95_wait_ms: u64,
+    pub max_wait_ms: u64,
+    pub avg_service_ms: u64,
+}
+
+/// Admits builds and runs into a fixed number of slots. Waiting owners are
+/// served fewest-running first, then in arrival order, so one user cannot
+/// fill every slot while others wait.
+pub struct Scheduler {
+    config: SchedulerConfig,
+    state: Mutex<SchedulerState>,
+}
+
+pub struct SchedulerPermit {
+    scheduler: Arc<Scheduler>,
+    owner: WorkspaceOwner,
+    started_at: Instant,
+}
+
+impl Drop for SchedulerPermit {
+    fn drop(&mut self) {
+        let elapsed = self.started_at.elapsed().as_millis() as u64;
+        let mut state = self.scheduler.state.lock().unwrap();
+        state.release(&self.owner);
+        state.completed += 1;
+        SchedulerState::record(&mut state.service_ms, elapsed);
+        self.scheduler.dispatch(&mut state);
+    }
+}
+
+// Removes a waiter whose request went away before being admitted, or gives
+// back the slot if it was granted in the meantime.
+struct QueueGuard {
+    scheduler: Arc<Scheduler>,
+    id: u64,
+    owner: WorkspaceOwner,
+    grant: Option<oneshot::Receiver<()>>,
+}
+
+impl Drop for QueueGuard {
+    fn drop(&mut self) {
+        let Some(mut grant) = self.grant.take() else {
+            return;
+        };
+
+        let mut state = self.scheduler.state.lock().unwrap();
+        if let Some(index) = state.queue.iter().position(|w| w.id == self.id) {
+            state.queue.remove(index);
+        } else if grant.try_recv().is_ok() {
+            state.release(&self.owner);
+        }
+        self.scheduler.dispatch(&mut state);
+    }
+}
+
+impl Scheduler {
+    pub fn new(config: SchedulerConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(SchedulerState::default()),
+        }
+    }
+
+    pub async fn acquire(
+        self: &Arc<Self>,
+        owner: WorkspaceOwner,
+        ctx: &ExecutionContext,
+    ) -> Result<Option<SchedulerPermit>, ApiError> {
+        let (grant_rx, mut position_rx, id, enqueued_at) = {
+            let mut state = self.state.lock().unwrap();
+
+            let queued_for_owner = state.queue.iter().filter(|w| w.owner == owner).count();
+            if state.queue.len() >= self.config.max_queue
+                || queued_for_owner >= self.config.max_queued_per_owner
+            {
+                state.rejected += 1;
+                let retry_after_secs = self.retry_after(&state);
+                eprintln!(
+                    "LOG: Fila de execução cheia para {} ({} aguardando)",
+                    owner.key(),
+                    state.queue.len()
+                );
+                return Err(ApiError::QueueFull { retry_after_secs });
+            }
+
+            state.next_id += 1;
+            let id = state.next_id;
+            let enqueued_at = Instant::now();
+            let (grant_tx, grant_rx) = oneshot::channel();
+            let (position_tx, position_rx) = watch::channel(state.queue.len() + 1);
+            state.queue.push_back(Waiter {
+                id,
+                owner,
+                enqueued_at,
+                grant: grant_tx,
+                position: position_tx,
+            });
+            self.dispatch(&mut state);
+
+            (grant_rx, position_rx, id, enqueued_at)
+        };
+
+        let mut guard = QueueGuard {
+            scheduler: self.clone(),
+            id,
+            owner,
+            grant: Some(grant_rx),
+        };
+
+        let mut last_position = 0;
+        let mut position_open = true;
+        loop {
+            let position = *position_rx.borrow_and_update();
+            if position != last_position && position > 0 {
+                ctx.emit(ExecutionEvent::queue_position(position));
+                last_position = position;
+            }
+
+            let grant = guard.grant.as_mut().expect("grant taken before admission");
+            tokio::select! {
+                result = grant => {
+                    guard.grant = None;
+                    if result.is_err() {
+                        return Err(ApiError::Request("Scheduler encerrado".to_string()));
+                    }
+                    let waited = enqueued_at.elapsed().as_millis() as u64;
+                    SchedulerState::record(&mut self.state.lock().unwrap().waits_ms, waited);
+                    return Ok(Some(SchedulerPermit {
+                        scheduler: self.clone(),
+                        owner,
+                        started_at: Instant::now(),
+                    }));
+                }
+                // Closes once the waiter leaves the queue; the grant resolves next.
+                changed = position_rx.changed(), if position_open => {
+                    position_open = changed.is_ok();
+                }
+                _ = ctx.cancel.cancelled() => return Ok(None),
+            }
+        }
+    }
+
+    fn dispatch(&self, state: &mut SchedulerState) {
+        while state.running_total < self.config.max_concurrent {
+            let next = state
+                .queue
+                .iter()
+                .enumerate()
+                .filter(|(_, w)| state.running_for(&w.owner) < self.config.max_per_owner)
+                .min_by_key(|(index, w)| (state.running_for(&w.owner), *index))
+                .map(|(index, _)| index);
+
+            let Some(index) = next else {
+                break;
+            };
+            let waiter = state.queue.remove(index).expect("index from iteration");
+
+            if waiter.grant.send(()).is_ok() {
+                state.running_total += 1;
+                *state.running.entry(waiter.owner).or_insert(0) += 1;
+                println!(
+                    "LOG: Execução de {} admitida após {} ms na fila",
+                    waiter.owner.key(),
+                    waiter.enqueued_at.elapsed().as_millis()
+                );
+            }
+        }
+
+        let positions = state.queue_positions();
+        for (waiter, new_position) in state.queue.iter().zip(positions) {
+            waiter.position.send_if_modified(|position| {
+                let changed = *position != new_position;
+                *position = new_position;
+                changed
+            });
+        }
+    }
+
+    fn retry_after(&self, state: &SchedulerState) -> u64 {
+        let avg_service_ms = average(&state.service_ms).unwrap_or(DEFAULT_SERVICE_MS);
+        let ahead = (state.queue.len() + 1) as u64;
+        let estimate_ms = avg_service_ms * ahead / self.config.max_concurrent as u64;
+        (estimate_ms / 1000).clamp(1, 120)
+    }
+
+    /// Whether `owner` has a build or run holding a slot right now.
+    pub fn is_running(&self, owner: &WorkspaceOwner) -> bool {
+        self.state.lock().unwrap().running_for(owner) > 0
+    }
+
+    pub fn metrics(&self) -> SchedulerMetrics {
+        let state = self.state.lock().unwrap();
+
+        let mut waits: Vec<u64> = state.waits_ms.iter().copied().collect();
+        waits.sort_unstable();
+        let p95_wait_ms = match waits.len() {
+            0 => 0,
+            n => waits[((n * 95).div_ceil(100)).saturating_sub(1)],
+        };
+
+        SchedulerMetrics {
+            queue_depth: state.queue.len(),
+            running: state.running_total,
+            max_concurrent: self.config.max_concurrent,
+            max_queue: self.config.max_queue,
+            completed: state.completed,
+            rejected: state.rejected,
+            avg_wait_ms: average(&state.waits_ms).unwrap_or(0),
+            p95_wait_ms,
+            max_wait_ms: waits.last().copied().unwrap_or(0),
+            avg_service_ms: average(&state.service_ms).unwrap_or(0),
+        }
+    }
+}
+
+fn average(samples: &VecDeque<u64>) -> Option<u64> {
+    if samples.is_empty() {
+        return None;
+    }
+    Some(samples.iter().sum::<u64>() / samples.len() as u64)
+}
@@ -4,13 +4,9 @@ use hyper::StatusCode;
 use pwhash::bcrypt;
 use rand::Rng;
 use std::env;
-use std::time::{Duration, SystemTime};
 
 use crate::models::error::ApiError;
 
-const ONE_SECOND: u64 = 1000;
-const ONE_MINUTE: u64 = 60 * ONE_SECOND;
-
 pub fn validate_cpf(cpf: &str) -> bool {
     let cpf: Vec<u8> = cpf
         .chars()
@@ -156,6 +152,16 @@ pub fn get_var_from_env(var: &str) -> Result<String, ApiError> {
     }
 }
 
+pub fn get_parsed_var_from_env<T: std::str::FromStr>(var: &str, default: T) -> T {
+    match get_var_from_env(var) {
+        Ok(value) => value.trim().parse().unwrap_or_else(|_| {
+            eprintln!("ERRO: Valor inválido para {}: {}", var, value);
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
 pub fn get_frontend_url_from_env() -> Result<String, (StatusCode, Json<String>)> {
     dotenv().ok();
 
@@ -201,43 +207,57 @@ pub fn extract_module_name(code: &str) -> Option<String> {
     None
 }
 
-pub async fn auto_delete_files() {
-    let mins = ONE_MINUTE * 20;
-    let intervalo_verificacao = Duration::from_millis(mins);
-    let tempo_maximo_vida = Duration::from_millis(mins);
+pub struct DependencyAnnotation {
+    pub name: String,
+    pub version: Option<String>,
+    pub features: Vec<String>,
+    pub line: usize,
+}
 
-    println!("LOG: Iniciando tarefa de limpeza automática");
+pub fn extract_dependencies(code: &str) -> Vec<DependencyAnnotation> {
+    let mut deps = vec![];
 
-    loop {
-        tokio::time::sleep(intervalo_verificacao).await;
+    for (index, line) in code.lines().enumerate() {
+        let trimmed = line.trim();
+        if !(trimmed.starts_with("//#[dep=") && trimmed.ends_with("]")) {
+            continue;
+        }
 
-        println!("LOG: [GC] Iniciando varredura de limpeza...");
+        let inner = trimmed.trim_start_matches("//#[dep=").trim_end_matches("]");
+        let mut parts = inner.split(',').map(|p| p.trim());
 
-        let mut entradas = match tokio::fs::read_dir("files").await {
-            Ok(e) => e,
-            Err(_) => continue,
+        let (name, version) = match parts.next() {
+            Some(spec) => match spec.split_once('@') {
+                Some((name, version)) => (name.trim(), Some(version.trim().to_string())),
+                None => (spec, None),
+            },
+            None => continue,
         };
 
-        while let Ok(Some(entry)) = entradas.next_entry().await {
-            let path = entry.path();
-
-            if path.is_dir() {
-                if let Ok(metadata) = tokio::fs::metadata(&path).await {
-                    if let Ok(modified) = metadata.modified() {
-                        if let Ok(idade) = SystemTime::now().duration_since(modified) {
-                            if idade > tempo_maximo_vida {
-                                println!("LOG: [GC] Removendo pasta antiga: {:?}", path);
-                                if let Err(e) = tokio::fs::remove_dir_all(&path).await {
-                                    eprintln!("ERRO: [GC] Falha ao deletar {:?}: {}", path, e);
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            continue;
         }
-        println!("LOG: [GC] Varredura finalizada.");
+
+        let features = parts
+            .filter_map(|p| p.strip_prefix("features="))
+            .flat_map(|f| f.split('+'))
+            .map(|f| f.trim().to_string())
+            .filter(|f| !f.is_empty())
+            .collect();
+
+        deps.push(DependencyAnnotation {
+            name: name.to_string(),
+            version,
+            features,
+            line: index + 1,
+        });
     }
+
+    deps
 }
 
 pub fn get_email_credentials() -> Result<(String, String), String> {
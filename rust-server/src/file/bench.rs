@@ -0,0 +1,547 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::sec::sandbox::Termination;
+
+const DEFAULT_ITERATIONS: u32 = 30;
+const MIN_ITERATIONS: u32 = 5;
+const MAX_ITERATIONS: u32 = 1000;
+const DEFAULT_WARMUP: u32 = 3;
+const MAX_WARMUP: u32 = 100;
+
+/// Calls are batched until one sample takes at least this long, so timer
+/// overhead does not dominate fast functions.
+const MIN_SAMPLE_NS: u64 = 1_000_000;
+const MAX_BATCH: u64 = 1 << 20;
+
+/// Written by the harness to the scratch directory, one sample per line. The
+/// measured code shares the harness's process and can rewrite it too.
+pub const SAMPLES_FILE: &str = ".bench-samples";
+
+/// What the user's `main` is renamed to, so the harness can take its place.
+const RENAMED_MAIN: &str = "__bench_main";
+
+const HIGH_VARIANCE_RATIO: f64 = 0.05;
+const OUTLIER_SHARE: f64 = 0.1;
+const TOO_FAST_NS: f64 = 1.0;
+
+const HARNESS: &str = r#"
+fn main() {
+    use ::std::io::Write as _;
+    let mut __bench_samples =
+        ::std::fs::File::create("{samples_file}").expect("benchmark: arquivo de amostras");
+    let mut __bench_batch: u64 = 1;
+    let mut __bench_warmed: u32 = 0;
+    loop {
+        let __bench_start = ::std::time::Instant::now();
+        for _ in 0..__bench_batch {
+            ::std::hint::black_box({target}());
+        }
+        if __bench_start.elapsed().as_nanos() < {min_sample_ns} && __bench_batch < {max_batch} {
+            __bench_batch *= 2;
+        } else if __bench_warmed == {warmup} {
+            break;
+        } else {
+            __bench_warmed += 1;
+        }
+    }
+    let _ = ::std::writeln!(__bench_samples, "batch {}", __bench_batch);
+    for _ in 0..{iterations} {
+        let __bench_start = ::std::time::Instant::now();
+        for _ in 0..__bench_batch {
+            ::std::hint::black_box({target}());
+        }
+        let __bench_elapsed = __bench_start.elapsed().as_nanos();
+        let _ = ::std::writeln!(__bench_samples, "{}", __bench_elapsed);
+    }
+}
+"#;
+
+/// What benchmark mode measures. Without `function`, the whole `main` is
+/// timed; `compare` is another version of the entry block to measure
+/// against it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BenchSettings {
+    #[serde(default)]
+    pub function: Option<String>,
+    #[serde(default = "default_iterations")]
+    pub iterations: u32,
+    #[serde(default = "default_warmup")]
+    pub warmup: u32,
+    #[serde(default)]
+    pub compare: Option<String>,
+}
+
+fn default_iterations() -> u32 {
+    DEFAULT_ITERATIONS
+}
+
+fn default_warmup() -> u32 {
+    DEFAULT_WARMUP
+}
+
+impl Default for BenchSettings {
+    fn default() -> Self {
+        Self {
+            function: None,
+            iterations: DEFAULT_ITERATIONS,
+            warmup: DEFAULT_WARMUP,
+            compare: None,
+        }
+    }
+}
+
+impl BenchSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(MIN_ITERATIONS..=MAX_ITERATIONS).contains(&self.iterations) {
+            return Err(format!(
+                "O número de iterações deve estar entre {} e {}.",
+                MIN_ITERATIONS, MAX_ITERATIONS
+            ));
+        }
+        if self.warmup > MAX_WARMUP {
+            return Err(format!(
+                "O aquecimento pode ter no máximo {} iterações.",
+                MAX_WARMUP
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn target(&self) -> &str {
+        self.function.as_deref().unwrap_or("main")
+    }
+
+    /// Rewrites `main.rs` so its `main` runs the harness instead. The user's
+    /// `main` is renamed in place, keeping diagnostics on the right lines.
+    pub fn instrument(&self, main: &str) -> Result<String, String> {
+        let file = syn::parse_file(main)
+            .map_err(|e| format!("Erro ao analisar o bloco principal: {}", e))?;
+
+        let functions: Vec<&syn::ItemFn> = file
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                syn::Item::Fn(f) => Some(f),
+                _ => None,
+            })
+            .collect();
+
+        let user_main = functions
+            .iter()
+            .find(|f| f.sig.ident == "main")
+            .ok_or("O benchmark precisa de uma função main no bloco principal.")?;
+
+        let target = self.target();
+        let measured = functions
+            .iter()
+            .find(|f| f.sig.ident == target)
+            .ok_or_else(|| format!("A função '{}' não existe no bloco principal.", target))?;
+        check_measurable(measured)?;
+
+        let call = if target == "main" {
+            RENAMED_MAIN
+        } else {
+            target
+        };
+
+        let mut instrumented = main.to_string();
+        let ident_at = byte_offset(main, user_main.sig.ident.span().start());
+        instrumented.replace_range(ident_at..ident_at + "main".len(), RENAMED_MAIN);
+        if target != "main" {
+            let fn_at = byte_offset(main, user_main.sig.fn_token.span.start());
+            instrumented.insert_str(fn_at, "#[allow(dead_code)] ");
+        }
+
+        instrumented.push_str(
+            &HARNESS
+                .replace("{samples_file}", SAMPLES_FILE)
+                .replace("{target}", call)
+                .replace("{min_sample_ns}", &MIN_SAMPLE_NS.to_string())
+                .replace("{max_batch}", &MAX_BATCH.to_string())
+                .replace("{warmup}", &self.warmup.to_string())
+                .replace("{iterations}", &self.iterations.to_string()),
+        );
+        Ok(instrumented)
+    }
+}
+
+fn check_measurable(function: &syn::ItemFn) -> Result<(), String> {
+    let sig = &function.sig;
+    let takes_generics = sig
+        .generics
+        .params
+        .iter()
+        .any(|p| !matches!(p, syn::GenericParam::Lifetime(_)));
+    if !sig.inputs.is_empty() || takes_generics || sig.variadic.is_some() {
+        return Err(format!(
+            "A função '{}' deve ser chamável sem argumentos nem parâmetros genéricos.",
+            sig.ident
+        ));
+    }
+    if sig.asyncness.is_some() {
+        return Err(format!(
+            "A função '{}' não pode ser assíncrona no modo benchmark.",
+            sig.ident
+        ));
+    }
+    Ok(())
+}
+
+/// Spans count lines from one and columns in characters.
+fn byte_offset(source: &str, at: proc_macro2::LineColumn) -> usize {
+    let line_start: usize = source
+        .split_inclusive('\n')
+        .take(at.line - 1)
+        .map(str::len)
+        .sum();
+    source[line_start..]
+        .char_indices()
+        .nth(at.column)
+        .map(|(offset, _)| line_start + offset)
+        .unwrap_or(source.len())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchSummary {
+    pub samples: usize,
+    /// Calls timed together in each sample; the times below are per call.
+    pub batch: u64,
+    pub min_ns: f64,
+    pub median_ns: f64,
+    pub mean_ns: f64,
+    pub max_ns: f64,
+    pub stddev_ns: f64,
+    /// Samples outside 1.5 interquartile ranges of the quartiles.
+    pub outliers_low: usize,
+    pub outliers_high: usize,
+}
+
+impl BenchSummary {
+    fn from_samples(batch: u64, samples: &[f64]) -> Option<Self> {
+        if samples.len() < 2 {
+            return None;
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+
+        let n = sorted.len() as f64;
+        let mean = sorted.iter().sum::<f64>() / n;
+        let variance = sorted.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (n - 1.0);
+
+        let q1 = quantile(&sorted, 0.25);
+        let q3 = quantile(&sorted, 0.75);
+        let fence = 1.5 * (q3 - q1);
+
+        Some(Self {
+            samples: sorted.len(),
+            batch,
+            min_ns: sorted[0],
+            median_ns: quantile(&sorted, 0.5),
+            mean_ns: mean,
+            max_ns: sorted[sorted.len() - 1],
+            stddev_ns: variance.sqrt(),
+            outliers_low: sorted.iter().filter(|s| **s < q1 - fence).count(),
+            outliers_high: sorted.iter().filter(|s| **s > q3 + fence).count(),
+        })
+    }
+
+    fn relative_stddev(&self) -> f64 {
+        if self.mean_ns > 0.0 {
+            self.stddev_ns / self.mean_ns
+        } else {
+            0.0
+        }
+    }
+}
+
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let position = q * (sorted.len() - 1) as f64;
+    let below = position.floor() as usize;
+    let above = position.ceil() as usize;
+    sorted[below] + (sorted[above] - sorted[below]) * (position - below as f64)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VariantReport {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<BenchSummary>,
+    /// Time per call of each sample, in the order they were taken.
+    pub samples_ns: Vec<f64>,
+    pub termination: Termination,
+    /// The samples added up to more than the run's wall time and were dropped.
+    #[serde(skip)]
+    impossible: bool,
+}
+
+impl VariantReport {
+    /// Reads what the harness left in `scratch_dir`. Samples written before
+    /// the run was stopped are kept, unless together they took longer than
+    /// the whole run did.
+    pub async fn collect(
+        name: &str,
+        scratch_dir: &Path,
+        termination: Termination,
+        wall_time: Option<Duration>,
+    ) -> Self {
+        let content = tokio::fs::read_to_string(scratch_dir.join(SAMPLES_FILE))
+            .await
+            .unwrap_or_default();
+        let (batch, mut samples_ns) = parse_samples(&content);
+
+        let measured_ns: f64 = samples_ns.iter().map(|s| s * batch as f64).sum();
+        // The wall time is kept in whole milliseconds.
+        let impossible = wall_time
+            .is_some_and(|wall| measured_ns > (wall + Duration::from_millis(1)).as_nanos() as f64);
+        if impossible {
+            samples_ns.clear();
+        }
+
+        Self {
+            name: name.to_string(),
+            summary: BenchSummary::from_samples(batch, &samples_ns),
+            samples_ns,
+            termination,
+            impossible,
+        }
+    }
+
+    fn label(&self) -> String {
+        format!("Variante {}", self.name.to_uppercase())
+    }
+}
+
+fn parse_samples(content: &str) -> (u64, Vec<f64>) {
+    let mut lines = content.lines();
+    let batch = lines
+        .next()
+        .and_then(|line| line.strip_prefix("batch "))
+        .and_then(|batch| batch.parse::<u64>().ok())
+        .filter(|batch| *batch > 0);
+    let Some(batch) = batch else {
+        return (1, vec![]);
+    };
+
+    let samples = lines
+        .map_while(|line| line.parse::<u64>().ok())
+        .map(|elapsed| elapsed as f64 / batch as f64)
+        .collect();
+    (batch, samples)
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseKind {
+    /// Fewer samples than requested, because the run stopped early.
+    Incomplete,
+    HighVariance,
+    Outliers,
+    /// Faster than a nanosecond, so the work was probably optimized away.
+    TooFast,
+    /// The variants differ by less than their spread.
+    WithinNoise,
+    /// The samples claimed more time than the run took.
+    Inconsistent,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NoiseWarning {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    pub kind: NoiseKind,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchComparison {
+    /// Median of the second variant over the first's.
+    pub ratio: f64,
+    /// The variant with the lower median, unless the gap is within noise.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub faster: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchReport {
+    pub function: String,
+    pub iterations: u32,
+    pub warmup: u32,
+    /// Always set: the timings are taken inside the measured program, which
+    /// can overwrite them, so they only inform the user who ran it.
+    pub self_reported: bool,
+    pub variants: Vec<VariantReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comparison: Option<BenchComparison>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<NoiseWarning>,
+}
+
+impl BenchReport {
+    pub fn new(settings: &BenchSettings, variants: Vec<VariantReport>) -> Self {
+        let mut warnings = vec![];
+        for variant in &variants {
+            warnings.extend(variant_warnings(variant, settings.iterations));
+        }
+
+        let comparison = match variants.as_slice() {
+            [a, b] => match (&a.summary, &b.summary) {
+                (Some(sa), Some(sb)) if sa.median_ns > 0.0 => {
+                    let within_noise =
+                        (sa.median_ns - sb.median_ns).abs() < sa.stddev_ns + sb.stddev_ns;
+                    if within_noise {
+                        warnings.push(NoiseWarning {
+                            variant: None,
+                            kind: NoiseKind::WithinNoise,
+                            message: "Medição ruidosa: a diferença entre as variantes é menor \
+                                      que a variação das medidas."
+                                .into(),
+                        });
+                    }
+                    let faster = if sa.median_ns <= sb.median_ns { a } else { b };
+                    Some(BenchComparison {
+                        ratio: sb.median_ns / sa.median_ns,
+                        faster: (!within_noise).then(|| faster.name.clone()),
+                    })
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+
+        Self {
+            function: settings.target().to_string(),
+            iterations: settings.iterations,
+            warmup: settings.warmup,
+            self_reported: true,
+            variants,
+            comparison,
+            warnings,
+        }
+    }
+
+    /// Summary for the response's stdout.
+    pub fn describe(&self) -> String {
+        let mut lines = vec![format!(
+            "Benchmark de '{}': {} iterações após {} de aquecimento",
+            self.function, self.iterations, self.warmup
+        )];
+        for variant in &self.variants {
+            lines.push(match &variant.summary {
+                Some(s) => format!(
+                    "{}: mediana {} (média {} ± {}, mín {}, máx {}, {} outliers)",
+                    variant.label(),
+                    format_ns(s.median_ns),
+                    format_ns(s.mean_ns),
+                    format_ns(s.stddev_ns),
+                    format_ns(s.min_ns),
+                    format_ns(s.max_ns),
+                    s.outliers_low + s.outliers_high
+                ),
+                None => format!("{}: sem amostras suficientes", variant.label()),
+            });
+        }
+        if let Some(comparison) = &self.comparison {
+            lines.push(match &comparison.faster {
+                Some(name) => {
+                    let factor = if comparison.ratio >= 1.0 {
+                        comparison.ratio
+                    } else {
+                        1.0 / comparison.ratio
+                    };
+                    format!(
+                        "Variante {} é {:.2}x mais rápida",
+                        name.to_uppercase(),
+                        factor
+                    )
+                }
+                None => "Sem diferença mensurável entre as variantes".to_string(),
+            });
+        }
+        for warning in &self.warnings {
+            lines.push(format!("AVISO: {}", warning.message));
+        }
+        lines.push(
+            "Os tempos são medidos pelo próprio programa e não podem ser verificados pelo servidor."
+                .to_string(),
+        );
+        lines.join("\n")
+    }
+}
+
+fn variant_warnings(variant: &VariantReport, iterations: u32) -> Vec<NoiseWarning> {
+    let mut warnings = vec![];
+    let mut warn = |kind, message: String| {
+        warnings.push(NoiseWarning {
+            variant: Some(variant.name.clone()),
+            kind,
+            message: format!("{}: {}", variant.label(), message),
+        })
+    };
+
+    if variant.impossible {
+        warn(
+            NoiseKind::Inconsistent,
+            "as amostras somam mais tempo do que a execução levou e foram descartadas.".into(),
+        );
+        return warnings;
+    }
+
+    if variant.samples_ns.len() < iterations as usize {
+        warn(
+            NoiseKind::Incomplete,
+            format!(
+                "apenas {} de {} amostras foram coletadas antes do fim da execução.",
+                variant.samples_ns.len(),
+                iterations
+            ),
+        );
+    }
+
+    let Some(summary) = &variant.summary else {
+        return warnings;
+    };
+
+    let spread = summary.relative_stddev();
+    if spread > HIGH_VARIANCE_RATIO {
+        warn(
+            NoiseKind::HighVariance,
+            format!(
+                "medição ruidosa, desvio padrão de {:.1}% da média.",
+                spread * 100.0
+            ),
+        );
+    }
+
+    let outliers = summary.outliers_low + summary.outliers_high;
+    if outliers as f64 > summary.samples as f64 * OUTLIER_SHARE {
+        warn(
+            NoiseKind::Outliers,
+            format!(
+                "medição ruidosa, {} de {} amostras são outliers.",
+                outliers, summary.samples
+            ),
+        );
+    }
+
+    if summary.median_ns < TOO_FAST_NS {
+        warn(
+            NoiseKind::TooFast,
+            "menos de 1 ns por chamada; o compilador pode ter eliminado o trabalho medido.".into(),
+        );
+    }
+    warnings
+}
+
+fn format_ns(ns: f64) -> String {
+    if ns >= 1e9 {
+        format!("{:.2} s", ns / 1e9)
+    } else if ns >= 1e6 {
+        format!("{:.2} ms", ns / 1e6)
+    } else if ns >= 1e3 {
+        format!("{:.2} µs", ns / 1e3)
+    } else {
+        format!("{:.2} ns", ns)
+    }
+}
@@ -0,0 +1,534 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Mutex as AsyncMutex;
+
+use crate::controllers::utils::{get_parsed_var_from_env, get_var_from_env};
+use crate::file::RunOutput;
+use crate::file::diagnostics::Diagnostic;
+use crate::file::toolchain::{BuildSettings, toolchain_version};
+use crate::sec::input::RunInput;
+use crate::sec::sandbox::Termination;
+
+const BINARY_FILE: &str = "bin";
+const META_FILE: &str = "meta.json";
+
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    pub dir: PathBuf,
+    pub max_bytes: u64,
+}
+
+impl CacheConfig {
+    pub fn from_env() -> Self {
+        Self {
+            dir: PathBuf::from(
+                get_var_from_env("BUILD_CACHE_DIR").unwrap_or_else(|_| "cache/builds".to_string()),
+            ),
+            max_bytes: get_parsed_var_from_env::<u64>("BUILD_CACHE_MAX_MB", 1024) * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheInfo {
+    pub key: String,
+    pub binary_hit: bool,
+    pub output_hit: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedRun {
+    pub stdout: String,
+    pub stderr: String,
+    pub termination: Termination,
+    /// Set once a second run produced the same output.
+    pub confirmed: bool,
+}
+
+pub struct CachedBinary<'a> {
+    pub path: String,
+    pub warnings: String,
+    pub diagnostics: Vec<Diagnostic>,
+    /// Keeps the entry from being evicted while the binary runs.
+    pub pin: CachePin<'a>,
+}
+
+pub struct CachePin<'a> {
+    cache: &'a BuildCache,
+    key: String,
+}
+
+impl Drop for CachePin<'_> {
+    fn drop(&mut self) {
+        let mut index = self.cache.index.lock().unwrap();
+        if let Some(count) = index.pins.get_mut(&self.key) {
+            *count -= 1;
+            if *count == 0 {
+                index.pins.remove(&self.key);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct EntryMeta {
+    warnings: String,
+    #[serde(default)]
+    diagnostics: Vec<Diagnostic>,
+    outputs: HashMap<String, CachedRun>,
+    /// Inputs whose runs disagreed with each other; never served from cache.
+    volatile: HashSet<String>,
+}
+
+struct EntryInfo {
+    size_bytes: u64,
+    last_access: u64,
+}
+
+#[derive(Default)]
+struct CacheIndex {
+    entries: HashMap<String, EntryInfo>,
+    /// Entries whose binary is in use; never evicted.
+    pins: HashMap<String, usize>,
+    total_bytes: u64,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl CacheIndex {
+    fn touch(&mut self, key: &str) -> bool {
+        self.clock += 1;
+        let clock = self.clock;
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                entry.last_access = clock;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn set_size(&mut self, key: &str, size_bytes: u64) {
+        self.clock += 1;
+        let clock = self.clock;
+        let entry = self.entries.entry(key.to_string()).or_insert(EntryInfo {
+            size_bytes: 0,
+            last_access: clock,
+        });
+        self.total_bytes = self.total_bytes - entry.size_bytes + size_bytes;
+        entry.size_bytes = size_bytes;
+        entry.last_access = clock;
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.total_bytes -= entry.size_bytes;
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub total_bytes: u64,
+    pub max_bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PurgeSummary {
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+/// Compiled binaries and verified program output, addressed by a hash of the
+/// workspace sources, manifest and toolchain. Entries are evicted least
+/// recently used first once the cache grows past its byte budget.
+pub struct BuildCache {
+    config: CacheConfig,
+    index: Mutex<CacheIndex>,
+    /// Held while an entry's `meta.json` is read and rewritten, so concurrent
+    /// runs of one binary do not lose each other's updates.
+    meta_locks: Mutex<HashMap<String, Arc<AsyncMutex<()>>>>,
+}
+
+impl BuildCache {
+    pub fn new(config: CacheConfig) -> Self {
+        let mut index = CacheIndex::default();
+
+        let mut found: Vec<(String, u64, std::time::SystemTime)> = vec![];
+        if let Ok(entries) = std::fs::read_dir(&config.dir) {
+            for entry in entries.flatten() {
+                let key = entry.file_name().to_string_lossy().to_string();
+                if key.starts_with('.') {
+                    // Leftover staging directory from an interrupted store.
+                    let _ = std::fs::remove_dir_all(entry.path());
+                    continue;
+                }
+                let size_bytes = dir_size(&entry.path());
+                let accessed = std::fs::metadata(entry.path().join(META_FILE))
+                    .and_then(|m| m.modified())
+                    .unwrap_or(std::time::UNIX_EPOCH);
+                found.push((key, size_bytes, accessed));
+            }
+        }
+        found.sort_by_key(|(_, _, accessed)| *accessed);
+        for (key, size_bytes, _) in found {
+            index.set_size(&key, size_bytes);
+        }
+
+        if !index.entries.is_empty() {
+            println!(
+                "LOG: Cache de compilação carregado ({} entradas, {} bytes)",
+                index.entries.len(),
+                index.total_bytes
+            );
+        }
+
+        Self {
+            config,
+            index: Mutex::new(index),
+            meta_locks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn entry_dir(&self, key: &str) -> PathBuf {
+        self.config.dir.join(key)
+    }
+
+    pub async fn build_key(&self, project_path: &Path, settings: &BuildSettings) -> Option<String> {
+        let mut hasher = Sha256::new();
+        hasher.update(
+            toolchain_version(settings.channel.as_deref())
+                .await
+                .as_bytes(),
+        );
+        hasher.update([0]);
+        hasher.update(serde_json::to_vec(settings).ok()?);
+        hasher.update([0]);
+
+        let manifest = tokio::fs::read(project_path.join("Cargo.toml"))
+            .await
+            .ok()?;
+        hasher.update(&manifest);
+        hasher.update([0]);
+
+        let mut sources = vec![];
+        let mut entries = tokio::fs::read_dir(project_path.join("src")).await.ok()?;
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "rs") {
+                sources.push(path);
+            }
+        }
+        sources.sort();
+
+        for path in sources {
+            let content = tokio::fs::read(&path).await.ok()?;
+            hasher.update(path.file_name()?.as_encoded_bytes());
+            hasher.update([0]);
+            hasher.update(&content);
+            hasher.update([0]);
+        }
+
+        Some(hex::encode(hasher.finalize()))
+    }
+
+    pub async fn binary(&self, key: &str) -> Option<CachedBinary<'_>> {
+        {
+            let mut index = self.index.lock().unwrap();
+            if !index.touch(key) {
+                index.misses += 1;
+                return None;
+            }
+            *index.pins.entry(key.to_string()).or_insert(0) += 1;
+        }
+        let pin = CachePin {
+            cache: self,
+            key: key.to_string(),
+        };
+
+        let dir = self.entry_dir(key);
+        let binary = dir.join(BINARY_FILE);
+        let meta = match read_meta(&dir).await {
+            Some(meta) if binary.exists() => meta,
+            _ => {
+                self.index.lock().unwrap().remove(key);
+                return None;
+            }
+        };
+
+        self.index.lock().unwrap().hits += 1;
+        Some(CachedBinary {
+            path: std::fs::canonicalize(&binary)
+                .unwrap_or(binary)
+                .to_string_lossy()
+                .to_string(),
+            warnings: meta.warnings,
+            diagnostics: meta.diagnostics,
+            pin,
+        })
+    }
+
+    /// Contents of a cached binary, for serving it to clients. Keeps the entry
+    /// fresh without counting as a build hit.
+    pub async fn artifact(&self, key: &str) -> Option<Vec<u8>> {
+        if !self.index.lock().unwrap().touch(key) {
+            return None;
+        }
+
+        match tokio::fs::read(self.entry_dir(key).join(BINARY_FILE)).await {
+            Ok(bytes) => Some(bytes),
+            Err(_) => {
+                self.index.lock().unwrap().remove(key);
+                None
+            }
+        }
+    }
+
+    pub async fn store_binary(
+        &self,
+        key: &str,
+        exe_path: &str,
+        warnings: &str,
+        diagnostics: &[Diagnostic],
+    ) {
+        let dir = self.entry_dir(key);
+        let staging = self
+            .config
+            .dir
+            .join(format!(".{}.{}", key, uuid::Uuid::new_v4().simple()));
+
+        let stored = async {
+            tokio::fs::create_dir_all(&staging).await?;
+            tokio::fs::copy(exe_path, staging.join(BINARY_FILE)).await?;
+            let meta = EntryMeta {
+                warnings: warnings.to_string(),
+                diagnostics: diagnostics.to_vec(),
+                ..Default::default()
+            };
+            write_meta(&staging, &meta).await?;
+            tokio::fs::rename(&staging, &dir).await
+        }
+        .await;
+
+        if let Err(e) = stored {
+            let _ = tokio::fs::remove_dir_all(&staging).await;
+            if !dir.exists() {
+                eprintln!("ERRO: Falha ao armazenar binário em cache: {}", e);
+            }
+            return;
+        }
+
+        self.index.lock().unwrap().set_size(key, dir_size(&dir));
+        self.evict(key).await;
+    }
+
+    pub async fn cached_output(&self, key: &str, output_key: &str) -> Option<CachedRun> {
+        if !self.index.lock().unwrap().touch(key) {
+            return None;
+        }
+
+        let meta = read_meta(&self.entry_dir(key)).await?;
+        let run = meta.outputs.get(output_key).filter(|run| run.confirmed)?;
+
+        self.index.lock().unwrap().hits += 1;
+        Some(run.clone())
+    }
+
+    /// Keeps the output of a finished, untruncated run. It is only served once
+    /// another run of the same binary and input reproduces it exactly.
+    pub async fn record_output(&self, key: &str, output_key: &str, run: &RunOutput) {
+        if !matches!(run.termination, Termination::Exited { .. })
+            || run.stats.stdout_truncated
+            || run.stats.stderr_truncated
+        {
+            return;
+        }
+
+        let lock = self.meta_lock(key);
+        let updated = {
+            let _guard = lock.lock().await;
+            self.update_outputs(key, output_key, run).await
+        };
+        drop(lock);
+        self.meta_locks
+            .lock()
+            .unwrap()
+            .retain(|_, lock| Arc::strong_count(lock) > 1);
+
+        if updated {
+            self.index
+                .lock()
+                .unwrap()
+                .set_size(key, dir_size(&self.entry_dir(key)));
+            self.evict(key).await;
+        }
+    }
+
+    fn meta_lock(&self, key: &str) -> Arc<AsyncMutex<()>> {
+        self.meta_locks
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// Returns whether `meta.json` was rewritten.
+    async fn update_outputs(&self, key: &str, output_key: &str, run: &RunOutput) -> bool {
+        let dir = self.entry_dir(key);
+        let Some(mut meta) = read_meta(&dir).await else {
+            return false;
+        };
+        if meta.volatile.contains(output_key) {
+            return false;
+        }
+
+        match meta.outputs.get_mut(output_key) {
+            Some(previous) => {
+                if previous.stdout == run.stdout
+                    && previous.stderr == run.stderr
+                    && previous.termination == run.termination
+                {
+                    if previous.confirmed {
+                        return false;
+                    }
+                    previous.confirmed = true;
+                } else {
+                    println!("LOG: Saída não determinística para {}, sem cache", key);
+                    meta.outputs.remove(output_key);
+                    meta.volatile.insert(output_key.to_string());
+                }
+            }
+            None => {
+                meta.outputs.insert(
+                    output_key.to_string(),
+                    CachedRun {
+                        stdout: run.stdout.clone(),
+                        stderr: run.stderr.clone(),
+                        termination: run.termination.clone(),
+                        confirmed: false,
+                    },
+                );
+            }
+        }
+
+        if let Err(e) = write_meta(&dir, &meta).await {
+            eprintln!("ERRO: Falha ao atualizar cache de saída: {}", e);
+            return false;
+        }
+        true
+    }
+
+    async fn evict(&self, keep: &str) {
+        let victims = {
+            let mut index = self.index.lock().unwrap();
+            let mut by_age: Vec<(u64, String)> = index
+                .entries
+                .iter()
+                .filter(|(key, _)| key.as_str() != keep && !index.pins.contains_key(*key))
+                .map(|(key, entry)| (entry.last_access, key.clone()))
+                .collect();
+            by_age.sort();
+
+            let mut victims = vec![];
+            for (_, key) in by_age {
+                if index.total_bytes <= self.config.max_bytes {
+                    break;
+                }
+                index.remove(&key);
+                victims.push(key);
+            }
+            victims
+        };
+
+        for key in victims {
+            println!("LOG: Removendo entrada do cache de compilação: {}", key);
+            let _ = tokio::fs::remove_dir_all(self.entry_dir(&key)).await;
+        }
+    }
+
+    /// Removes every entry except those whose binary is running.
+    pub async fn purge(&self) -> PurgeSummary {
+        let (keys, bytes) = {
+            let mut index = self.index.lock().unwrap();
+            let keys: Vec<String> = index
+                .entries
+                .keys()
+                .filter(|key| !index.pins.contains_key(*key))
+                .cloned()
+                .collect();
+            let before = index.total_bytes;
+            for key in &keys {
+                index.remove(key);
+            }
+            (keys, before - index.total_bytes)
+        };
+
+        for key in &keys {
+            let _ = tokio::fs::remove_dir_all(self.entry_dir(key)).await;
+        }
+
+        PurgeSummary {
+            entries: keys.len(),
+            bytes,
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let index = self.index.lock().unwrap();
+        CacheStats {
+            entries: index.entries.len(),
+            total_bytes: index.total_bytes,
+            max_bytes: self.config.max_bytes,
+            hits: index.hits,
+            misses: index.misses,
+        }
+    }
+}
+
+/// Runs with different argv or environment are cached separately.
+pub fn output_key(build_key: &str, input: &RunInput) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(build_key.as_bytes());
+    for arg in &input.args {
+        hasher.update([0]);
+        hasher.update(arg.as_bytes());
+    }
+    hasher.update([1]);
+    for (name, value) in &input.env {
+        hasher.update(name.as_bytes());
+        hasher.update([0]);
+        hasher.update(value.as_bytes());
+        hasher.update([0]);
+    }
+    hex::encode(hasher.finalize())
+}
+
+async fn read_meta(dir: &Path) -> Option<EntryMeta> {
+    let raw = tokio::fs::read(dir.join(META_FILE)).await.ok()?;
+    serde_json::from_slice(&raw).ok()
+}
+
+async fn write_meta(dir: &Path, meta: &EntryMeta) -> std::io::Result<()> {
+    let raw = serde_json::to_vec(meta)?;
+    tokio::fs::write(dir.join(META_FILE), raw).await
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|e| e.metadata().ok())
+                .map(|m| m.len())
+                .sum()
+        })
+        .unwrap_or(0)
+}
@@ -0,0 +1,200 @@
+use serde::{Deserialize, Serialize};
+
+/// A compiler diagnostic in the shape the editor needs to draw squiggles and
+/// offer quick-fixes. Lines and columns are 1-based; `column_end` is exclusive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub level: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    pub message: String,
+    pub spans: Vec<DiagnosticSpan>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Diagnostic>,
+    /// Machine-applicable edits found anywhere in this diagnostic's tree.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub suggestions: Vec<Suggestion>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rendered: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosticSpan {
+    pub file_name: String,
+    pub line_start: usize,
+    pub line_end: usize,
+    pub column_start: usize,
+    pub column_end: usize,
+    pub is_primary: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Suggestion {
+    pub message: String,
+    pub file_name: String,
+    pub line_start: usize,
+    pub line_end: usize,
+    pub column_start: usize,
+    pub column_end: usize,
+    pub replacement: String,
+}
+
+/// What a `cargo --message-format=json` invocation reported.
+#[derive(Debug, Default)]
+pub struct CompilerMessages {
+    /// Human-readable messages joined for the console view.
+    pub rendered: String,
+    pub diagnostics: Vec<Diagnostic>,
+    pub executable: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawDiagnostic {
+    message: String,
+    level: String,
+    #[serde(default)]
+    code: Option<RawCode>,
+    #[serde(default)]
+    spans: Vec<RawSpan>,
+    #[serde(default)]
+    children: Vec<RawDiagnostic>,
+    #[serde(default)]
+    rendered: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawCode {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct RawSpan {
+    file_name: String,
+    line_start: usize,
+    line_end: usize,
+    column_start: usize,
+    column_end: usize,
+    is_primary: bool,
+    #[serde(default)]
+    label: Option<String>,
+    #[serde(default)]
+    suggested_replacement: Option<String>,
+    #[serde(default)]
+    suggestion_applicability: Option<String>,
+}
+
+/// Reads cargo's JSON lines, picking the test harness executable when
+/// `test_harness` is set and the regular binary otherwise.
+pub fn parse_cargo_messages(stdout: &str, test_harness: bool) -> CompilerMessages {
+    let mut messages = CompilerMessages::default();
+
+    for line in stdout.lines() {
+        let Ok(val) = serde_json::from_str::<serde_json::Value>(line) else {
+            continue;
+        };
+
+        if let Some(message) = val.get("message") {
+            if let Some(rendered) = message.get("rendered").and_then(|r| r.as_str()) {
+                messages.rendered.push_str(rendered);
+                messages.rendered.push('\n');
+            }
+
+            if let Ok(raw) = RawDiagnostic::deserialize(message)
+                && !is_summary(&raw)
+            {
+                messages.diagnostics.push(convert(raw));
+            }
+        }
+
+        let is_test = val
+            .pointer("/profile/test")
+            .and_then(|t| t.as_bool())
+            .unwrap_or(false);
+        if line.contains(r#""executable""#)
+            && is_test == test_harness
+            && let Some(exec) = val.get("executable").and_then(|v| v.as_str())
+        {
+            messages.executable = Some(exec.to_string());
+        }
+    }
+
+    messages
+}
+
+/// Reads a line rustc printed with `--error-format=json`, returning its
+/// rendered text and, unless it is only a summary, the diagnostic.
+pub fn parse_rustc_diagnostic(line: &str) -> Option<(String, Option<Diagnostic>)> {
+    let val = serde_json::from_str::<serde_json::Value>(line).ok()?;
+    if val.get("$message_type").and_then(|t| t.as_str()) != Some("diagnostic") {
+        return None;
+    }
+    let raw = RawDiagnostic::deserialize(&val).ok()?;
+    let rendered = raw.rendered.clone().unwrap_or_default();
+    let diagnostic = (!is_summary(&raw)).then(|| convert(raw));
+    Some((rendered, diagnostic))
+}
+
+// "aborting due to 2 previous errors" and "1 warning emitted" point nowhere
+// and only matter in the rendered view.
+fn is_summary(raw: &RawDiagnostic) -> bool {
+    raw.spans.is_empty() && raw.children.is_empty() && raw.code.is_none()
+}
+
+fn convert(raw: RawDiagnostic) -> Diagnostic {
+    let mut suggestions = vec![];
+    collect_suggestions(&raw, &mut suggestions);
+
+    let mut diagnostic = convert_node(raw);
+    diagnostic.suggestions = suggestions;
+    diagnostic
+}
+
+fn convert_node(raw: RawDiagnostic) -> Diagnostic {
+    Diagnostic {
+        level: raw.level,
+        code: raw.code.map(|c| c.code),
+        message: raw.message,
+        spans: raw
+            .spans
+            .into_iter()
+            .map(|span| DiagnosticSpan {
+                file_name: span.file_name,
+                line_start: span.line_start,
+                line_end: span.line_end,
+                column_start: span.column_start,
+                column_end: span.column_end,
+                is_primary: span.is_primary,
+                label: span.label,
+            })
+            .collect(),
+        children: raw.children.into_iter().map(convert_node).collect(),
+        suggestions: vec![],
+        rendered: raw.rendered,
+    }
+}
+
+fn collect_suggestions(raw: &RawDiagnostic, out: &mut Vec<Suggestion>) {
+    for span in &raw.spans {
+        let Some(replacement) = &span.suggested_replacement else {
+            continue;
+        };
+        if span.suggestion_applicability.as_deref() != Some("MachineApplicable") {
+            continue;
+        }
+        out.push(Suggestion {
+            message: raw.message.clone(),
+            file_name: span.file_name.clone(),
+            line_start: span.line_start,
+            line_end: span.line_end,
+            column_start: span.column_start,
+            column_end: span.column_end,
+            replacement: replacement.clone(),
+        });
+    }
+
+    for child in &raw.children {
+        collect_suggestions(child, out);
+    }
+}
@@ -0,0 +1,520 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::file::WORKSPACE_PACKAGE_NAME;
+use crate::file::toolchain::{BuildProfile, BuildSettings};
+
+/// Explorer builds get their own target directory so switching flags does
+/// not invalidate the regular build.
+pub const EXPLORER_TARGET_DIR: &str = "target/explore";
+
+const MAX_EXPLORER_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmitKind {
+    #[default]
+    Asm,
+    LlvmIr,
+    Mir,
+    /// Source after macro expansion, pretty-printed.
+    Expanded,
+}
+
+impl EmitKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            EmitKind::Asm => "Assembly",
+            EmitKind::LlvmIr => "LLVM IR",
+            EmitKind::Mir => "MIR",
+            EmitKind::Expanded => "Código expandido",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            EmitKind::Asm => "s",
+            EmitKind::LlvmIr => "ll",
+            EmitKind::Mir => "mir",
+            EmitKind::Expanded => "rs",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OptLevel {
+    #[serde(rename = "0")]
+    O0,
+    #[serde(rename = "1")]
+    O1,
+    #[serde(rename = "2")]
+    O2,
+    #[serde(rename = "3")]
+    O3,
+    #[serde(rename = "s")]
+    Size,
+    #[serde(rename = "z")]
+    MinSize,
+}
+
+impl OptLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OptLevel::O0 => "0",
+            OptLevel::O1 => "1",
+            OptLevel::O2 => "2",
+            OptLevel::O3 => "3",
+            OptLevel::Size => "s",
+            OptLevel::MinSize => "z",
+        }
+    }
+}
+
+/// What explorer mode shows. Without `opt_level` the profile's own is used.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExploreSettings {
+    #[serde(default)]
+    pub emit: EmitKind,
+    #[serde(default)]
+    pub opt_level: Option<OptLevel>,
+    /// Keeps only the functions written in the notebook and drops assembler
+    /// directives. Applies to assembly only.
+    #[serde(default = "default_filter")]
+    pub filter: bool,
+}
+
+impl Default for ExploreSettings {
+    fn default() -> Self {
+        Self {
+            emit: EmitKind::default(),
+            opt_level: None,
+            filter: default_filter(),
+        }
+    }
+}
+
+fn default_filter() -> bool {
+    true
+}
+
+impl ExploreSettings {
+    pub fn effective_opt_level(&self, build: &BuildSettings) -> OptLevel {
+        self.opt_level.unwrap_or(match build.profile {
+            BuildProfile::Debug => OptLevel::O0,
+            BuildProfile::Release => OptLevel::O3,
+        })
+    }
+
+    /// Where rustc writes the output; macro expansion goes to stdout instead.
+    pub fn output_path(&self, project_path: &Path) -> Option<PathBuf> {
+        match self.emit {
+            EmitKind::Expanded => None,
+            emit => Some(
+                project_path
+                    .join(EXPLORER_TARGET_DIR)
+                    .join(format!("out.{}", emit.extension())),
+            ),
+        }
+    }
+
+    /// Arguments for `cargo`, given the absolute path from `output_path`.
+    pub fn cargo_args(&self, output_path: Option<&Path>) -> Vec<String> {
+        let mut args: Vec<String> = [
+            "rustc",
+            "--bin",
+            WORKSPACE_PACKAGE_NAME,
+            "--target-dir",
+            EXPLORER_TARGET_DIR,
+            "--config=profile.dev.incremental=false",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+        if self.emit != EmitKind::Expanded {
+            args.push("--message-format=json".into());
+        }
+        args.push("--".into());
+
+        match (self.emit, output_path) {
+            (EmitKind::Expanded, _) | (_, None) => args.push("-Zunpretty=expanded".into()),
+            (emit, Some(path)) => {
+                let kind = match emit {
+                    EmitKind::Asm => "asm",
+                    EmitKind::LlvmIr => "llvm-ir",
+                    _ => "mir",
+                };
+                args.push(format!("--emit={}={}", kind, path.display()));
+                // One codegen unit keeps the output in a single file, in
+                // source order.
+                args.extend(["-C".into(), "codegen-units=1".into()]);
+                if emit == EmitKind::Asm {
+                    args.extend(["-C".into(), "debuginfo=line-tables-only".into()]);
+                }
+            }
+        }
+        if let Some(level) = self.opt_level {
+            args.extend(["-C".into(), format!("opt-level={}", level.as_str())]);
+        }
+        args
+    }
+
+    /// `-Z unpretty` is unstable; the same escape hatch as the test runner
+    /// makes it work on stable toolchains.
+    pub fn cargo_env(&self) -> Vec<(String, String)> {
+        match self.emit {
+            EmitKind::Expanded => vec![("RUSTC_BOOTSTRAP".to_string(), "1".to_string())],
+            _ => vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceMapping {
+    /// 1-based line in `code`.
+    pub output_line: usize,
+    /// File under `src/`, such as `main.rs`.
+    pub file: String,
+    pub line: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExplorerOutput {
+    pub emit: EmitKind,
+    pub opt_level: OptLevel,
+    pub code: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub source_map: Vec<SourceMapping>,
+    /// Demangled names of the functions in `code`; assembly only.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub functions: Vec<String>,
+    pub truncated: bool,
+}
+
+impl ExplorerOutput {
+    pub fn new(emit: EmitKind, opt_level: OptLevel, code: String) -> Self {
+        let mut output = Self {
+            emit,
+            opt_level,
+            code,
+            source_map: vec![],
+            functions: vec![],
+            truncated: false,
+        };
+        output.truncate();
+        output
+    }
+
+    pub fn from_asm(opt_level: OptLevel, asm: &str, src_dir: &Path, filter: bool) -> Self {
+        let listing = parse_asm(asm, src_dir, filter);
+        let mut output = Self {
+            emit: EmitKind::Asm,
+            opt_level,
+            code: listing.lines.join("\n"),
+            source_map: listing.source_map,
+            functions: listing.functions,
+            truncated: false,
+        };
+        output.truncate();
+        output
+    }
+
+    fn truncate(&mut self) {
+        if self.code.len() <= MAX_EXPLORER_BYTES {
+            return;
+        }
+        let mut cut = self.code.as_bytes()[..MAX_EXPLORER_BYTES]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .unwrap_or(MAX_EXPLORER_BYTES);
+        while !self.code.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        self.code.truncate(cut);
+        let kept_lines = self.code.lines().count();
+        self.source_map.retain(|m| m.output_line <= kept_lines);
+        self.truncated = true;
+    }
+}
+
+struct AsmListing {
+    lines: Vec<String>,
+    source_map: Vec<SourceMapping>,
+    functions: Vec<String>,
+}
+
+struct AsmFunction {
+    name: String,
+    lines: Vec<(String, Option<(String, u32)>)>,
+    from_user_code: bool,
+}
+
+/// Demangles symbols and attaches the user's source lines from the `.loc`
+/// directives. With `filter`, only functions with code from `src_dir` are
+/// kept, without directives, comments or debug labels.
+fn parse_asm(asm: &str, src_dir: &Path, filter: bool) -> AsmListing {
+    let mut files: HashMap<u32, Option<String>> = HashMap::new();
+    let mut listing = AsmListing {
+        lines: vec![],
+        source_map: vec![],
+        functions: vec![],
+    };
+    let mut current: Option<AsmFunction> = None;
+    let mut location: Option<(String, u32)> = None;
+
+    for raw in asm.lines() {
+        let trimmed = raw.trim();
+
+        if let Some(rest) = trimmed.strip_prefix(".file")
+            && let Some((number, path)) = parse_file_directive(rest)
+        {
+            files.insert(number, user_file(&path, src_dir));
+        }
+        if let Some(rest) = trimmed.strip_prefix(".loc") {
+            location = parse_loc_directive(rest)
+                .and_then(|(file, line)| Some((files.get(&file)?.clone()?, line)));
+        }
+
+        if is_function_label(raw) {
+            if let Some(function) = current.take() {
+                emit_function(&mut listing, function, filter);
+            }
+            let name = demangle_line(trimmed.trim_end_matches(':'));
+            current = Some(AsmFunction {
+                from_user_code: is_user_symbol(&name),
+                name,
+                lines: vec![],
+            });
+            location = None;
+        }
+
+        let Some(function) = current.as_mut() else {
+            if !filter {
+                listing.lines.push(demangle_line(raw));
+            }
+            continue;
+        };
+
+        if trimmed.starts_with(".Lfunc_end") {
+            if let Some(function) = current.take() {
+                emit_function(&mut listing, function, filter);
+            }
+            if !filter {
+                listing.lines.push(raw.to_string());
+            }
+            continue;
+        }
+
+        let is_instruction = !trimmed.is_empty()
+            && !trimmed.starts_with('.')
+            && !trimmed.starts_with('#')
+            && !trimmed.ends_with(':');
+        if is_instruction && location.is_some() {
+            function.from_user_code = true;
+        }
+
+        let keep = !filter
+            || is_instruction
+            || (trimmed.ends_with(':')
+                && !trimmed.starts_with(".Ltmp")
+                && !trimmed.starts_with(".Lfunc_begin"));
+        if keep {
+            let mapped = if is_instruction {
+                location.clone()
+            } else {
+                None
+            };
+            function.lines.push((demangle_line(raw), mapped));
+        }
+    }
+    if let Some(function) = current.take() {
+        emit_function(&mut listing, function, filter);
+    }
+    listing
+}
+
+fn emit_function(listing: &mut AsmListing, function: AsmFunction, filter: bool) {
+    if filter && !function.from_user_code {
+        return;
+    }
+    if filter && !listing.lines.is_empty() {
+        listing.lines.push(String::new());
+    }
+    listing.functions.push(function.name);
+    for (line, location) in function.lines {
+        listing.lines.push(line);
+        if let Some((file, source_line)) = location {
+            listing.source_map.push(SourceMapping {
+                output_line: listing.lines.len(),
+                file,
+                line: source_line,
+            });
+        }
+    }
+}
+
+fn is_function_label(line: &str) -> bool {
+    !line.starts_with(|c: char| c.is_whitespace() || c == '.' || c == '#')
+        && line.trim_end().ends_with(':')
+}
+
+fn is_user_symbol(name: &str) -> bool {
+    let prefix = format!("{}::", WORKSPACE_PACKAGE_NAME);
+    name.starts_with(&prefix) || name.starts_with(&format!("<{}", prefix))
+}
+
+/// `.file 1 "dir" "name" md5 0x...` or `.file 1 "path"`.
+fn parse_file_directive(rest: &str) -> Option<(u32, String)> {
+    let rest = rest.trim_start();
+    let (number, rest) = rest.split_once(char::is_whitespace)?;
+    let number = number.parse().ok()?;
+    let quoted: Vec<&str> = rest.split('"').skip(1).step_by(2).collect();
+    let path = match quoted.as_slice() {
+        [path] => PathBuf::from(path),
+        [dir, name, ..] => Path::new(dir).join(name),
+        [] => return None,
+    };
+    Some((number, path.to_string_lossy().to_string()))
+}
+
+/// `.loc file line column ...`.
+fn parse_loc_directive(rest: &str) -> Option<(u32, u32)> {
+    let mut fields = rest.split_whitespace();
+    let file = fields.next()?.parse().ok()?;
+    let line = fields.next()?.parse().ok()?;
+    Some((file, line))
+}
+
+fn user_file(path: &str, src_dir: &Path) -> Option<String> {
+    Path::new(path)
+        .strip_prefix(src_dir)
+        .ok()
+        .map(|relative| relative.to_string_lossy().to_string())
+}
+
+/// Replaces every legacy-mangled (`_ZN...E`) symbol in `line`.
+fn demangle_line(line: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find("_ZN") {
+        result.push_str(&rest[..start]);
+        match demangle_symbol(&rest[start..]) {
+            Some((name, used)) => {
+                result.push_str(&name);
+                rest = &rest[start + used..];
+            }
+            None => {
+                result.push_str("_ZN");
+                rest = &rest[start + 3..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Demangles the symbol at the start of `text`, returning it with the number
+/// of bytes it took.
+fn demangle_symbol(text: &str) -> Option<(String, usize)> {
+    let mut pos = 3;
+    let bytes = text.as_bytes();
+    let mut segments = vec![];
+
+    while *bytes.get(pos)? != b'E' {
+        let digits = text[pos..].bytes().take_while(u8::is_ascii_digit).count();
+        if digits == 0 {
+            return None;
+        }
+        let len: usize = text[pos..pos + digits].parse().ok()?;
+        pos += digits;
+        let end = pos.checked_add(len)?;
+        segments.push(text.get(pos..end)?);
+        pos = end;
+    }
+    pos += 1;
+
+    // The trailing hash only tells instances apart.
+    if let Some(last) = segments.last()
+        && last.len() == 17
+        && last.starts_with('h')
+        && last[1..].bytes().all(|b| b.is_ascii_hexdigit())
+    {
+        segments.pop();
+    }
+    if segments.is_empty() {
+        return None;
+    }
+
+    let name = segments
+        .iter()
+        .map(|segment| decode_segment(segment))
+        .collect::<Vec<String>>()
+        .join("::");
+    Some((name, pos))
+}
+
+fn decode_segment(segment: &str) -> String {
+    // A leading `_` only escapes segments that start with `$`.
+    let segment = if segment.starts_with("_$") {
+        &segment[1..]
+    } else {
+        segment
+    };
+    let mut decoded = String::with_capacity(segment.len());
+    let mut rest = segment;
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("..") {
+            decoded.push_str("::");
+            rest = after;
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix('$')
+            && let Some(end) = after.find('$')
+        {
+            let escape = &after[..end];
+            let replacement = match escape {
+                "SP" => Some('@'),
+                "BP" => Some('*'),
+                "RF" => Some('&'),
+                "LT" => Some('<'),
+                "GT" => Some('>'),
+                "LP" => Some('('),
+                "RP" => Some(')'),
+                "C" => Some(','),
+                _ => escape
+                    .strip_prefix('u')
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .and_then(char::from_u32),
+            };
+            if let Some(c) = replacement {
+                decoded.push(c);
+                rest = &after[end + 1..];
+                continue;
+            }
+        }
+        let c = rest.chars().next().unwrap_or_default();
+        decoded.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn demangles_symbols() {
+        assert_eq!(
+            demangle_line("call _ZN4core3fmt5write17h0123456789abcdefE@PLT"),
+            "call core::fmt::write@PLT"
+        );
+    }
+
+    #[test]
+    fn leaves_oversized_lengths_alone() {
+        let line = "\"_ZN18446744073709551610abcE\"";
+        assert_eq!(demangle_line(line), line);
+    }
+}
@@ -0,0 +1,430 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tokio::process::Command;
+use tokio::sync::OnceCell;
+
+use crate::controllers::utils::{get_parsed_var_from_env, get_var_from_env};
+use crate::file::diagnostics::{Diagnostic, parse_rustc_diagnostic};
+use crate::file::{RunOutput, run_sandboxed_command};
+use crate::models::execution::ExecutionContext;
+use crate::sec::input::RunInput;
+use crate::sec::sandbox::{SandboxConfig, Termination, load_sandbox_config};
+
+// cargo, rustc and the interpreter together need far more than a user
+// binary does.
+const MIRI_MAX_PROCESSES: u64 = 256;
+const MIRI_MAX_OPEN_FILES: u64 = 1024;
+const MIRI_MAX_FILE_SIZE_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Miri's JSON diagnostics are verbose; keep enough stderr to hold them.
+const MIRI_MIN_OUTPUT_BYTES: usize = 512 * 1024;
+
+/// Variables the user may not pass, since cargo and rustc read them.
+const RESERVED_ENV_PREFIXES: &[&str] = &["CARGO", "RUST", "MIRI"];
+
+#[derive(Debug, Clone)]
+pub struct MiriConfig {
+    /// Nightly toolchain with the `miri` and `rust-src` components.
+    pub toolchain: String,
+    pub memory_bytes: u64,
+    pub cpu_seconds: u64,
+    pub wall_timeout_secs: u64,
+}
+
+impl MiriConfig {
+    pub fn from_env() -> Self {
+        Self {
+            toolchain: get_var_from_env("MIRI_TOOLCHAIN").unwrap_or_else(|_| "nightly".to_string()),
+            memory_bytes: get_parsed_var_from_env::<u64>("MIRI_MEMORY_MB", 2048) * 1024 * 1024,
+            cpu_seconds: get_parsed_var_from_env("MIRI_CPU_SECONDS", 30),
+            wall_timeout_secs: get_parsed_var_from_env("MIRI_TIMEOUT_SECONDS", 60),
+        }
+    }
+}
+
+pub fn load_miri_config() -> &'static MiriConfig {
+    static CONFIG: OnceLock<MiriConfig> = OnceLock::new();
+    CONFIG.get_or_init(MiriConfig::from_env)
+}
+
+#[derive(Debug, Clone)]
+pub struct MiriRuntime {
+    pub version: String,
+    /// The toolchain's `bin` directory, holding `cargo` and `cargo-miri`.
+    bin_dir: PathBuf,
+    /// Standard library built for Miri by `cargo miri setup`.
+    miri_sysroot: PathBuf,
+}
+
+/// Looks up the toolchain and prepares Miri's sysroot once, which takes a
+/// while the first time. `None` when Miri is not installed.
+pub async fn miri_runtime() -> Option<&'static MiriRuntime> {
+    static RUNTIME: OnceCell<Option<MiriRuntime>> = OnceCell::const_new();
+    RUNTIME
+        .get_or_init(|| async {
+            let config = load_miri_config();
+            let runtime = find_miri(&config.toolchain).await;
+            if runtime.is_none() {
+                eprintln!(
+                    "LOG: Miri indisponível no toolchain '{}'.",
+                    config.toolchain
+                );
+            }
+            runtime
+        })
+        .await
+        .as_ref()
+}
+
+async fn find_miri(toolchain: &str) -> Option<MiriRuntime> {
+    let output = Command::new("rustc")
+        .args(["--print", "sysroot"])
+        .env("RUSTUP_TOOLCHAIN", toolchain)
+        .env("RUSTUP_AUTO_INSTALL", "0")
+        .output()
+        .await
+        .ok()
+        .filter(|out| out.status.success())?;
+    let sysroot = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    let bin_dir = sysroot.join("bin");
+    if !bin_dir.join("cargo-miri").exists() {
+        return None;
+    }
+
+    let cargo_miri = |args: &[&str]| {
+        let mut command = Command::new(bin_dir.join("cargo"));
+        command
+            .arg("miri")
+            .args(args)
+            .env("PATH", toolchain_path(&bin_dir))
+            .env("RUSTUP_TOOLCHAIN", toolchain);
+        command
+    };
+
+    let version = cargo_miri(&["--version"])
+        .output()
+        .await
+        .ok()
+        .filter(|out| out.status.success())?;
+    let version = String::from_utf8_lossy(&version.stdout).trim().to_string();
+
+    println!("LOG: Preparando sysroot do Miri...");
+    let setup = cargo_miri(&["setup", "--print-sysroot"])
+        .output()
+        .await
+        .ok()?;
+    if !setup.status.success() {
+        eprintln!(
+            "ERRO: Falha ao preparar o sysroot do Miri: {}",
+            String::from_utf8_lossy(&setup.stderr)
+        );
+        return None;
+    }
+    let miri_sysroot = PathBuf::from(String::from_utf8_lossy(&setup.stdout).trim());
+
+    Some(MiriRuntime {
+        version,
+        bin_dir,
+        miri_sysroot,
+    })
+}
+
+fn toolchain_path(bin_dir: &Path) -> String {
+    format!("{}:/usr/local/bin:/usr/bin:/bin", bin_dir.display())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MiriErrorKind {
+    UndefinedBehavior,
+    MemoryLeak,
+    Unsupported,
+    Deadlock,
+    Abort,
+    ResourceExhaustion,
+    Other,
+}
+
+impl MiriErrorKind {
+    fn classify(message: &str) -> Self {
+        let message = message.to_lowercase();
+        [
+            ("undefined behavior", MiriErrorKind::UndefinedBehavior),
+            ("memory leaked", MiriErrorKind::MemoryLeak),
+            ("unsupported operation", MiriErrorKind::Unsupported),
+            ("deadlock", MiriErrorKind::Deadlock),
+            ("abnormal termination", MiriErrorKind::Abort),
+            ("resource exhaustion", MiriErrorKind::ResourceExhaustion),
+        ]
+        .into_iter()
+        .find(|(prefix, _)| message.starts_with(prefix))
+        .map_or(MiriErrorKind::Other, |(_, kind)| kind)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StackFrame {
+    pub function: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+}
+
+/// An error Miri stopped the program with. The innermost frame comes first in
+/// `backtrace`, which is empty when the error is in `main` itself.
+#[derive(Debug, Clone, Serialize)]
+pub struct MiriReport {
+    pub kind: MiriErrorKind,
+    pub message: String,
+    pub diagnostic: Diagnostic,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub backtrace: Vec<StackFrame>,
+}
+
+impl MiriReport {
+    fn new(diagnostic: Diagnostic) -> Self {
+        let backtrace = diagnostic
+            .children
+            .iter()
+            .find_map(|child| parse_backtrace(&child.message))
+            .unwrap_or_default();
+        Self {
+            kind: MiriErrorKind::classify(&diagnostic.message),
+            message: diagnostic.message.clone(),
+            diagnostic,
+            backtrace,
+        }
+    }
+}
+
+/// Reads the "stack backtrace:" note, where each frame is `N: function`
+/// followed by `at file:line:column: line:column`.
+fn parse_backtrace(note: &str) -> Option<Vec<StackFrame>> {
+    let mut lines = note.lines();
+    if !lines
+        .next()?
+        .trim_end()
+        .to_lowercase()
+        .ends_with("backtrace:")
+    {
+        return None;
+    }
+
+    let mut frames: Vec<StackFrame> = vec![];
+    for line in lines {
+        let line = line.trim();
+        if let Some(location) = line.strip_prefix("at ") {
+            if let Some(frame) = frames.last_mut() {
+                let start = location.split(": ").next().unwrap_or(location);
+                let mut parts = start.rsplitn(3, ':');
+                let column = parts.next().and_then(|c| c.parse().ok());
+                let line = parts.next().and_then(|l| l.parse().ok());
+                frame.file_name = parts.next().map(String::from);
+                frame.line = line;
+                frame.column = column;
+            }
+        } else if let Some((index, function)) = line.split_once(": ")
+            && index.chars().all(|c| c.is_ascii_digit())
+        {
+            frames.push(StackFrame {
+                function: function.to_string(),
+                file_name: None,
+                line: None,
+                column: None,
+            });
+        }
+    }
+    Some(frames)
+}
+
+pub struct MiriRun {
+    /// `stderr` holds the program's own output followed by Miri's messages.
+    pub output: RunOutput,
+    pub reports: Vec<MiriReport>,
+}
+
+/// Interprets the workspace binary with `cargo miri run`, inside the sandbox
+/// with Miri's own limits. The workspace must already have a `Cargo.lock`
+/// and its dependencies downloaded, since the sandbox has no network.
+pub async fn run_miri(
+    runtime: &MiriRuntime,
+    workspace: &Path,
+    input: &RunInput,
+    ctx: &ExecutionContext,
+) -> MiriRun {
+    let workspace = match tokio::fs::canonicalize(workspace).await {
+        Ok(path) => path,
+        Err(e) => {
+            return MiriRun {
+                output: RunOutput::failure(
+                    format!("Erro ao preparar execução: {}", e),
+                    Termination::SandboxFailure {
+                        detail: e.to_string(),
+                    },
+                ),
+                reports: vec![],
+            };
+        }
+    };
+
+    let config = load_miri_config();
+    let base = load_sandbox_config();
+    let sandbox = SandboxConfig {
+        memory_bytes: config.memory_bytes,
+        cpu_seconds: config.cpu_seconds,
+        wall_timeout_secs: config.wall_timeout_secs,
+        max_processes: MIRI_MAX_PROCESSES,
+        max_open_files: MIRI_MAX_OPEN_FILES,
+        max_file_size_bytes: MIRI_MAX_FILE_SIZE_BYTES,
+        max_output_bytes: base.max_output_bytes.max(MIRI_MIN_OUTPUT_BYTES),
+        allow_socketpair: true,
+        ..base.clone()
+    }
+    .exposing([
+        workspace.as_path(),
+        runtime.bin_dir.parent().unwrap_or(&runtime.bin_dir),
+        &runtime.miri_sysroot,
+    ]);
+
+    let user_input = RunInput {
+        env: input
+            .env
+            .iter()
+            .filter(|(key, _)| {
+                !RESERVED_ENV_PREFIXES.iter().any(|p| key.starts_with(p))
+                    && !key.contains(char::is_whitespace)
+            })
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect(),
+        ..input.clone()
+    };
+
+    let manifest = workspace.join("Cargo.toml").to_string_lossy().to_string();
+    let env = [
+        ("PATH", toolchain_path(&runtime.bin_dir)),
+        // The server's own CARGO_HOME holds its credentials and is hidden;
+        // dependencies come from the vendored registry.
+        (
+            "CARGO_HOME",
+            workspace
+                .join("scratch/cargo-home")
+                .to_string_lossy()
+                .to_string(),
+        ),
+        (
+            "CARGO_TARGET_DIR",
+            workspace
+                .join("scratch/target")
+                .to_string_lossy()
+                .to_string(),
+        ),
+        (
+            "MIRI_SYSROOT",
+            runtime.miri_sysroot.to_string_lossy().to_string(),
+        ),
+        // Miri's own isolation would also cut off stdin; the sandbox already
+        // confines the interpreter as it does native programs.
+        (
+            "MIRIFLAGS",
+            "--error-format=json -Zmiri-disable-isolation".to_string(),
+        ),
+        // Warnings were already reported by the check before the run.
+        ("RUSTFLAGS", "-Awarnings".to_string()),
+    ];
+
+    // Called the way cargo calls subcommands, so no rustup proxy from
+    // CARGO_HOME is picked up.
+    let cargo_miri = runtime
+        .bin_dir
+        .join("cargo-miri")
+        .to_string_lossy()
+        .to_string();
+    println!("LOG: Executando {} em {:?}", runtime.version, workspace);
+    let mut output = run_sandboxed_command(
+        &sandbox,
+        &cargo_miri,
+        &[
+            "miri",
+            "run",
+            "-q",
+            "--frozen",
+            "--manifest-path",
+            &manifest,
+            "--",
+        ],
+        &env,
+        &workspace,
+        &user_input,
+        ctx,
+    )
+    .await;
+
+    // Miri reports as the interpreted program stops, after everything it
+    // printed, and fails the run. Earlier lines are the program's own, even
+    // those that look like diagnostics.
+    let lines: Vec<&str> = output.stderr.lines().collect();
+    let failed = matches!(output.termination, Termination::Exited { code } if code != 0);
+    let start = if failed {
+        report_start(&lines)
+    } else {
+        lines.len()
+    };
+    let (program_lines, report_lines) = lines.split_at(start);
+
+    let mut program_stderr = String::new();
+    for line in program_lines {
+        program_stderr.push_str(line);
+        program_stderr.push('\n');
+    }
+    let mut reports = vec![];
+    for (text, diagnostic) in report_lines
+        .iter()
+        .filter_map(|line| parse_rustc_diagnostic(line))
+    {
+        program_stderr.push_str(&text);
+        if let Some(diagnostic) = diagnostic.filter(|d| d.level == "error") {
+            reports.push(MiriReport::new(diagnostic));
+        }
+    }
+    output.stderr = program_stderr;
+
+    MiriRun { output, reports }
+}
+
+// Miri closes its report with rustc's "aborting due to N previous errors",
+// so the report is that many errors back from the last line, with the notes
+// in between. `lines.len()` when stderr does not end in a report.
+fn report_start(lines: &[&str]) -> usize {
+    let Some((last, rest)) = lines.split_last() else {
+        return lines.len();
+    };
+    let Some(mut errors) = parse_rustc_diagnostic(last).and_then(|(text, _)| aborted_errors(&text))
+    else {
+        return lines.len();
+    };
+
+    for (index, line) in rest.iter().enumerate().rev() {
+        let Some((_, diagnostic)) = parse_rustc_diagnostic(line) else {
+            break;
+        };
+        if diagnostic.is_some_and(|d| d.level == "error") {
+            errors -= 1;
+            if errors == 0 {
+                return index;
+            }
+        }
+    }
+    lines.len()
+}
+
+fn aborted_errors(rendered: &str) -> Option<usize> {
+    let count = rendered.trim().strip_prefix("error: aborting due to ")?;
+    match count.split_whitespace().next()? {
+        "previous" => Some(1),
+        n => n.parse().ok().filter(|&n| n > 0),
+    }
+}
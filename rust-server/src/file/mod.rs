@@ -1,12 +1,57 @@
-use chrono::Local;
-use std::fs::{self, OpenOptions};
-use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::Stdio;
-use tokio::process::Command;
-use tokio::time::{Duration, timeout};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{ChildStdin, Command};
+use tokio::sync::mpsc;
+use tokio::time::{Duration, Instant, timeout};
+use uuid::Uuid;
 
-pub async fn run_safe_bin(caminho_binario: &str) -> (String, String) {
+pub mod bench;
+pub mod cache;
+pub mod diagnostics;
+pub mod explorer;
+pub mod miri;
+pub mod project;
+pub mod python;
+pub mod stats;
+pub mod testing;
+pub mod toolchain;
+pub mod wasm;
+pub mod workspace;
+
+use crate::controllers::utils::extract_dependencies;
+use crate::file::stats::{ExecutionStats, PeakRssSampler, wait_for_cpu_time};
+use crate::file::toolchain::BuildSettings;
+use crate::file::workspace::mark_workspace_used;
+use crate::models::execution::{ExecutionContext, ExecutionEvent, OutputStream};
+use crate::sec::dependencies::{CrateDependency, load_dependency_allowlist, merge_dependencies};
+use crate::sec::input::RunInput;
+use crate::sec::sandbox::{SandboxConfig, Termination, apply_sandbox, load_sandbox_config};
+
+pub struct RunOutput {
+    pub stdout: String,
+    pub stderr: String,
+    pub termination: Termination,
+    pub stats: ExecutionStats,
+}
+
+impl RunOutput {
+    pub(crate) fn failure(stderr: String, termination: Termination) -> Self {
+        Self {
+            stdout: "".into(),
+            stderr,
+            termination,
+            stats: ExecutionStats::default(),
+        }
+    }
+}
+
+pub async fn run_safe_bin(
+    caminho_binario: &str,
+    workspace: &Path,
+    input: &RunInput,
+    ctx: &ExecutionContext,
+) -> RunOutput {
     println!(
         "LOG: Tentando iniciar processo no caminho ABSOLUTO: {}",
         caminho_binario
@@ -18,71 +63,524 @@ pub async fn run_safe_bin(caminho_binario: &str) -> (String, String) {
             "ERRO CRÍTICO: O arquivo binário NÃO EXISTE no disco: {}",
             caminho_binario
         );
-        return ("".into(), format!("Erro interno: Binário não encontrado."));
+        return RunOutput::failure(
+            "Erro interno: Binário não encontrado.".into(),
+            Termination::SandboxFailure {
+                detail: "binário não encontrado".into(),
+            },
+        );
+    }
+
+    run_safe_command(caminho_binario, &[], &[], workspace, input, ctx).await
+}
+
+/// Runs `program` with `leading_args` before the user's arguments, inside the
+/// sandbox and under the same limits as compiled binaries. `env` is applied
+/// after the user's variables so it cannot be overridden.
+pub async fn run_safe_command(
+    program: &str,
+    leading_args: &[&str],
+    env: &[(&str, String)],
+    workspace: &Path,
+    input: &RunInput,
+    ctx: &ExecutionContext,
+) -> RunOutput {
+    run_sandboxed_command(
+        load_sandbox_config(),
+        program,
+        leading_args,
+        env,
+        workspace,
+        input,
+        ctx,
+    )
+    .await
+}
+
+/// Same as `run_safe_command`, under limits other than the configured ones.
+pub async fn run_sandboxed_command(
+    config: &SandboxConfig,
+    program: &str,
+    leading_args: &[&str],
+    env: &[(&str, String)],
+    workspace: &Path,
+    input: &RunInput,
+    ctx: &ExecutionContext,
+) -> RunOutput {
+    let scratch_dir = workspace.join("scratch");
+    let _ = tokio::fs::remove_dir_all(&scratch_dir).await;
+    if let Err(e) = tokio::fs::create_dir_all(&scratch_dir).await {
+        eprintln!(
+            "ERRO: Falha ao criar diretório temporário {:?}: {}",
+            scratch_dir, e
+        );
+        return RunOutput::failure(
+            format!("Erro ao preparar execução: {}", e),
+            Termination::SandboxFailure {
+                detail: e.to_string(),
+            },
+        );
     }
 
-    let child = match Command::new(caminho_binario)
+    let mut command = Command::new(program);
+    command
+        .args(leading_args)
+        .args(&input.args)
+        .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
-        .spawn()
-    {
+        .kill_on_drop(true);
+
+    if let Err(e) = apply_sandbox(&mut command, config, &scratch_dir) {
+        eprintln!("ERRO ao preparar sandbox: {}", e);
+        return RunOutput::failure(
+            format!("Erro ao iniciar execução: {}", e),
+            Termination::SandboxFailure {
+                detail: e.to_string(),
+            },
+        );
+    }
+    command.envs(&input.env);
+    command.envs(env.iter().cloned());
+
+    let mut child = match command.spawn() {
         Ok(c) => c,
         Err(e) => {
             eprintln!("ERRO ao spawnar processo: {}", e);
-            return ("".into(), format!("Erro ao iniciar execução: {}", e));
+            return RunOutput::failure(
+                format!("Erro ao iniciar execução: {}", e),
+                Termination::SandboxFailure {
+                    detail: e.to_string(),
+                },
+            );
         }
     };
 
     let pid = child.id().expect("Falha ao obter PID");
     println!("LOG: Processo iniciado com PID: {}", pid);
+    let started = Instant::now();
+    let rss_sampler = PeakRssSampler::start(pid);
+
+    let stdin_writer = tokio::spawn(write_stdin(
+        child.stdin.take(),
+        input.stdin.clone(),
+        ctx.take_stdin(),
+    ));
+    let stdout_reader = tokio::spawn(read_stream(
+        child.stdout.take(),
+        OutputStream::Stdout,
+        config.max_output_bytes,
+        ctx.clone(),
+    ));
+    let stderr_reader = tokio::spawn(read_stream(
+        child.stderr.take(),
+        OutputStream::Stderr,
+        config.max_output_bytes,
+        ctx.clone(),
+    ));
+
+    let exited = async {
+        let cpu_time = wait_for_cpu_time(pid).await;
+        child.wait().await.map(|status| (status, cpu_time))
+    };
+
+    let outcome = tokio::select! {
+        result = timeout(Duration::from_secs(config.wall_timeout_secs), exited) => match result {
+            Ok(Ok(exit)) => Ok(exit),
+            Ok(Err(e)) => Err(RunOutput::failure(
+                format!("Erro de I/O na execução: {}", e),
+                Termination::SandboxFailure {
+                    detail: e.to_string(),
+                },
+            )),
+            Err(_) => {
+                eprintln!("TIMEOUT: Matando processo {}", pid);
+                Err(RunOutput::failure(
+                    "Erro: Tempo limite de execução excedido.".into(),
+                    Termination::Timeout,
+                ))
+            }
+        },
+        _ = ctx.cancel.cancelled() => {
+            eprintln!("LOG: Execução cancelada pelo cliente, matando processo {}", pid);
+            Err(RunOutput::failure(
+                "".into(),
+                Termination::Cancelled,
+            ))
+        }
+    };
+
+    let wall_time = started.elapsed();
+    if outcome.is_err() {
+        let _ = child.kill().await;
+    }
+    stdin_writer.abort();
+
+    let stdout = collect_stream(stdout_reader).await;
+    let stderr = collect_stream(stderr_reader).await;
+    let stats = ExecutionStats {
+        wall_time_ms: Some(wall_time.as_millis() as u64),
+        peak_rss_kb: rss_sampler.finish().await,
+        stdout_truncated: stdout.truncated,
+        stderr_truncated: stderr.truncated,
+        ..Default::default()
+    };
+    let stdout = String::from_utf8_lossy(&stdout.bytes).to_string();
+    let stderr = String::from_utf8_lossy(&stderr.bytes).to_string();
+
+    match outcome {
+        Ok((status, cpu_time)) => {
+            let cpu_limit_reached = cpu_time.is_some_and(|t| t.as_secs() >= config.cpu_seconds);
+            let termination = Termination::from_status(&status, &stderr, cpu_limit_reached);
+
+            if termination.is_violation() {
+                eprintln!("LOG: Execução interrompida pelo sandbox: {:?}", termination);
+            } else {
+                println!("LOG: Execução finalizada com sucesso.");
+            }
+
+            RunOutput {
+                stdout,
+                stderr,
+                termination,
+                stats: ExecutionStats {
+                    exit_code: status.code(),
+                    signal: exit_signal(&status),
+                    cpu_time_ms: cpu_time.map(|d| d.as_millis() as u64),
+                    ..stats
+                },
+            }
+        }
+        Err(mut failure) => {
+            // Whatever the program printed before being stopped is still useful.
+            failure.stdout = stdout;
+            if failure.stderr.is_empty() {
+                failure.stderr = stderr;
+            }
+            failure.stats = stats;
+            failure
+        }
+    }
+}
+
+fn exit_signal(status: &std::process::ExitStatus) -> Option<i32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        status.signal()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = status;
+        None
+    }
+}
+
+async fn write_stdin(
+    pipe: Option<ChildStdin>,
+    initial: Option<String>,
+    interactive: Option<mpsc::UnboundedReceiver<String>>,
+) {
+    let Some(mut pipe) = pipe else {
+        return;
+    };
+
+    if let Some(text) = initial
+        && pipe.write_all(text.as_bytes()).await.is_err()
+    {
+        return;
+    }
+
+    let Some(mut interactive) = interactive else {
+        return;
+    };
+
+    // The sender caps the total, since a program that never reads would
+    // leave it queued here.
+    while let Some(line) = interactive.recv().await {
+        if pipe.write_all(line.as_bytes()).await.is_err() || pipe.flush().await.is_err() {
+            return;
+        }
+    }
+}
+
+#[derive(Default)]
+struct CapturedStream {
+    bytes: Vec<u8>,
+    truncated: bool,
+}
+
+/// Keeps the first `limit` bytes of the stream and keeps draining the rest,
+/// so a chatty program is not blocked on a full pipe.
+async fn read_stream<R: AsyncRead + Unpin>(
+    stream: Option<R>,
+    kind: OutputStream,
+    limit: usize,
+    ctx: ExecutionContext,
+) -> CapturedStream {
+    let mut captured = CapturedStream::default();
+    let Some(mut stream) = stream else {
+        return captured;
+    };
+
+    let mut buf = [0u8; 4096];
+    let mut pending: Vec<u8> = vec![];
+
+    loop {
+        let n = match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => n,
+        };
+        let kept = n.min(limit - captured.bytes.len());
+        captured.bytes.extend_from_slice(&buf[..kept]);
+
+        if kept < n && !captured.truncated {
+            captured.truncated = true;
+            ctx.emit(ExecutionEvent::error(format!(
+                "A saída excedeu o limite de {} bytes e foi truncada.",
+                limit
+            )));
+        }
 
-    match timeout(Duration::from_secs(5), child.wait_with_output()).await {
-        Ok(Ok(output)) => {
-            println!("LOG: Execução finalizada com sucesso.");
-            (
-                String::from_utf8_lossy(&output.stdout).to_string(),
-                String::from_utf8_lossy(&output.stderr).to_string(),
-            )
+        if ctx.is_streaming() && kept > 0 {
+            pending.extend_from_slice(&buf[..kept]);
+            let chunk = take_utf8_prefix(&mut pending);
+            if !chunk.is_empty() {
+                ctx.emit(ExecutionEvent::output(kind, chunk));
+            }
         }
-        Ok(Err(e)) => {
-            eprintln!("ERRO de I/O durante execução: {}", e);
-            ("".into(), format!("Erro de I/O na execução: {}", e))
+    }
+
+    if !pending.is_empty() {
+        ctx.emit(ExecutionEvent::output(
+            kind,
+            String::from_utf8_lossy(&pending).to_string(),
+        ));
+    }
+
+    captured
+}
+
+/// Splits off the longest valid UTF-8 prefix, keeping an incomplete trailing
+/// character buffered for the next read.
+fn take_utf8_prefix(pending: &mut Vec<u8>) -> String {
+    match std::str::from_utf8(pending) {
+        Ok(s) => {
+            let chunk = s.to_string();
+            pending.clear();
+            chunk
+        }
+        Err(e) if e.error_len().is_none() => {
+            let rest = pending.split_off(e.valid_up_to());
+            let chunk = String::from_utf8_lossy(pending).to_string();
+            *pending = rest;
+            chunk
         }
         Err(_) => {
-            eprintln!("TIMEOUT: Matando processo {}", pid);
-            #[cfg(windows)]
-            let _ = std::process::Command::new("taskkill")
-                .args(["/F", "/PID", &pid.to_string()])
-                .output();
+            let chunk = String::from_utf8_lossy(pending).to_string();
+            pending.clear();
+            chunk
+        }
+    }
+}
 
-            #[cfg(not(windows))]
-            let _ = std::process::Command::new("kill")
-                .args(["-9", &pid.to_string()])
-                .output();
+async fn collect_stream(reader: tokio::task::JoinHandle<CapturedStream>) -> CapturedStream {
+    match timeout(Duration::from_secs(1), reader).await {
+        Ok(Ok(captured)) => captured,
+        _ => CapturedStream::default(),
+    }
+}
 
-            ("".into(), "Erro: Tempo limite de execução excedido.".into())
+pub struct CargoOutput {
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Runs cargo in the workspace, reporting each finished compilation unit as
+/// progress. Returns `Ok(None)` when the run is cancelled.
+pub async fn run_cargo(
+    project_path: &Path,
+    args: &[&str],
+    settings: &BuildSettings,
+    ctx: &ExecutionContext,
+) -> std::io::Result<Option<CargoOutput>> {
+    run_cargo_with_env(project_path, args, &[], settings, ctx).await
+}
+
+/// Same as `run_cargo`, with extra variables for cargo and the compiler.
+pub async fn run_cargo_with_env(
+    project_path: &Path,
+    args: &[&str],
+    env: &[(String, String)],
+    settings: &BuildSettings,
+    ctx: &ExecutionContext,
+) -> std::io::Result<Option<CargoOutput>> {
+    let mut child = Command::new("cargo")
+        .current_dir(project_path)
+        .args(args)
+        .args(settings.cargo_args())
+        .envs(settings.cargo_env())
+        .envs(env.iter().cloned())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let stdout = child.stdout.take();
+    let progress_ctx = ctx.clone();
+    let stdout_reader = tokio::spawn(async move {
+        let mut collected = String::new();
+        let Some(stdout) = stdout else {
+            return collected;
+        };
+
+        let mut lines = BufReader::new(stdout).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if let Some(message) = cargo_progress_message(&line) {
+                progress_ctx.emit(ExecutionEvent::cargo_progress(message));
+            }
+            collected.push_str(&line);
+            collected.push('\n');
         }
+        collected
+    });
+
+    let stderr = child.stderr.take();
+    let stderr_reader = tokio::spawn(async move {
+        let mut collected = vec![];
+        if let Some(mut stderr) = stderr {
+            let _ = stderr.read_to_end(&mut collected).await;
+        }
+        collected
+    });
+
+    let status = tokio::select! {
+        status = child.wait() => status?,
+        _ = ctx.cancel.cancelled() => {
+            let _ = child.kill().await;
+            stdout_reader.abort();
+            stderr_reader.abort();
+            return Ok(None);
+        }
+    };
+
+    let stdout = stdout_reader.await.unwrap_or_default();
+    let stderr = stderr_reader.await.unwrap_or_default();
+
+    Ok(Some(CargoOutput {
+        success: status.success(),
+        stdout,
+        stderr: String::from_utf8_lossy(&stderr).to_string(),
+    }))
+}
+
+fn cargo_progress_message(line: &str) -> Option<String> {
+    let val = serde_json::from_str::<serde_json::Value>(line).ok()?;
+    match val.get("reason")?.as_str()? {
+        "compiler-artifact" => {
+            let target = val.get("target")?.get("name")?.as_str()?;
+            let fresh = val.get("fresh").and_then(|f| f.as_bool()).unwrap_or(false);
+            Some(if fresh {
+                format!("Reutilizado {}", target)
+            } else {
+                format!("Compilado {}", target)
+            })
+        }
+        "build-script-executed" => {
+            let package = val.get("package_id")?.as_str()?;
+            Some(format!("Build script executado: {}", package))
+        }
+        "build-finished" => {
+            let success = val.get("success")?.as_bool()?;
+            Some(if success {
+                "Compilação concluída".to_string()
+            } else {
+                "Compilação falhou".to_string()
+            })
+        }
+        _ => None,
     }
 }
 
-pub async fn setup_user_env(ip_safe: &str) -> PathBuf {
-    let user_dir = format!("files/{}", ip_safe);
+pub const WORKSPACE_PACKAGE_NAME: &str = "app";
+
+pub async fn setup_user_env(owner_key: &str, notebook_id: Option<Uuid>) -> PathBuf {
+    let notebook_dir = match notebook_id {
+        Some(id) => format!("nb_{}", id.simple()),
+        None => "default".to_string(),
+    };
+    init_workspace(format!("files/{}/{}", owner_key, notebook_dir)).await
+}
+
+/// Workspace for whole-notebook builds, kept apart from the per-block one so
+/// its `src/` can be rewritten from the notebook on every run.
+pub async fn setup_project_env(owner_key: &str, notebook_id: Option<Uuid>) -> PathBuf {
+    let project_dir = match notebook_id {
+        Some(id) => format!("proj_{}", id.simple()),
+        None => "proj_default".to_string(),
+    };
+    init_workspace(format!("files/{}/{}", owner_key, project_dir)).await
+}
+
+/// Miri runs accept `unsafe`, so their sources never share a workspace with
+/// native builds: one for single blocks and one for whole notebooks.
+pub async fn setup_miri_env(
+    owner_key: &str,
+    notebook_id: Option<Uuid>,
+    whole_notebook: bool,
+) -> PathBuf {
+    let kind = if whole_notebook { "miri_proj" } else { "miri" };
+    let miri_dir = match notebook_id {
+        Some(id) => format!("{}_{}", kind, id.simple()),
+        None => format!("{}_default", kind),
+    };
+    init_workspace(format!("files/{}/{}", owner_key, miri_dir)).await
+}
+
+/// Holds the build directory of the notebook's kernel, which compiles every
+/// block it is given.
+pub async fn setup_kernel_env(owner_key: &str, notebook_id: Option<Uuid>) -> PathBuf {
+    let kernel_dir = match notebook_id {
+        Some(id) => format!("kernel_{}", id.simple()),
+        None => "kernel_default".to_string(),
+    };
+    init_workspace(format!("files/{}/{}", owner_key, kernel_dir)).await
+}
+
+/// Benchmarks build an instrumented copy of `project_path` in a workspace
+/// next to it, leaving its own `main.rs` untouched.
+pub async fn setup_bench_env(project_path: &Path) -> PathBuf {
+    let name = project_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    init_workspace(
+        project_path
+            .with_file_name(format!("bench_{}", name))
+            .to_string_lossy()
+            .to_string(),
+    )
+    .await
+}
+
+async fn init_workspace(user_dir: String) -> PathBuf {
     let src_dir = format!("{}/src", user_dir);
 
     if let Err(e) = tokio::fs::create_dir_all(&src_dir).await {
         eprintln!("ERRO: Falha ao criar diretórios {}: {}", src_dir, e);
     }
+    mark_workspace_used(Path::new(&user_dir)).await;
 
     if !Path::new(&format!("{}/Cargo.toml", user_dir)).exists() {
         eprintln!("LOG: Iniciando novo projeto Cargo em {}", user_dir);
 
-        let package_name = format!("app_{}", ip_safe);
-
         let output = Command::new("cargo")
             .arg("init")
             .arg("--bin")
             .arg("--name")
-            .arg(&package_name)
+            .arg(WORKSPACE_PACKAGE_NAME)
             .arg(&user_dir)
             .output()
             .await;
@@ -100,45 +598,95 @@ pub async fn setup_user_env(ip_safe: &str) -> PathBuf {
         }
     }
 
+    if let Some(vendor_dir) = &load_dependency_allowlist().vendor_dir {
+        let config_path = format!("{}/.cargo/config.toml", user_dir);
+        if !Path::new(&config_path).exists() {
+            let config = format!(
+                "[source.crates-io]\n\
+                 replace-with = \"vendored-sources\"\n\n\
+                 [source.vendored-sources]\n\
+                 directory = \"{}\"\n\n\
+                 [net]\n\
+                 offline = true\n",
+                vendor_dir
+            );
+            let _ = tokio::fs::create_dir_all(format!("{}/.cargo", user_dir)).await;
+            if let Err(e) = tokio::fs::write(&config_path, config).await {
+                eprintln!(
+                    "ERRO: Falha ao configurar registro local em {}: {}",
+                    user_dir, e
+                );
+            }
+        }
+    }
+
     PathBuf::from(user_dir)
 }
 
-pub async fn register_log(
-    codigo: &str,
-    safe_ip: &str,
-    real_ip: &str,
-    user_agent: &str,
-) -> std::io::Result<()> {
-    let log_dir = "logs";
+pub async fn collect_workspace_dependencies(src_path: &Path) -> Vec<CrateDependency> {
+    let allowlist = load_dependency_allowlist();
+    let mut deps = vec![];
 
-    if !Path::new(log_dir).exists() {
-        fs::create_dir(log_dir)?;
-    }
+    let mut entries = match tokio::fs::read_dir(src_path).await {
+        Ok(e) => e,
+        Err(_) => return deps,
+    };
 
-    if !Path::new(&format!("{}/{}", log_dir, safe_ip)).exists() {
-        fs::create_dir(format!("{}/{}", log_dir, safe_ip))?;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != "rs") {
+            continue;
+        }
+
+        let code = match tokio::fs::read_to_string(&path).await {
+            Ok(c) => c,
+            Err(_) => continue,
+        };
+
+        for annotation in extract_dependencies(&code) {
+            match allowlist.resolve(&annotation) {
+                Ok(dep) => deps.push(dep),
+                Err(e) => eprintln!("LOG: Dependência ignorada em {:?}: {}", path, e),
+            }
+        }
     }
 
-    let timestamp = Local::now().format("%Y-%m-%d_%H-%M-%S");
-    let file_path = format!("{}/{}/{}.log", log_dir, safe_ip, timestamp);
+    merge_dependencies(deps)
+}
 
-    let log_content = format!(
-        "--- REQUISIÇÃO EM {} ---\n\
-         IP: {}\n\
-         USER-AGENT: {}\n\
-         ---------------------------\n\
-         CÓDIGO RECEBIDO:\n\n\
-         {}\n",
-        timestamp, real_ip, user_agent, codigo
-    );
+pub async fn write_manifest_dependencies(
+    project_path: &Path,
+    deps: &[CrateDependency],
+) -> std::io::Result<()> {
+    let manifest_path = project_path.join("Cargo.toml");
+    let manifest = tokio::fs::read_to_string(&manifest_path).await?;
 
-    let mut file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .append(true)
-        .open(file_path)?;
+    let package_section = match manifest.find("[dependencies]") {
+        Some(index) => &manifest[..index],
+        None => &manifest,
+    };
 
-    file.write_all(log_content.as_bytes())?;
+    let mut content = package_section.trim_end().to_string();
+    content.push_str("\n\n[dependencies]\n");
+    for dep in deps {
+        if dep.features.is_empty() {
+            content.push_str(&format!("{} = \"{}\"\n", dep.name, dep.version));
+        } else {
+            let features = dep
+                .features
+                .iter()
+                .map(|f| format!("\"{}\"", f))
+                .collect::<Vec<String>>()
+                .join(", ");
+            content.push_str(&format!(
+                "{} = {{ version = \"{}\", features = [{}] }}\n",
+                dep.name, dep.version, features
+            ));
+        }
+    }
 
+    if content != manifest {
+        tokio::fs::write(&manifest_path, content).await?;
+    }
     Ok(())
 }
@@ -0,0 +1,201 @@
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::controllers::utils::extract_module_name;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProjectBlock {
+    #[serde(default)]
+    pub id: Option<Uuid>,
+    pub code: String,
+}
+
+/// The crate built from a notebook: `main.rs` from the entry block and one
+/// file per `//#[mod=name]` block.
+#[derive(Debug)]
+pub struct ProjectSource {
+    pub main: String,
+    pub modules: BTreeMap<String, String>,
+}
+
+/// Picks the entry block and the module blocks. Without `entry`, the single
+/// block lacking a module annotation is used, or the only one of those that
+/// defines `fn main`.
+pub fn assemble_project(
+    blocks: &[ProjectBlock],
+    entry: Option<Uuid>,
+) -> Result<ProjectSource, String> {
+    let mut modules = BTreeMap::new();
+    let mut candidates = vec![];
+
+    for block in blocks {
+        match extract_module_name(&block.code) {
+            Some(name) => {
+                if name == "main" {
+                    return Err("O nome de módulo 'main' é reservado.".to_string());
+                }
+                if modules.insert(name.clone(), block.code.clone()).is_some() {
+                    return Err(format!("O módulo '{}' foi definido mais de uma vez.", name));
+                }
+            }
+            None => candidates.push(block),
+        }
+    }
+
+    let entry_block = match entry {
+        Some(id) => candidates
+            .iter()
+            .find(|b| b.id == Some(id))
+            .copied()
+            .ok_or_else(|| {
+                format!(
+                    "O bloco de entrada {} não existe ou está marcado como módulo.",
+                    id
+                )
+            })?,
+        None => match candidates.as_slice() {
+            [] => return Err("Nenhum bloco de entrada (sem //#[mod=...]) encontrado.".into()),
+            [only] => *only,
+            many => {
+                let with_main: Vec<&&ProjectBlock> =
+                    many.iter().filter(|b| defines_main(&b.code)).collect();
+                match with_main.as_slice() {
+                    [only] => **only,
+                    _ => {
+                        return Err("Mais de um bloco pode ser a entrada do projeto; \
+                                    informe entry_block_id."
+                            .into());
+                    }
+                }
+            }
+        },
+    };
+
+    Ok(ProjectSource {
+        main: declare_modules(&entry_block.code, &modules),
+        modules,
+    })
+}
+
+fn defines_main(code: &str) -> bool {
+    match syn::parse_file(code) {
+        Ok(file) => file
+            .items
+            .iter()
+            .any(|item| matches!(item, syn::Item::Fn(f) if f.sig.ident == "main")),
+        Err(_) => code.contains("fn main"),
+    }
+}
+
+// Missing `mod name;` items go at the end so compiler line numbers still
+// match the block the user wrote.
+pub fn declare_modules(main: &str, modules: &BTreeMap<String, String>) -> String {
+    let declared: HashSet<String> = match syn::parse_file(main) {
+        Ok(file) => file
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                syn::Item::Mod(m) => Some(m.ident.to_string()),
+                _ => None,
+            })
+            .collect(),
+        Err(_) => return main.to_string(),
+    };
+
+    let mut content = main.to_string();
+    for name in modules.keys().filter(|name| !declared.contains(*name)) {
+        if !content.ends_with('\n') {
+            content.push('\n');
+        }
+        content.push_str(&format!("mod {};\n", name));
+    }
+    content
+}
+
+/// Makes `src/` hold exactly the project's files, so nothing left over from
+/// earlier runs ends up in the build.
+pub async fn write_project_sources(
+    project_path: &Path,
+    source: &ProjectSource,
+) -> std::io::Result<()> {
+    let src_path = project_path.join("src");
+    let mut expected: HashSet<String> = source
+        .modules
+        .keys()
+        .map(|name| format!("{}.rs", name))
+        .collect();
+    expected.insert("main.rs".to_string());
+
+    let mut entries = tokio::fs::read_dir(&src_path).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if entry.file_type().await?.is_dir() {
+            tokio::fs::remove_dir_all(entry.path()).await?;
+        } else if !expected.contains(&name) {
+            tokio::fs::remove_file(entry.path()).await?;
+        }
+    }
+
+    write_if_changed(&src_path.join("main.rs"), &source.main).await?;
+    for (name, code) in &source.modules {
+        write_if_changed(&src_path.join(format!("{}.rs", name)), code).await?;
+    }
+    Ok(())
+}
+
+/// Reads back the crate at `project_path`, to build a copy of it elsewhere.
+pub async fn read_project_sources(project_path: &Path) -> std::io::Result<ProjectSource> {
+    let src_path = project_path.join("src");
+    let main = tokio::fs::read_to_string(src_path.join("main.rs")).await?;
+
+    let mut modules = BTreeMap::new();
+    let mut entries = tokio::fs::read_dir(&src_path).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let Some(name) = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_suffix(".rs"))
+        else {
+            continue;
+        };
+        if name != "main" && entry.file_type().await?.is_file() {
+            modules.insert(name.to_string(), tokio::fs::read_to_string(&path).await?);
+        }
+    }
+
+    Ok(ProjectSource { main, modules })
+}
+
+/// Writes the blocks carrying a `//#[mod=name]` annotation to `src/`, leaving
+/// `main.rs` and any other file as they are. Returns whether a file changed.
+pub async fn write_module_sources(
+    project_path: &Path,
+    blocks: &[ProjectBlock],
+) -> std::io::Result<bool> {
+    let src_path = project_path.join("src");
+    let mut changed = false;
+    for block in blocks {
+        let Some(name) = extract_module_name(&block.code) else {
+            continue;
+        };
+        if name.is_empty() || name == "main" {
+            continue;
+        }
+        changed |= write_if_changed(&src_path.join(format!("{}.rs", name)), &block.code).await?;
+    }
+    Ok(changed)
+}
+
+// Leaves unchanged files alone so cargo's mtime fingerprints stay valid.
+async fn write_if_changed(path: &Path, content: &str) -> std::io::Result<bool> {
+    if let Ok(current) = tokio::fs::read_to_string(path).await
+        && current == content
+    {
+        return Ok(false);
+    }
+    tokio::fs::write(path, content).await?;
+    Ok(true)
+}
@@ -0,0 +1,225 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tokio::process::Command;
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+use crate::controllers::utils::get_var_from_env;
+use crate::file::workspace::{WORKSPACES_DIR, mark_workspace_used};
+use crate::file::{RunOutput, run_sandboxed_command};
+use crate::models::execution::ExecutionContext;
+use crate::sec::input::RunInput;
+use crate::sec::sandbox::{SandboxConfig, Termination, load_sandbox_config};
+
+#[derive(Debug, Clone)]
+pub struct PythonConfig {
+    pub interpreter: String,
+    /// `pip install --target` directory holding the curated packages. Only the
+    /// standard library is importable without it.
+    pub packages_dir: Option<String>,
+}
+
+impl PythonConfig {
+    pub fn from_env() -> Self {
+        let packages_dir = get_var_from_env("PYTHON_PACKAGES_DIR")
+            .ok()
+            .and_then(|dir| match std::fs::canonicalize(&dir) {
+                Ok(path) => Some(path.to_string_lossy().to_string()),
+                Err(e) => {
+                    eprintln!(
+                        "ERRO: Diretório de pacotes Python {} inacessível: {}",
+                        dir, e
+                    );
+                    None
+                }
+            });
+
+        Self {
+            interpreter: get_var_from_env("PYTHON_BIN").unwrap_or_else(|_| "python3".to_string()),
+            packages_dir,
+        }
+    }
+}
+
+pub fn load_python_config() -> &'static PythonConfig {
+    static CONFIG: OnceLock<PythonConfig> = OnceLock::new();
+    CONFIG.get_or_init(PythonConfig::from_env)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PythonPackage {
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PythonRuntime {
+    pub version: String,
+    pub packages: Vec<PythonPackage>,
+}
+
+/// Interpreter version and curated packages, looked up once. `None` when the
+/// interpreter cannot be started.
+pub async fn python_runtime() -> Option<&'static PythonRuntime> {
+    static RUNTIME: OnceCell<Option<PythonRuntime>> = OnceCell::const_new();
+    RUNTIME
+        .get_or_init(|| async {
+            let config = load_python_config();
+            let output = match Command::new(&config.interpreter)
+                .arg("--version")
+                .output()
+                .await
+            {
+                Ok(out) if out.status.success() => out,
+                _ => {
+                    eprintln!(
+                        "LOG: Interpretador Python '{}' indisponível.",
+                        config.interpreter
+                    );
+                    return None;
+                }
+            };
+
+            let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            let packages = match &config.packages_dir {
+                Some(dir) => installed_packages(Path::new(dir)).await,
+                None => vec![],
+            };
+            Some(PythonRuntime { version, packages })
+        })
+        .await
+        .as_ref()
+}
+
+// pip leaves one `name-version.dist-info` directory per installed package.
+async fn installed_packages(dir: &Path) -> Vec<PythonPackage> {
+    let mut packages = vec![];
+    let Ok(mut entries) = tokio::fs::read_dir(dir).await else {
+        return packages;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if let Some((package, version)) = name
+            .strip_suffix(".dist-info")
+            .and_then(|stem| stem.split_once('-'))
+        {
+            packages.push(PythonPackage {
+                name: package.to_string(),
+                version: version.to_string(),
+            });
+        }
+    }
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+    packages
+}
+
+pub async fn setup_python_env(owner_key: &str, notebook_id: Option<Uuid>) -> PathBuf {
+    let python_dir = match notebook_id {
+        Some(id) => format!("py_{}", id.simple()),
+        None => "py_default".to_string(),
+    };
+    let user_dir = PathBuf::from(format!("files/{}/{}", owner_key, python_dir));
+    if let Err(e) = tokio::fs::create_dir_all(&user_dir).await {
+        eprintln!("ERRO: Falha ao criar diretórios {:?}: {}", user_dir, e);
+    }
+    mark_workspace_used(&user_dir).await;
+    user_dir
+}
+
+/// Writes the block as `main.py` and runs it with the sandboxed interpreter.
+pub async fn run_python(
+    workspace: &Path,
+    code: &str,
+    input: &RunInput,
+    ctx: &ExecutionContext,
+) -> RunOutput {
+    let script_path = workspace.join("main.py");
+    if let Err(e) = tokio::fs::write(&script_path, code).await {
+        return RunOutput::failure(
+            format!("Erro ao salvar arquivo main.py: {}", e),
+            Termination::SandboxFailure {
+                detail: e.to_string(),
+            },
+        );
+    }
+    let script = match tokio::fs::canonicalize(&script_path).await {
+        Ok(path) => path.to_string_lossy().to_string(),
+        Err(e) => {
+            return RunOutput::failure(
+                format!("Erro ao preparar execução: {}", e),
+                Termination::SandboxFailure {
+                    detail: e.to_string(),
+                },
+            );
+        }
+    };
+
+    let config = load_python_config();
+    let mut env = vec![("PYTHONIOENCODING", "utf-8".to_string())];
+    if let Some(dir) = &config.packages_dir {
+        env.push(("PYTHONPATH", dir.clone()));
+    }
+    // Python is held back by nothing but the sandbox, so it only runs where the
+    // home directory and every workspace are hidden.
+    let sandbox = SandboxConfig {
+        require_isolation: true,
+        ..load_sandbox_config().clone()
+    }
+    .exposing(
+        [
+            Some(PathBuf::from(&script)),
+            config.packages_dir.as_ref().map(PathBuf::from),
+            interpreter_prefix(&config.interpreter),
+        ]
+        .into_iter()
+        .flatten(),
+    );
+    let exposed = [
+        get_var_from_env("HOME").ok().map(PathBuf::from),
+        Some(PathBuf::from(WORKSPACES_DIR)),
+    ]
+    .into_iter()
+    .flatten()
+    .find(|dir| !sandbox.hides(dir));
+    if let Some(dir) = exposed {
+        eprintln!(
+            "ERRO: Python recusado: SANDBOX_HIDDEN_DIRS não esconde {}.",
+            dir.display()
+        );
+        return RunOutput::failure(
+            "Execução de Python indisponível neste servidor.".into(),
+            Termination::SandboxFailure {
+                detail: format!("{} visível dentro do sandbox", dir.display()),
+            },
+        );
+    }
+
+    println!("LOG: Executando script Python {}", script);
+    // -B: the workspace is read-only inside the sandbox; -s: no user site;
+    // -u: unbuffered, so output streams as it is printed.
+    run_sandboxed_command(
+        &sandbox,
+        &config.interpreter,
+        &["-B", "-s", "-u", &script],
+        &env,
+        workspace,
+        input,
+        ctx,
+    )
+    .await
+}
+
+// An interpreter installed in a hidden directory, such as pyenv's in the home
+// directory, needs its whole installation mounted back.
+fn interpreter_prefix(interpreter: &str) -> Option<PathBuf> {
+    if !Path::new(interpreter).is_absolute() {
+        return None;
+    }
+    let path = std::fs::canonicalize(interpreter).ok()?;
+    let bin_dir = path.parent()?;
+    if bin_dir.file_name()? != "bin" {
+        return None;
+    }
+    bin_dir.parent().map(Path::to_path_buf)
+}
@@ -0,0 +1,176 @@
+use serde::Serialize;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::sec::sandbox::Termination;
+
+/// How a run ended and what it cost. Fields the server could not measure,
+/// such as CPU time after a wall-clock timeout, are left out.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExecutionStats {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signal: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compile_time_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wall_time_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_time_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peak_rss_kb: Option<u64>,
+    pub stdout_truncated: bool,
+    pub stderr_truncated: bool,
+}
+
+impl ExecutionStats {
+    /// What can be told from the termination alone, for outputs served from
+    /// the cache.
+    pub fn from_termination(termination: &Termination) -> Self {
+        match termination {
+            Termination::Exited { code } => Self {
+                exit_code: Some(*code),
+                ..Default::default()
+            },
+            Termination::Signaled { signal } => Self {
+                signal: Some(*signal),
+                ..Default::default()
+            },
+            _ => Self::default(),
+        }
+    }
+
+    pub fn with_compile_time(mut self, compile_time: Option<Duration>) -> Self {
+        self.compile_time_ms = compile_time.map(|d| d.as_millis() as u64);
+        self
+    }
+}
+
+/// Waits for `pid` to exit without reaping it and returns the CPU time it and
+/// its reaped descendants used. The caller still has to wait on the process.
+///
+/// `pid` is the sandbox's reaper: the program is its child, not ours, so its
+/// times are read from the reaper's `/proc` entry while it is a zombie.
+pub async fn wait_for_cpu_time(pid: u32) -> Option<Duration> {
+    tokio::task::spawn_blocking(move || sys::wait_for_cpu_time(pid))
+        .await
+        .ok()
+        .flatten()
+}
+
+/// Samples the resident-set high-water mark of the program the sandbox's
+/// reaper runs, until [`PeakRssSampler::finish`] is called.
+pub struct PeakRssSampler {
+    stop: CancellationToken,
+    task: JoinHandle<Option<u64>>,
+}
+
+impl PeakRssSampler {
+    const INTERVAL: Duration = Duration::from_millis(10);
+
+    pub fn start(reaper_pid: u32) -> Self {
+        let stop = CancellationToken::new();
+        let token = stop.clone();
+        let task = tokio::spawn(async move {
+            let mut peak = None;
+            loop {
+                if let Some(kb) = sys::program_peak_rss_kb(reaper_pid) {
+                    peak = peak.max(Some(kb));
+                }
+                tokio::select! {
+                    _ = token.cancelled() => break,
+                    _ = tokio::time::sleep(Self::INTERVAL) => {}
+                }
+            }
+            peak
+        });
+        Self { stop, task }
+    }
+
+    /// Peak RSS in KiB of the program's main process. Growth in the last
+    /// sampling interval before exit can be missed.
+    pub async fn finish(self) -> Option<u64> {
+        self.stop.cancel();
+        self.task.await.ok().flatten()
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::time::Duration;
+
+    pub fn wait_for_cpu_time(pid: u32) -> Option<Duration> {
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        loop {
+            let rc = unsafe {
+                libc::waitid(
+                    libc::P_PID,
+                    pid as libc::id_t,
+                    &mut info,
+                    libc::WEXITED | libc::WNOWAIT,
+                )
+            };
+            if rc == 0 {
+                break;
+            }
+            if std::io::Error::last_os_error().raw_os_error() != Some(libc::EINTR) {
+                return None;
+            }
+        }
+
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+        // Fields after the parenthesised command name, starting at `state`.
+        let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+        // utime, stime, cutime and cstime are fields 14 to 17 of the file.
+        let ticks: u64 = fields
+            .get(11..15)?
+            .iter()
+            .map(|f| f.parse::<u64>().ok())
+            .sum::<Option<u64>>()?;
+
+        let per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+        if per_second <= 0 {
+            return None;
+        }
+        Some(Duration::from_millis(ticks * 1000 / per_second as u64))
+    }
+
+    pub fn program_peak_rss_kb(reaper_pid: u32) -> Option<u64> {
+        let children =
+            std::fs::read_to_string(format!("/proc/{}/task/{}/children", reaper_pid, reaper_pid))
+                .ok()?;
+        let pid = children.split_whitespace().next()?;
+
+        // Until exec the program is still a copy of the server, with the
+        // server's memory in its resident set.
+        let exe = std::fs::read_link(format!("/proc/{}/exe", pid)).ok()?;
+        if std::env::current_exe().ok()? == exe {
+            return None;
+        }
+
+        std::fs::read_to_string(format!("/proc/{}/status", pid))
+            .ok()?
+            .lines()
+            .find_map(|line| line.strip_prefix("VmHWM:"))?
+            .trim()
+            .trim_end_matches("kB")
+            .trim()
+            .parse()
+            .ok()
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use std::time::Duration;
+
+    pub fn wait_for_cpu_time(_pid: u32) -> Option<Duration> {
+        None
+    }
+
+    pub fn program_peak_rss_kb(_reaper_pid: u32) -> Option<u64> {
+        None
+    }
+}
@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+
+use crate::sec::input::RunInput;
+
+/// libtest only emits JSON behind `-Z unstable-options`; the test binary
+/// itself honours `RUSTC_BOOTSTRAP` at runtime, so stable toolchains work.
+pub fn test_binary_input(user: &RunInput) -> RunInput {
+    let mut args: Vec<String> = [
+        "-Z",
+        "unstable-options",
+        "--format",
+        "json",
+        "--report-time",
+        "--show-output",
+        "--test-threads",
+        "1",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect();
+    args.extend(user.args.iter().cloned());
+
+    let mut env = user.env.clone();
+    env.insert("RUSTC_BOOTSTRAP".to_string(), "1".to_string());
+
+    RunInput {
+        stdin: None,
+        args,
+        env,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    Ignored,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TestResult {
+    pub name: String,
+    pub status: TestStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<f64>,
+    pub output: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub panic_message: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TestReport {
+    pub passed: usize,
+    pub failed: usize,
+    pub ignored: usize,
+    pub filtered_out: usize,
+    pub results: Vec<TestResult>,
+}
+
+#[derive(Deserialize)]
+struct LibtestEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    event: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    exec_time: Option<f64>,
+    #[serde(default)]
+    stdout: Option<String>,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    filtered_out: Option<usize>,
+}
+
+/// Builds the report from libtest's JSON lines. Tests that started but never
+/// finished (the run was killed) are reported as failed with `interrupted`.
+pub fn parse_test_output(stdout: &str, interrupted: Option<String>) -> TestReport {
+    let mut report = TestReport::default();
+    let mut pending: Vec<String> = vec![];
+
+    for line in stdout.lines() {
+        let Ok(event) = serde_json::from_str::<LibtestEvent>(line) else {
+            continue;
+        };
+
+        match (event.kind.as_str(), event.event.as_str()) {
+            ("suite", "ok" | "failed") => {
+                report.filtered_out = event.filtered_out.unwrap_or(0);
+            }
+            ("test", "started") => {
+                if let Some(name) = event.name {
+                    pending.push(name);
+                }
+            }
+            ("test", outcome @ ("ok" | "failed" | "ignored")) => {
+                let Some(name) = event.name else {
+                    continue;
+                };
+                pending.retain(|p| p != &name);
+
+                let status = match outcome {
+                    "ok" => TestStatus::Passed,
+                    "failed" => TestStatus::Failed,
+                    _ => TestStatus::Ignored,
+                };
+                let captured = event.stdout.unwrap_or_default();
+                let (output, panic_message) = split_panic(&captured);
+
+                report.results.push(TestResult {
+                    name,
+                    status,
+                    duration_ms: event.exec_time.map(|secs| secs * 1000.0),
+                    output,
+                    panic_message: panic_message.or(event.message),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    for name in pending {
+        report.results.push(TestResult {
+            name,
+            status: TestStatus::Failed,
+            duration_ms: None,
+            output: String::new(),
+            panic_message: interrupted.clone(),
+        });
+    }
+
+    for result in &report.results {
+        match result.status {
+            TestStatus::Passed => report.passed += 1,
+            TestStatus::Failed => report.failed += 1,
+            TestStatus::Ignored => report.ignored += 1,
+        }
+    }
+
+    report
+}
+
+// Captured output ends with the default panic hook's report:
+// "thread 'name' (tid) panicked at file:line:col:\nmessage\nnote: ..."
+fn split_panic(captured: &str) -> (String, Option<String>) {
+    let Some(start) = captured
+        .match_indices("thread '")
+        .map(|(i, _)| i)
+        .find(|&i| {
+            (i == 0 || captured[..i].ends_with('\n'))
+                && captured[i..]
+                    .lines()
+                    .next()
+                    .is_some_and(|l| l.contains("panicked at"))
+        })
+    else {
+        return (captured.to_string(), None);
+    };
+
+    let output = captured[..start].to_string();
+    let message = captured[start..]
+        .lines()
+        .skip(1)
+        .take_while(|l| !l.starts_with("note: run with") && !l.starts_with("stack backtrace:"))
+        .collect::<Vec<&str>>()
+        .join("\n");
+
+    (output, Some(message))
+}
@@ -0,0 +1,402 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio::process::Command;
+use tokio::sync::OnceCell;
+
+use crate::file::python::{PythonRuntime, python_runtime};
+use crate::models::execution::RunMode;
+
+const CHANNELS: [&str; 3] = ["stable", "beta", "nightly"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Edition {
+    #[serde(rename = "2018")]
+    E2018,
+    #[serde(rename = "2021")]
+    E2021,
+    #[default]
+    #[serde(rename = "2024")]
+    E2024,
+}
+
+impl Edition {
+    pub const ALL: [Edition; 3] = [Edition::E2018, Edition::E2021, Edition::E2024];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Edition::E2018 => "2018",
+            Edition::E2021 => "2021",
+            Edition::E2024 => "2024",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BuildProfile {
+    #[default]
+    Debug,
+    Release,
+}
+
+impl BuildProfile {
+    /// Directory under `target/` holding this profile's artifacts.
+    pub fn target_dir(&self) -> &'static str {
+        match self {
+            BuildProfile::Debug => "debug",
+            BuildProfile::Release => "release",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BuildTarget {
+    #[default]
+    #[serde(rename = "native")]
+    Native,
+    /// WebAssembly with WASI, run by the browser instead of the server.
+    #[serde(rename = "wasm32-wasip1")]
+    Wasip1,
+}
+
+impl BuildTarget {
+    pub fn is_native(&self) -> bool {
+        *self == BuildTarget::Native
+    }
+
+    pub fn triple(&self) -> Option<&'static str> {
+        match self {
+            BuildTarget::Native => None,
+            BuildTarget::Wasip1 => Some("wasm32-wasip1"),
+        }
+    }
+}
+
+/// Compiler settings chosen per request. Without a `channel` the server's
+/// default toolchain is used.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BuildSettings {
+    #[serde(default)]
+    pub edition: Edition,
+    #[serde(default)]
+    pub profile: BuildProfile,
+    #[serde(default)]
+    pub overflow_checks: Option<bool>,
+    #[serde(default)]
+    pub channel: Option<String>,
+    /// Left out when native, so the build cache keys of native builds do
+    /// not depend on it.
+    #[serde(default, skip_serializing_if = "BuildTarget::is_native")]
+    pub target: BuildTarget,
+}
+
+/// The settings a build actually ran with, echoed back for reproducibility.
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildInfo {
+    pub edition: Edition,
+    pub profile: BuildProfile,
+    pub overflow_checks: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    #[serde(skip_serializing_if = "BuildTarget::is_native")]
+    pub target: BuildTarget,
+    pub toolchain: String,
+}
+
+impl BuildSettings {
+    /// Cargo enables overflow checks in debug builds only, unless told otherwise.
+    pub fn overflow_checks(&self) -> bool {
+        self.overflow_checks
+            .unwrap_or(self.profile == BuildProfile::Debug)
+    }
+
+    pub fn cargo_args(&self) -> Vec<&'static str> {
+        let mut args = match self.profile {
+            BuildProfile::Debug => vec![],
+            BuildProfile::Release => vec!["--release"],
+        };
+        if let Some(triple) = self.target.triple() {
+            args.extend(["--target", triple]);
+        }
+        args
+    }
+
+    /// Benchmarks are only meaningful with optimizations, so they always
+    /// build in release. The kernel keeps one configuration for its whole
+    /// life: the defaults, on the default toolchain.
+    pub fn for_mode(&self, mode: RunMode) -> BuildSettings {
+        match mode {
+            RunMode::Bench => BuildSettings {
+                profile: BuildProfile::Release,
+                ..self.clone()
+            },
+            RunMode::Kernel => BuildSettings::default(),
+            _ => self.clone(),
+        }
+    }
+
+    /// Directory holding this build's artifacts, below `target/`.
+    pub fn artifact_dir(&self) -> PathBuf {
+        let mut dir = PathBuf::new();
+        if let Some(triple) = self.target.triple() {
+            dir.push(triple);
+        }
+        dir.push(self.profile.target_dir());
+        dir
+    }
+
+    pub fn cargo_env(&self) -> Vec<(String, String)> {
+        let profile = match self.profile {
+            BuildProfile::Debug => "DEV",
+            BuildProfile::Release => "RELEASE",
+        };
+        let mut env = vec![(
+            format!("CARGO_PROFILE_{}_OVERFLOW_CHECKS", profile),
+            self.overflow_checks().to_string(),
+        )];
+        if let Some(channel) = &self.channel {
+            env.push(("RUSTUP_TOOLCHAIN".to_string(), channel.clone()));
+            env.push(("RUSTUP_AUTO_INSTALL".to_string(), "0".to_string()));
+        }
+        env
+    }
+
+    pub async fn validate(&self) -> Result<(), String> {
+        if let Some(channel) = &self.channel
+            && !installed_toolchains()
+                .await
+                .iter()
+                .any(|t| &t.channel == channel)
+        {
+            return Err(format!(
+                "O canal '{}' não está disponível neste servidor.",
+                channel
+            ));
+        }
+
+        if let Some(triple) = self.target.triple()
+            && !target_installed(self.channel.as_deref(), triple).await
+        {
+            return Err(format!(
+                "O alvo '{}' não está instalado neste servidor.",
+                triple
+            ));
+        }
+        Ok(())
+    }
+
+    pub async fn describe(&self) -> BuildInfo {
+        BuildInfo {
+            edition: self.edition,
+            profile: self.profile,
+            overflow_checks: self.overflow_checks(),
+            channel: self.channel.clone(),
+            target: self.target,
+            toolchain: toolchain_version(self.channel.as_deref())
+                .await
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolchainInfo {
+    pub channel: String,
+    pub version: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SupportedToolchains {
+    pub channels: Vec<ToolchainInfo>,
+    pub default_toolchain: String,
+    pub editions: Vec<Edition>,
+    pub profiles: Vec<BuildProfile>,
+    /// Targets the default toolchain can build for.
+    pub targets: Vec<BuildTarget>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub python: Option<PythonRuntime>,
+}
+
+pub async fn supported_toolchains() -> SupportedToolchains {
+    SupportedToolchains {
+        channels: installed_toolchains().await.to_vec(),
+        default_toolchain: toolchain_version(None)
+            .await
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+        editions: Edition::ALL.to_vec(),
+        profiles: vec![BuildProfile::Debug, BuildProfile::Release],
+        targets: available_targets(None).await,
+        python: python_runtime().await.cloned(),
+    }
+}
+
+/// The stable/beta/nightly toolchains rustup has installed, looked up once.
+/// Empty when the server's cargo is not managed by rustup.
+pub async fn installed_toolchains() -> &'static [ToolchainInfo] {
+    static TOOLCHAINS: OnceCell<Vec<ToolchainInfo>> = OnceCell::const_new();
+    TOOLCHAINS
+        .get_or_init(|| async {
+            let output = match Command::new("rustup")
+                .args(["toolchain", "list"])
+                .output()
+                .await
+            {
+                Ok(out) if out.status.success() => out,
+                _ => {
+                    eprintln!("LOG: rustup indisponível; apenas o toolchain padrão será usado.");
+                    return vec![];
+                }
+            };
+
+            let host = toolchain_version(None)
+                .await
+                .lines()
+                .find_map(|l| l.strip_prefix("host: "))
+                .unwrap_or_default()
+                .to_string();
+
+            let mut toolchains = vec![];
+            for line in String::from_utf8_lossy(&output.stdout).lines() {
+                let name = line.split_whitespace().next().unwrap_or_default();
+                let channel = name.strip_suffix(&format!("-{}", host)).unwrap_or(name);
+                if !CHANNELS.contains(&channel) {
+                    continue;
+                }
+                let version = toolchain_version(Some(channel))
+                    .await
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string();
+                toolchains.push(ToolchainInfo {
+                    channel: channel.to_string(),
+                    version,
+                });
+            }
+            toolchains
+        })
+        .await
+}
+
+/// `rustc -vV` of the given channel, or of the default toolchain.
+pub async fn toolchain_version(channel: Option<&str>) -> String {
+    static VERSIONS: Mutex<Option<HashMap<Option<String>, String>>> = Mutex::new(None);
+
+    let key = channel.map(str::to_string);
+    if let Some(version) = VERSIONS
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|versions| versions.get(&key))
+    {
+        return version.clone();
+    }
+
+    let mut command = Command::new("rustc");
+    command.arg("-vV");
+    if let Some(channel) = channel {
+        // rustup would otherwise download a missing channel on first use.
+        command
+            .env("RUSTUP_TOOLCHAIN", channel)
+            .env("RUSTUP_AUTO_INSTALL", "0");
+    }
+
+    let version = match command.output().await {
+        Ok(out) if out.status.success() => String::from_utf8_lossy(&out.stdout).trim().to_string(),
+        _ => {
+            eprintln!("ERRO: Não foi possível obter a versão do rustc");
+            return "unknown".to_string();
+        }
+    };
+
+    VERSIONS
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(key, version.clone());
+    version
+}
+
+async fn available_targets(channel: Option<&str>) -> Vec<BuildTarget> {
+    let mut targets = vec![BuildTarget::Native];
+    for target in [BuildTarget::Wasip1] {
+        if let Some(triple) = target.triple()
+            && target_installed(channel, triple).await
+        {
+            targets.push(target);
+        }
+    }
+    targets
+}
+
+/// Whether the channel's sysroot has the standard library for `triple`.
+async fn target_installed(channel: Option<&str>, triple: &str) -> bool {
+    static SYSROOTS: Mutex<Option<HashMap<Option<String>, PathBuf>>> = Mutex::new(None);
+
+    let key = channel.map(str::to_string);
+    let cached = SYSROOTS
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|sysroots| sysroots.get(&key).cloned());
+    let sysroot = match cached {
+        Some(sysroot) => sysroot,
+        None => {
+            let mut command = Command::new("rustc");
+            command.args(["--print", "sysroot"]);
+            if let Some(channel) = channel {
+                command
+                    .env("RUSTUP_TOOLCHAIN", channel)
+                    .env("RUSTUP_AUTO_INSTALL", "0");
+            }
+            let sysroot = match command.output().await {
+                Ok(out) if out.status.success() => {
+                    PathBuf::from(String::from_utf8_lossy(&out.stdout).trim())
+                }
+                _ => return false,
+            };
+            SYSROOTS
+                .lock()
+                .unwrap()
+                .get_or_insert_with(HashMap::new)
+                .insert(key, sysroot.clone());
+            sysroot
+        }
+    };
+
+    tokio::fs::try_exists(sysroot.join("lib/rustlib").join(triple))
+        .await
+        .unwrap_or(false)
+}
+
+pub async fn write_manifest_edition(project_path: &Path, edition: Edition) -> std::io::Result<()> {
+    let manifest_path = project_path.join("Cargo.toml");
+    let manifest = tokio::fs::read_to_string(&manifest_path).await?;
+
+    let edition_line = format!("edition = \"{}\"", edition.as_str());
+    let content = manifest
+        .lines()
+        .map(|line| {
+            if line.trim_start().starts_with("edition") {
+                edition_line.as_str()
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<&str>>()
+        .join("\n")
+        + "\n";
+
+    if content != manifest {
+        tokio::fs::write(&manifest_path, content).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use serde::Serialize;
+
+use crate::file::toolchain::BuildTarget;
+
+/// Every WebAssembly binary starts with these bytes.
+const WASM_MAGIC: &[u8] = b"\0asm";
+
+/// A compiled module the browser downloads and runs itself.
+#[derive(Debug, Clone, Serialize)]
+pub struct WasmModule {
+    pub target: BuildTarget,
+    pub url: String,
+    pub size_bytes: u64,
+}
+
+impl WasmModule {
+    pub fn new(target: BuildTarget, key: &str, size_bytes: u64) -> Self {
+        Self {
+            target,
+            url: module_url(key),
+            size_bytes,
+        }
+    }
+}
+
+/// Modules are addressed by their build cache key, so the same sources and
+/// settings always map to the same URL.
+pub fn module_url(key: &str) -> String {
+    format!("/run/wasm/{}", key)
+}
+
+pub fn is_cache_key(key: &str) -> bool {
+    key.len() == 64 && key.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Native binaries share the cache, so only hand out real modules.
+pub fn is_wasm_module(bytes: &[u8]) -> bool {
+    bytes.starts_with(WASM_MAGIC)
+}
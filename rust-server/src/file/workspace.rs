@@ -0,0 +1,394 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::Notify;
+
+use crate::controllers::utils::get_parsed_var_from_env;
+use crate::http::identity::WorkspaceOwner;
+
+pub(crate) const WORKSPACES_DIR: &str = "files";
+
+/// Touched whenever a workspace is used; its mtime orders eviction and
+/// survives restarts.
+const LAST_USED_FILE: &str = ".last_used";
+
+/// A finished build asks for a sweep, but sweeps never run closer together.
+const MIN_SWEEP_GAP: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkspaceConfig {
+    pub ttl_secs: u64,
+    pub max_total_bytes: u64,
+    pub max_owner_bytes: u64,
+    pub sweep_interval_secs: u64,
+}
+
+impl WorkspaceConfig {
+    pub fn from_env() -> Self {
+        Self {
+            ttl_secs: get_parsed_var_from_env::<u64>("WORKSPACE_TTL_MINUTES", 120) * 60,
+            max_total_bytes: get_parsed_var_from_env::<u64>("WORKSPACE_MAX_DISK_MB", 10240)
+                * 1024
+                * 1024,
+            max_owner_bytes: get_parsed_var_from_env::<u64>("WORKSPACE_MAX_PER_USER_MB", 2048)
+                * 1024
+                * 1024,
+            sweep_interval_secs: get_parsed_var_from_env::<u64>("WORKSPACE_SWEEP_SECONDS", 300)
+                .max(MIN_SWEEP_GAP.as_secs()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EvictionReason {
+    Expired,
+    OwnerCap,
+    DiskBudget,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct EvictionCounts {
+    pub expired: u64,
+    pub owner_cap: u64,
+    pub disk_budget: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkspaceMetrics {
+    pub workspaces: usize,
+    pub owners: usize,
+    pub total_bytes: u64,
+    pub max_total_bytes: u64,
+    pub max_owner_bytes: u64,
+    pub ttl_secs: u64,
+    pub in_use: usize,
+    pub evictions: EvictionCounts,
+    pub evicted_bytes: u64,
+    pub sweeps: u64,
+    pub last_sweep_ms: u64,
+    /// Unix time of the last sweep, in seconds.
+    pub last_sweep_at: Option<u64>,
+}
+
+#[derive(Default)]
+struct ManagerState {
+    leases: HashMap<WorkspaceOwner, usize>,
+    /// Owner directories the sweep is deleting from; leases wait for them.
+    evicting: HashSet<String>,
+    workspaces: usize,
+    owners: usize,
+    total_bytes: u64,
+    evictions: EvictionCounts,
+    evicted_bytes: u64,
+    sweeps: u64,
+    last_sweep_ms: u64,
+    last_sweep_at: Option<u64>,
+}
+
+struct WorkspaceEntry {
+    path: PathBuf,
+    owner_dir: String,
+    size_bytes: u64,
+    last_used: SystemTime,
+}
+
+/// Keeps `files/` within its disk budget. Workspaces unused for longer than
+/// the TTL go first, then the least recently used ones of owners over their
+/// cap, then the least recently used overall until the total fits. Owners
+/// with a build in progress are never touched.
+pub struct WorkspaceManager {
+    config: WorkspaceConfig,
+    state: Mutex<ManagerState>,
+    wake: Notify,
+    evicted: Notify,
+}
+
+/// Held while an owner builds or runs; their workspaces are not evicted.
+pub struct WorkspaceLease {
+    manager: Arc<WorkspaceManager>,
+    owner: WorkspaceOwner,
+}
+
+impl Drop for WorkspaceLease {
+    fn drop(&mut self) {
+        let mut state = self.manager.state.lock().unwrap();
+        if let Some(count) = state.leases.get_mut(&self.owner) {
+            *count -= 1;
+            if *count == 0 {
+                state.leases.remove(&self.owner);
+            }
+        }
+        self.manager.wake.notify_one();
+    }
+}
+
+impl WorkspaceManager {
+    pub fn new(config: WorkspaceConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(ManagerState::default()),
+            wake: Notify::new(),
+            evicted: Notify::new(),
+        }
+    }
+
+    /// Waits out a sweep deleting the owner's workspaces, so the lease is
+    /// taken before any workspace is set up.
+    pub async fn lease(self: &Arc<Self>, owner: WorkspaceOwner) -> WorkspaceLease {
+        let key = owner.key();
+        loop {
+            let evicted = self.evicted.notified();
+            {
+                let mut state = self.state.lock().unwrap();
+                if !state.evicting.contains(&key) {
+                    *state.leases.entry(owner).or_insert(0) += 1;
+                    break;
+                }
+            }
+            evicted.await;
+        }
+        WorkspaceLease {
+            manager: self.clone(),
+            owner,
+        }
+    }
+
+    pub fn metrics(&self) -> WorkspaceMetrics {
+        let state = self.state.lock().unwrap();
+        WorkspaceMetrics {
+            workspaces: state.workspaces,
+            owners: state.owners,
+            total_bytes: state.total_bytes,
+            max_total_bytes: self.config.max_total_bytes,
+            max_owner_bytes: self.config.max_owner_bytes,
+            ttl_secs: self.config.ttl_secs,
+            in_use: state.leases.len(),
+            evictions: state.evictions.clone(),
+            evicted_bytes: state.evicted_bytes,
+            sweeps: state.sweeps,
+            last_sweep_ms: state.last_sweep_ms,
+            last_sweep_at: state.last_sweep_at,
+        }
+    }
+
+    /// Sweeps on the configured interval, and after builds finish.
+    pub async fn run(self: Arc<Self>) {
+        println!("LOG: Iniciando gerenciador de workspaces");
+        let interval = Duration::from_secs(self.config.sweep_interval_secs);
+
+        loop {
+            self.sweep().await;
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = self.wake.notified() => tokio::time::sleep(MIN_SWEEP_GAP).await,
+            }
+        }
+    }
+
+    /// Marks the owner as being evicted unless they hold a lease, checking
+    /// both under one lock so no lease slips in before the deletion.
+    fn begin_eviction(&self, owner_dir: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.leases.keys().any(|owner| owner.key() == owner_dir) {
+            return false;
+        }
+        state.evicting.insert(owner_dir.to_string());
+        true
+    }
+
+    fn end_eviction(&self, owner_dir: &str) {
+        self.state.lock().unwrap().evicting.remove(owner_dir);
+        self.evicted.notify_waiters();
+    }
+
+    async fn remove_empty_owner_dirs(&self) {
+        let Ok(mut owners) = tokio::fs::read_dir(WORKSPACES_DIR).await else {
+            return;
+        };
+        while let Ok(Some(owner)) = owners.next_entry().await {
+            let owner_dir = owner.file_name().to_string_lossy().to_string();
+            if !self.begin_eviction(&owner_dir) {
+                continue;
+            }
+            // Fails unless the directory is empty.
+            let _ = tokio::fs::remove_dir(owner.path()).await;
+            self.end_eviction(&owner_dir);
+        }
+    }
+
+    pub async fn sweep(&self) {
+        let started = Instant::now();
+        let mut entries = match tokio::task::spawn_blocking(scan_workspaces).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("ERRO: [GC] Falha ao varrer workspaces: {}", e);
+                return;
+            }
+        };
+
+        let protected: Vec<String> = self
+            .state
+            .lock()
+            .unwrap()
+            .leases
+            .keys()
+            .map(|owner| owner.key())
+            .collect();
+
+        // Oldest first, so every pass below evicts in LRU order.
+        entries.sort_by_key(|e| e.last_used);
+
+        let now = SystemTime::now();
+        let ttl = Duration::from_secs(self.config.ttl_secs);
+        let mut evicted = vec![];
+        let mut kept = vec![];
+        for entry in entries {
+            let expired = now
+                .duration_since(entry.last_used)
+                .is_ok_and(|age| age > ttl);
+            if expired && !protected.contains(&entry.owner_dir) {
+                evicted.push((entry, EvictionReason::Expired));
+            } else {
+                kept.push(entry);
+            }
+        }
+
+        let mut owner_bytes: HashMap<String, u64> = HashMap::new();
+        for entry in &kept {
+            *owner_bytes.entry(entry.owner_dir.clone()).or_insert(0) += entry.size_bytes;
+        }
+        let mut index = 0;
+        while index < kept.len() {
+            let entry = &kept[index];
+            let used = owner_bytes[&entry.owner_dir];
+            if used > self.config.max_owner_bytes && !protected.contains(&entry.owner_dir) {
+                *owner_bytes.get_mut(&entry.owner_dir).unwrap() -= entry.size_bytes;
+                evicted.push((kept.remove(index), EvictionReason::OwnerCap));
+            } else {
+                index += 1;
+            }
+        }
+
+        let mut total: u64 = kept.iter().map(|e| e.size_bytes).sum();
+        let mut index = 0;
+        while total > self.config.max_total_bytes && index < kept.len() {
+            if protected.contains(&kept[index].owner_dir) {
+                index += 1;
+                continue;
+            }
+            let entry = kept.remove(index);
+            total -= entry.size_bytes;
+            evicted.push((entry, EvictionReason::DiskBudget));
+        }
+        if total > self.config.max_total_bytes {
+            eprintln!(
+                "AVISO: [GC] Workspaces em uso ocupam {} bytes, acima do limite de {}",
+                total, self.config.max_total_bytes
+            );
+        }
+
+        let mut freed = EvictionCounts::default();
+        let mut freed_bytes = 0;
+        for (entry, reason) in evicted {
+            // The owner may have started a build since the scan.
+            if !self.begin_eviction(&entry.owner_dir) {
+                total += entry.size_bytes;
+                kept.push(entry);
+                continue;
+            }
+            println!(
+                "LOG: [GC] Removendo workspace {:?} ({} bytes, {:?})",
+                entry.path, entry.size_bytes, reason
+            );
+            let removed = tokio::fs::remove_dir_all(&entry.path).await;
+            self.end_eviction(&entry.owner_dir);
+            if let Err(e) = removed {
+                eprintln!("ERRO: [GC] Falha ao deletar {:?}: {}", entry.path, e);
+                total += entry.size_bytes;
+                kept.push(entry);
+                continue;
+            }
+            freed_bytes += entry.size_bytes;
+            match reason {
+                EvictionReason::Expired => freed.expired += 1,
+                EvictionReason::OwnerCap => freed.owner_cap += 1,
+                EvictionReason::DiskBudget => freed.disk_budget += 1,
+            }
+        }
+        self.remove_empty_owner_dirs().await;
+
+        let mut owners: Vec<&str> = kept.iter().map(|e| e.owner_dir.as_str()).collect();
+        owners.sort_unstable();
+        owners.dedup();
+
+        let mut state = self.state.lock().unwrap();
+        state.workspaces = kept.len();
+        state.owners = owners.len();
+        state.total_bytes = total;
+        state.evictions.expired += freed.expired;
+        state.evictions.owner_cap += freed.owner_cap;
+        state.evictions.disk_budget += freed.disk_budget;
+        state.evicted_bytes += freed_bytes;
+        state.sweeps += 1;
+        state.last_sweep_ms = started.elapsed().as_millis() as u64;
+        state.last_sweep_at = now.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs());
+    }
+}
+
+/// Records that a workspace was just used.
+pub async fn mark_workspace_used(dir: &Path) {
+    if let Err(e) = tokio::fs::write(dir.join(LAST_USED_FILE), b"").await {
+        eprintln!("ERRO: Falha ao marcar uso do workspace {:?}: {}", dir, e);
+    }
+}
+
+fn scan_workspaces() -> Vec<WorkspaceEntry> {
+    let mut entries = vec![];
+    let Ok(owners) = std::fs::read_dir(WORKSPACES_DIR) else {
+        return entries;
+    };
+
+    for owner in owners.flatten() {
+        if !owner.file_type().is_ok_and(|t| t.is_dir()) {
+            continue;
+        }
+        let owner_dir = owner.file_name().to_string_lossy().to_string();
+        let Ok(workspaces) = std::fs::read_dir(owner.path()) else {
+            continue;
+        };
+
+        for workspace in workspaces.flatten() {
+            let path = workspace.path();
+            if !workspace.file_type().is_ok_and(|t| t.is_dir()) {
+                continue;
+            }
+            let last_used = std::fs::metadata(path.join(LAST_USED_FILE))
+                .or_else(|_| std::fs::metadata(&path))
+                .and_then(|m| m.modified())
+                .unwrap_or(UNIX_EPOCH);
+            entries.push(WorkspaceEntry {
+                size_bytes: tree_size(&path),
+                path,
+                owner_dir: owner_dir.clone(),
+                last_used,
+            });
+        }
+    }
+    entries
+}
+
+fn tree_size(dir: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(t) if t.is_dir() => tree_size(&entry.path()),
+            Ok(t) if t.is_file() => entry.metadata().map(|m| m.len()).unwrap_or(0),
+            _ => 0,
+        })
+        .sum()
+}
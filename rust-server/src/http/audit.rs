@@ -0,0 +1,88 @@
+use diesel_async::{AsyncPgConnection, pooled_connection::deadpool::Pool};
+
+use crate::CodeResponse;
+use crate::http::identity::{RequestIdentity, WorkspaceOwner};
+use crate::models::audit::{AuditVerdict, NewAuditRecord, insert_audit_record};
+use crate::models::error::ApiError;
+use crate::models::execution_result::code_hash;
+use crate::sec::sandbox::Termination;
+
+pub fn audit_verdict(outcome: &Result<CodeResponse, ApiError>) -> AuditVerdict {
+    let response = match outcome {
+        Ok(response) => response,
+        Err(e) if e.retry_after_secs().is_some() => return AuditVerdict::Throttled,
+        Err(_) => return AuditVerdict::Failed,
+    };
+
+    if !response.violations.is_empty() {
+        return AuditVerdict::PolicyViolation;
+    }
+
+    match &response.termination {
+        Some(Termination::Cancelled) => AuditVerdict::Cancelled,
+        Some(Termination::SandboxFailure { .. }) => AuditVerdict::Failed,
+        Some(termination) if termination.is_violation() => AuditVerdict::SandboxViolation,
+        Some(_) => AuditVerdict::Completed,
+        // Nothing ran: either the compiler stopped the build, a module was
+        // only checked, or the request was turned away before building.
+        None => {
+            let compile_failed = response.diagnostics.iter().any(|d| d.level == "error");
+            match &response.stats {
+                Some(_) if compile_failed || response.stdout.is_empty() => {
+                    AuditVerdict::CompileError
+                }
+                Some(_) => AuditVerdict::Completed,
+                None => AuditVerdict::InvalidRequest,
+            }
+        }
+    }
+}
+
+/// Writes the audit record for an execution request in the background.
+pub fn record_audit(
+    pool: &Pool<AsyncPgConnection>,
+    identity: &RequestIdentity,
+    endpoint: &str,
+    code: &str,
+    outcome: &Result<CodeResponse, ApiError>,
+) {
+    let verdict = audit_verdict(outcome);
+    let response = outcome.as_ref().ok();
+
+    let record = NewAuditRecord {
+        user_id: identity.owner.user_id(),
+        anonymous_session: match identity.owner {
+            WorkspaceOwner::Anonymous(sid) => Some(sid),
+            WorkspaceOwner::User(_) => None,
+        },
+        client_ip: identity.client_ip.to_string(),
+        user_agent: identity.user_agent.clone(),
+        endpoint: endpoint.to_string(),
+        code_hash: code_hash(code),
+        verdict: verdict.as_str().to_string(),
+        violations: response
+            .and_then(|r| serde_json::to_value(&r.violations).ok())
+            .unwrap_or_else(|| serde_json::json!([])),
+        termination: response
+            .and_then(|r| r.termination.as_ref())
+            .and_then(|t| serde_json::to_value(t).ok()),
+        stats: response
+            .and_then(|r| r.stats.as_ref())
+            .and_then(|s| serde_json::to_value(s).ok()),
+    };
+
+    let pool = pool.clone();
+    let code = code.to_string();
+    tokio::spawn(async move {
+        let mut conn = match pool.get().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                eprintln!("ERRO: Falha ao obter conexão para auditoria: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = insert_audit_record(&mut conn, &record, &code).await {
+            eprintln!("ERRO: Falha ao gravar registro de auditoria: {}", e);
+        }
+    });
+}
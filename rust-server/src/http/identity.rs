@@ -0,0 +1,158 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::OnceLock;
+
+use axum::http::HeaderMap;
+use uuid::Uuid;
+
+use crate::controllers::jwt::{
+    decode_anonymous_session, extract_claims_from_header, extract_claims_from_ws_headers,
+    extract_token_from_ws, generate_anonymous_session,
+};
+use crate::controllers::utils::get_var_from_env;
+
+pub const ANONYMOUS_SESSION_HEADER: &str = "x-anonymous-session";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WorkspaceOwner {
+    User(Uuid),
+    Anonymous(Uuid),
+}
+
+impl WorkspaceOwner {
+    pub fn key(&self) -> String {
+        match self {
+            WorkspaceOwner::User(id) => format!("u_{}", id.simple()),
+            WorkspaceOwner::Anonymous(id) => format!("a_{}", id.simple()),
+        }
+    }
+
+    pub fn user_id(&self) -> Option<Uuid> {
+        match self {
+            WorkspaceOwner::User(id) => Some(*id),
+            WorkspaceOwner::Anonymous(_) => None,
+        }
+    }
+}
+
+pub struct RequestIdentity {
+    pub owner: WorkspaceOwner,
+    pub client_ip: IpAddr,
+    pub user_agent: String,
+    pub issued_session: Option<String>,
+}
+
+pub async fn resolve_identity(peer: SocketAddr, headers: &HeaderMap) -> RequestIdentity {
+    let user_id = extract_claims_from_header(headers)
+        .await
+        .ok()
+        .map(|(_, claims)| claims.id);
+    let session = headers
+        .get(ANONYMOUS_SESSION_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .map(|token| token.trim().to_string());
+
+    build_identity(peer, headers, user_id, session.as_deref())
+}
+
+/// Browsers cannot set headers on WebSocket upgrades, so both the user JWT and
+/// the anonymous session token travel in `Sec-WebSocket-Protocol`.
+pub async fn resolve_ws_identity(peer: SocketAddr, headers: &HeaderMap) -> RequestIdentity {
+    let user_id = extract_claims_from_ws_headers(headers)
+        .await
+        .ok()
+        .map(|(_, claims)| claims.id);
+    let session = extract_token_from_ws(headers);
+
+    build_identity(peer, headers, user_id, session.as_deref())
+}
+
+fn build_identity(
+    peer: SocketAddr,
+    headers: &HeaderMap,
+    user_id: Option<Uuid>,
+    session: Option<&str>,
+) -> RequestIdentity {
+    let client_ip = client_ip(peer.ip(), headers);
+
+    let user_agent = headers
+        .get("user-agent")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("Unknown Agent")
+        .to_string();
+
+    if let Some(id) = user_id {
+        return RequestIdentity {
+            owner: WorkspaceOwner::User(id),
+            client_ip,
+            user_agent,
+            issued_session: None,
+        };
+    }
+
+    let existing_session = session.and_then(|token| decode_anonymous_session(token).ok());
+
+    if let Some(claims) = existing_session {
+        return RequestIdentity {
+            owner: WorkspaceOwner::Anonymous(claims.sid),
+            client_ip,
+            user_agent,
+            issued_session: None,
+        };
+    }
+
+    let sid = Uuid::new_v4();
+    let issued_session = match generate_anonymous_session(sid) {
+        Ok(token) => Some(token),
+        Err(e) => {
+            eprintln!("ERRO: Falha ao emitir sessão anônima: {}", e);
+            None
+        }
+    };
+
+    RequestIdentity {
+        owner: WorkspaceOwner::Anonymous(sid),
+        client_ip,
+        user_agent,
+        issued_session,
+    }
+}
+
+fn trusted_proxies() -> &'static Vec<IpAddr> {
+    static PROXIES: OnceLock<Vec<IpAddr>> = OnceLock::new();
+    PROXIES.get_or_init(|| match get_var_from_env("TRUSTED_PROXIES") {
+        Ok(list) => list
+            .split(',')
+            .filter_map(|ip| {
+                let ip = ip.trim();
+                if ip.is_empty() {
+                    return None;
+                }
+                ip.parse::<IpAddr>()
+                    .map_err(|_| eprintln!("ERRO: IP de proxy inválido em TRUSTED_PROXIES: {}", ip))
+                    .ok()
+            })
+            .collect(),
+        Err(_) => vec![],
+    })
+}
+
+pub fn client_ip(peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+    let proxies = trusted_proxies();
+    if !proxies.contains(&peer) {
+        return peer;
+    }
+
+    let forwarded = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(','))
+        .filter_map(|ip| ip.trim().parse::<IpAddr>().ok())
+        .collect::<Vec<IpAddr>>();
+
+    forwarded
+        .into_iter()
+        .rev()
+        .find(|ip| !proxies.contains(ip))
+        .unwrap_or(peer)
+}
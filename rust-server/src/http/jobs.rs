@@ -0,0 +1,193 @@
+use axum::{
+    extract::{
+        ConnectInfo, Json, Path, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures::{SinkExt, stream::StreamExt};
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+use crate::CodeRequest;
+use crate::controllers::jobs::{JobKind, JobSubscription, JobView};
+use crate::http::identity::{resolve_identity, resolve_ws_identity};
+use crate::http::project::{ProjectRunRequest, run_project_payload};
+use crate::http::run_code_request;
+use crate::models::error::ApiError;
+use crate::models::state::AppState;
+
+#[derive(Serialize)]
+pub struct SubmittedJob {
+    #[serde(flatten)]
+    job: JobView,
+    /// Needed to poll the job when it was submitted without a session.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    anonymous_session: Option<String>,
+}
+
+pub async fn submit_job(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<CodeRequest>,
+) -> Result<(StatusCode, Json<SubmittedJob>), ApiError> {
+    let identity = resolve_identity(addr, &headers).await;
+
+    println!("--------------------------------------------------");
+    println!(
+        "LOG: Novo job de {} (IP: {})",
+        identity.owner.key(),
+        identity.client_ip
+    );
+
+    let anonymous_session = identity.issued_session.clone();
+    let jobs = state.jobs.clone();
+    let job = jobs.spawn(identity.owner, JobKind::Run, |ctx| async move {
+        run_code_request(&state, &identity, &payload, &ctx, "job").await
+    })?;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(SubmittedJob {
+            job,
+            anonymous_session,
+        }),
+    ))
+}
+
+pub async fn submit_project_job(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<ProjectRunRequest>,
+) -> Result<(StatusCode, Json<SubmittedJob>), ApiError> {
+    let identity = resolve_identity(addr, &headers).await;
+
+    println!("--------------------------------------------------");
+    println!(
+        "LOG: Novo job de projeto de {} (IP: {})",
+        identity.owner.key(),
+        identity.client_ip
+    );
+
+    let anonymous_session = identity.issued_session.clone();
+    let jobs = state.jobs.clone();
+    let job = jobs.spawn(identity.owner, JobKind::Project, |ctx| async move {
+        run_project_payload(&state, &identity, &payload, &ctx, "project_job").await
+    })?;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(SubmittedJob {
+            job,
+            anonymous_session,
+        }),
+    ))
+}
+
+pub async fn list_jobs(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Json<Vec<JobView>> {
+    let identity = resolve_identity(addr, &headers).await;
+    Json(state.jobs.list(identity.owner))
+}
+
+pub async fn job_status(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<JobView>, ApiError> {
+    let identity = resolve_identity(addr, &headers).await;
+    state
+        .jobs
+        .get(id, identity.owner)
+        .map(Json)
+        .ok_or(ApiError::JobNotFound)
+}
+
+pub async fn cancel_job(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<(StatusCode, Json<JobView>), ApiError> {
+    let identity = resolve_identity(addr, &headers).await;
+    let job = state
+        .jobs
+        .cancel(id, identity.owner)
+        .ok_or(ApiError::JobNotFound)?;
+
+    let status = if job.status.is_finished() {
+        StatusCode::OK
+    } else {
+        StatusCode::ACCEPTED
+    };
+    Ok((status, Json(job)))
+}
+
+/// Replays the job's events so far, then follows it live with the same
+/// messages as `/run/ws` until its `result` or `error`.
+pub async fn job_events(
+    ws: WebSocketUpgrade,
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Response, ApiError> {
+    let identity = resolve_ws_identity(addr, &headers).await;
+    let subscription = state
+        .jobs
+        .subscribe(id, identity.owner)
+        .ok_or(ApiError::JobNotFound)?;
+
+    Ok(ws
+        .protocols(["access_token"])
+        .on_upgrade(move |socket| follow_job(socket, subscription))
+        .into_response())
+}
+
+async fn follow_job(socket: WebSocket, subscription: JobSubscription) {
+    let (mut sender, mut receiver) = socket.split();
+
+    for event in subscription.replay {
+        if sender
+            .send(Message::Text(event.as_ref().into()))
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+
+    if let Some(mut live) = subscription.live {
+        loop {
+            tokio::select! {
+                event = live.recv() => match event {
+                    Ok(event) => {
+                        if sender.send(Message::Text(event.as_ref().into())).await.is_err() {
+                            return;
+                        }
+                    }
+                    // Output the client fell behind on is skipped; the
+                    // result still arrives.
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                },
+                message = receiver.next() => match message {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                    Some(Ok(_)) => {}
+                }
+            }
+        }
+    }
+
+    let _ = sender.close().await;
+}
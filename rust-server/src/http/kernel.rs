@@ -0,0 +1,128 @@
+use axum::extract::{ConnectInfo, Json, Query, State};
+use axum::http::HeaderMap;
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::controllers::kernel::{KernelKey, KernelView};
+use crate::file::setup_kernel_env;
+use crate::http::identity::{RequestIdentity, resolve_identity};
+use crate::models::error::ApiError;
+use crate::models::execution::{ExecutionContext, ExecutionEvent, ExecutionPhase};
+use crate::models::state::AppState;
+use crate::{CodeRequest, CodeResponse};
+
+#[derive(Deserialize)]
+pub struct KernelQuery {
+    /// Kernel of the owner's scratch workspace when absent.
+    #[serde(default)]
+    notebook_id: Option<Uuid>,
+}
+
+impl KernelQuery {
+    fn key(&self, identity: &RequestIdentity) -> KernelKey {
+        KernelKey {
+            owner: identity.owner,
+            notebook_id: self.notebook_id,
+        }
+    }
+}
+
+/// Evaluates a block in the notebook's kernel, starting one if needed.
+pub async fn run_kernel_block(
+    state: &AppState,
+    identity: &RequestIdentity,
+    payload: &CodeRequest,
+    ctx: &ExecutionContext,
+) -> Result<CodeResponse, ApiError> {
+    let key = KernelKey {
+        owner: identity.owner,
+        notebook_id: payload.notebook_id,
+    };
+    let lease = state.workspaces.lease(identity.owner).await;
+    let workspace = setup_kernel_env(&identity.owner.key(), payload.notebook_id).await;
+    let kernel = state.kernels.acquire(key, &workspace, lease).await?;
+
+    // The kernel compiles and runs each block in one step.
+    ctx.emit(ExecutionEvent::phase(ExecutionPhase::Running));
+    let run = kernel.evaluate(&payload.code, ctx).await;
+
+    Ok(CodeResponse {
+        stdout: run.stdout,
+        stderr: run.stderr,
+        termination: Some(run.termination),
+        stats: Some(run.stats),
+        kernel: Some(kernel.view()),
+        ..Default::default()
+    })
+}
+
+pub async fn kernel_status(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<KernelQuery>,
+) -> Result<Json<KernelView>, ApiError> {
+    let identity = resolve_identity(addr, &headers).await;
+    state
+        .kernels
+        .get(query.key(&identity))
+        .map(|kernel| Json(kernel.view()))
+        .ok_or(ApiError::KernelNotFound)
+}
+
+/// Starts the notebook's kernel afresh, whether or not it had one.
+pub async fn restart_kernel(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<KernelQuery>,
+) -> Result<Json<KernelView>, ApiError> {
+    let identity = resolve_identity(addr, &headers).await;
+    state.limiter.admit(&identity)?;
+
+    println!(
+        "LOG: Reiniciando kernel de {} (IP: {})",
+        identity.owner.key(),
+        identity.client_ip
+    );
+
+    let lease = state.workspaces.lease(identity.owner).await;
+    let workspace = setup_kernel_env(&identity.owner.key(), query.notebook_id).await;
+    let kernel = state
+        .kernels
+        .restart(query.key(&identity), &workspace, lease)
+        .await?;
+    Ok(Json(kernel.view()))
+}
+
+/// Stops the block the kernel is running, which then fails with the output
+/// it printed so far. Cancelling the run itself interrupts the kernel too.
+pub async fn interrupt_kernel(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<KernelQuery>,
+) -> Result<Json<KernelView>, ApiError> {
+    let identity = resolve_identity(addr, &headers).await;
+    state
+        .kernels
+        .interrupt(query.key(&identity))
+        .map(|kernel| Json(kernel.view()))
+        .ok_or(ApiError::KernelNotFound)
+}
+
+pub async fn shutdown_kernel(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<KernelQuery>,
+) -> Result<Json<KernelView>, ApiError> {
+    let identity = resolve_identity(addr, &headers).await;
+    state
+        .kernels
+        .shutdown(query.key(&identity))
+        .map(|kernel| Json(kernel.view()))
+        .ok_or(ApiError::KernelNotFound)
+}
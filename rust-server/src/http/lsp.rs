@@ -0,0 +1,473 @@
+use axum::{
+    extract::{
+        ConnectInfo, Query, State,
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade, close_code},
+    },
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+use futures::{SinkExt, stream::StreamExt};
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use crate::controllers::analyzer::AnalyzerSlot;
+use crate::file::project::{ProjectBlock, write_module_sources};
+use crate::file::workspace::mark_workspace_used;
+use crate::file::{collect_workspace_dependencies, setup_user_env, write_manifest_dependencies};
+use crate::http::identity::{RequestIdentity, resolve_ws_identity};
+use crate::http::project::load_rust_blocks;
+use crate::http::verify_source;
+use crate::models::error::ApiError;
+use crate::models::execution::RunMode;
+use crate::models::state::AppState;
+
+/// Root the editor uses for workspace files. Server paths never reach it.
+const VIRTUAL_ROOT: &str = "file:///workspace";
+
+const MAX_MESSAGE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Requests and notifications passed on to the analyzer. Anything else, such
+/// as commands that run cargo or change its configuration, is refused.
+const FORWARDED_PREFIXES: &[&str] = &[
+    "textDocument/",
+    "completionItem/",
+    "codeAction/",
+    "codeLens/",
+    "inlayHint/",
+    "$/",
+];
+const FORWARDED_METHODS: &[&str] = &[
+    "initialize",
+    "initialized",
+    "shutdown",
+    "exit",
+    "workspace/symbol",
+    "window/workDoneProgress/cancel",
+    "rust-analyzer/expandMacro",
+];
+
+#[derive(Deserialize)]
+pub struct LspQuery {
+    #[serde(default)]
+    notebook_id: Option<Uuid>,
+}
+
+pub async fn lsp_request(
+    ws: WebSocketUpgrade,
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(query): Query<LspQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let identity = resolve_ws_identity(addr, &headers).await;
+
+    let blocks = match query.notebook_id {
+        Some(notebook_id) => Some(load_rust_blocks(&state, &identity, notebook_id).await?),
+        None => None,
+    };
+    let slot = state.analyzers.acquire(identity.owner)?;
+
+    Ok(ws
+        .protocols(["access_token"])
+        .on_upgrade(move |socket| {
+            handle_lsp(socket, state, identity, query.notebook_id, blocks, slot)
+        })
+        .into_response())
+}
+
+async fn handle_lsp(
+    socket: WebSocket,
+    state: Arc<AppState>,
+    identity: RequestIdentity,
+    notebook_id: Option<Uuid>,
+    blocks: Option<Vec<ProjectBlock>>,
+    _slot: AnalyzerSlot,
+) {
+    let (mut sender, mut receiver) = socket.split();
+    let _lease = state.workspaces.lease(identity.owner).await;
+
+    println!("--------------------------------------------------");
+    println!(
+        "LOG: Iniciando rust-analyzer para {} (IP: {})",
+        identity.owner.key(),
+        identity.client_ip
+    );
+
+    let workspace = setup_user_env(&identity.owner.key(), notebook_id).await;
+    let workspace = match tokio::fs::canonicalize(&workspace).await {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("ERRO: Workspace {:?} inacessível: {}", workspace, e);
+            close_with(&mut sender, close_code::ERROR, "Workspace indisponível.").await;
+            return;
+        }
+    };
+    if let Some(blocks) = &blocks {
+        write_blocks(&workspace, blocks).await;
+    }
+
+    let config = state.analyzers.config().clone();
+    let mut child = match state.analyzers.spawn(&workspace).await {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("ERRO: Falha ao iniciar rust-analyzer: {}", e);
+            close_with(
+                &mut sender,
+                close_code::ERROR,
+                "rust-analyzer indisponível neste servidor.",
+            )
+            .await;
+            return;
+        }
+    };
+    let (Some(mut stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+        let _ = child.kill().await;
+        close_with(
+            &mut sender,
+            close_code::ERROR,
+            "Falha ao iniciar rust-analyzer.",
+        )
+        .await;
+        return;
+    };
+
+    let (server_tx, mut server_rx) = mpsc::unbounded_channel::<String>();
+    let reader = tokio::spawn(async move {
+        let mut stdout = BufReader::new(stdout);
+        loop {
+            match read_message(&mut stdout).await {
+                Ok(Some(message)) => {
+                    if server_tx.send(message).is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    eprintln!("ERRO: Mensagem inválida do rust-analyzer: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+
+    let mut bridge = LspBridge::new(&workspace);
+    let idle_timeout = Duration::from_secs(config.idle_timeout_secs);
+    let mut last_activity = Instant::now();
+    let mut sync = tokio::time::interval(Duration::from_secs(config.sync_interval_secs));
+    sync.tick().await;
+
+    loop {
+        tokio::select! {
+            message = server_rx.recv() => {
+                let Some(message) = message else {
+                    close_with(&mut sender, close_code::ERROR, "O rust-analyzer foi encerrado.")
+                        .await;
+                    break;
+                };
+                if let Some(json) = bridge.server_message(&message)
+                    && sender.send(Message::Text(json.into())).await.is_err()
+                {
+                    break;
+                }
+            }
+            message = receiver.next() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    last_activity = Instant::now();
+                    match bridge.client_message(&text) {
+                        ClientAction::Forward(json) => {
+                            if let Err(e) = write_message(&mut stdin, &json).await {
+                                eprintln!("ERRO: Falha ao enviar mensagem ao rust-analyzer: {}", e);
+                                break;
+                            }
+                        }
+                        ClientAction::Reply(json) => {
+                            if sender.send(Message::Text(json.into())).await.is_err() {
+                                break;
+                            }
+                        }
+                        ClientAction::Drop => {}
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+            _ = tokio::time::sleep_until(last_activity + idle_timeout) => {
+                println!("LOG: Encerrando rust-analyzer inativo de {}", identity.owner.key());
+                state.analyzers.record_idle_shutdown();
+                close_with(&mut sender, close_code::NORMAL, "Encerrado por inatividade.").await;
+                break;
+            }
+            _ = sync.tick(), if notebook_id.is_some() => {
+                if let Some(notebook_id) = notebook_id {
+                    sync_notebook_blocks(&state, &identity, notebook_id, &workspace).await;
+                }
+            }
+        }
+    }
+
+    reader.abort();
+    let _ = child.kill().await;
+    let _ = sender.close().await;
+    println!("LOG: rust-analyzer de {} encerrado", identity.owner.key());
+}
+
+async fn close_with<S>(sender: &mut S, code: u16, reason: &str)
+where
+    S: SinkExt<Message> + Unpin,
+{
+    let _ = sender
+        .send(Message::Close(Some(CloseFrame {
+            code,
+            reason: reason.into(),
+        })))
+        .await;
+}
+
+async fn sync_notebook_blocks(
+    state: &AppState,
+    identity: &RequestIdentity,
+    notebook_id: Uuid,
+    workspace: &Path,
+) {
+    // A build reads these files; the next tick catches up once it is done.
+    if state.scheduler.is_running(&identity.owner) {
+        return;
+    }
+
+    match load_rust_blocks(state, identity, notebook_id).await {
+        Ok(blocks) => write_blocks(workspace, &blocks).await,
+        Err(e) => eprintln!(
+            "ERRO: Falha ao carregar blocos do notebook {}: {}",
+            notebook_id, e
+        ),
+    }
+}
+
+/// Writes the notebook's module blocks the policy accepts, so the analyzer
+/// sees the same crate a build would.
+async fn write_blocks(workspace: &Path, blocks: &[ProjectBlock]) {
+    let accepted: Vec<ProjectBlock> = blocks
+        .iter()
+        .filter(|b| verify_source(&b.code, RunMode::Run).is_none())
+        .cloned()
+        .collect();
+
+    match write_module_sources(workspace, &accepted).await {
+        Ok(true) => {
+            let dependencies = collect_workspace_dependencies(&workspace.join("src")).await;
+            if let Err(e) = write_manifest_dependencies(workspace, &dependencies).await {
+                eprintln!("ERRO: Falha ao configurar dependências: {}", e);
+            }
+        }
+        Ok(false) => {}
+        Err(e) => eprintln!(
+            "ERRO: Falha ao sincronizar blocos com {:?}: {}",
+            workspace, e
+        ),
+    }
+    mark_workspace_used(workspace).await;
+}
+
+enum ClientAction {
+    Forward(String),
+    Reply(String),
+    Drop,
+}
+
+/// Rewrites the JSON-RPC traffic between the editor and the analyzer: maps
+/// [`VIRTUAL_ROOT`] to the workspace, refuses methods outside the allowlist
+/// and pins the analyzer's settings so the editor cannot turn on build
+/// scripts, proc macros or custom commands.
+struct LspBridge {
+    workspace_uri: String,
+    /// Ids of `workspace/configuration` requests the editor has to answer.
+    config_requests: HashSet<String>,
+}
+
+impl LspBridge {
+    fn new(workspace: &Path) -> Self {
+        Self {
+            workspace_uri: format!("file://{}", workspace.display()),
+            config_requests: HashSet::new(),
+        }
+    }
+
+    fn client_message(&mut self, text: &str) -> ClientAction {
+        let mut message: Value = match serde_json::from_str(text) {
+            Ok(message) => message,
+            Err(e) => {
+                return ClientAction::Reply(error_response(
+                    Value::Null,
+                    -32700,
+                    &format!("JSON inválido: {}", e),
+                ));
+            }
+        };
+
+        match message.get("method").and_then(Value::as_str) {
+            Some(method) if !is_forwarded(method) => {
+                return match message.get("id") {
+                    Some(id) => ClientAction::Reply(error_response(
+                        id.clone(),
+                        -32601,
+                        &format!("Método não suportado: {}", method),
+                    )),
+                    None => ClientAction::Drop,
+                };
+            }
+            Some("initialize") => {
+                if let Some(params) = message.get_mut("params").and_then(Value::as_object_mut) {
+                    params.insert("processId".into(), Value::Null);
+                    params.remove("rootPath");
+                    params.insert("rootUri".into(), json!(VIRTUAL_ROOT));
+                    params.insert(
+                        "workspaceFolders".into(),
+                        json!([{ "uri": VIRTUAL_ROOT, "name": "workspace" }]),
+                    );
+                    params.insert("initializationOptions".into(), analyzer_settings());
+                }
+            }
+            Some(_) => {}
+            None => {
+                let answers_config = message
+                    .get("id")
+                    .is_some_and(|id| self.config_requests.remove(&id.to_string()));
+                if answers_config
+                    && let Some(items) = message.get("result").and_then(Value::as_array)
+                {
+                    let pinned = vec![analyzer_settings(); items.len()];
+                    message["result"] = Value::Array(pinned);
+                }
+            }
+        }
+
+        rewrite_uris(&mut message, VIRTUAL_ROOT, &self.workspace_uri);
+        ClientAction::Forward(message.to_string())
+    }
+
+    fn server_message(&mut self, text: &str) -> Option<String> {
+        let mut message: Value = serde_json::from_str(text).ok()?;
+
+        if message.get("method").and_then(Value::as_str) == Some("workspace/configuration")
+            && let Some(id) = message.get("id")
+        {
+            self.config_requests.insert(id.to_string());
+        }
+
+        rewrite_uris(&mut message, &self.workspace_uri, VIRTUAL_ROOT);
+        Some(message.to_string())
+    }
+}
+
+fn is_forwarded(method: &str) -> bool {
+    FORWARDED_METHODS.contains(&method)
+        || FORWARDED_PREFIXES
+            .iter()
+            .any(|prefix| method.starts_with(prefix))
+}
+
+/// The whole `rust-analyzer` configuration; nothing the editor sends is kept.
+fn analyzer_settings() -> Value {
+    json!({
+        "cargo": {
+            "buildScripts": { "enable": false },
+            "autoreload": true,
+        },
+        "procMacro": { "enable": false },
+        "checkOnSave": false,
+        "files": { "watcher": "server" },
+    })
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+    .to_string()
+}
+
+/// Replaces the `from` prefix of every URI in `value`, including the keys of
+/// maps such as `WorkspaceEdit.changes`.
+fn rewrite_uris(value: &mut Value, from: &str, to: &str) {
+    let rewrite = |s: &str| -> Option<String> {
+        let rest = s.strip_prefix(from)?;
+        (rest.is_empty() || rest.starts_with('/')).then(|| format!("{}{}", to, rest))
+    };
+
+    match value {
+        Value::String(s) => {
+            if let Some(rewritten) = rewrite(s) {
+                *s = rewritten;
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                rewrite_uris(item, from, to);
+            }
+        }
+        Value::Object(map) => {
+            let entries = std::mem::take(map);
+            *map = entries
+                .into_iter()
+                .map(|(key, mut item)| {
+                    rewrite_uris(&mut item, from, to);
+                    (rewrite(&key).unwrap_or(key), item)
+                })
+                .collect::<Map<String, Value>>();
+        }
+        _ => {}
+    }
+}
+
+async fn read_message<R: AsyncBufRead + Unpin>(reader: &mut R) -> std::io::Result<Option<String>> {
+    let mut length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let length = length
+        .filter(|length| *length <= MAX_MESSAGE_BYTES)
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "cabeçalho Content-Length ausente ou inválido",
+            )
+        })?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, body: &str) -> std::io::Result<()> {
+    writer
+        .write_all(format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes())
+        .await?;
+    writer.write_all(body.as_bytes()).await?;
+    writer.flush().await
+}
@@ -1,52 +1,439 @@
 use axum::extract::ConnectInfo;
 use axum::extract::Json;
+use axum::extract::Path as PathParam;
+use axum::extract::State;
 use axum::http::HeaderMap;
+use axum::http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use axum::response::{IntoResponse, Response};
+use diesel::{ExpressionMethods, QueryDsl};
+use diesel_async::{AsyncPgConnection, RunQueryDsl, pooled_connection::deadpool::Pool};
+use serde::Serialize;
 use std::net::SocketAddr;
-use tokio::process::Command;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub mod audit;
+pub mod identity;
+pub mod jobs;
+pub mod kernel;
+pub mod lsp;
+pub mod project;
+pub mod stream;
 
 use crate::CodeRequest;
 use crate::CodeResponse;
-use crate::controllers::utils::extract_module_name;
-use crate::file::register_log;
+use crate::controllers::analyzer::AnalyzerMetrics;
+use crate::controllers::jobs::JobMetrics;
+use crate::controllers::jwt::extract_admin_claims_from_header;
+use crate::controllers::kernel::KernelMetrics;
+use crate::controllers::limiter::LimiterMetrics;
+use crate::controllers::scheduler::SchedulerMetrics;
+use crate::controllers::utils::{extract_dependencies, extract_module_name};
+use crate::file::bench::{BenchReport, BenchSettings, VariantReport};
+use crate::file::cache::{BuildCache, CacheInfo, CacheStats, output_key};
+use crate::file::diagnostics::{CompilerMessages, Diagnostic, parse_cargo_messages};
+use crate::file::explorer::{EmitKind, ExploreSettings, ExplorerOutput};
+use crate::file::miri::{miri_runtime, run_miri};
+use crate::file::project::{ProjectSource, read_project_sources, write_project_sources};
+use crate::file::python::{python_runtime, run_python, setup_python_env};
 use crate::file::run_safe_bin;
-use crate::file::setup_user_env;
-use crate::sec::verify_code;
+use crate::file::stats::ExecutionStats;
+use crate::file::testing::{parse_test_output, test_binary_input};
+use crate::file::toolchain::{
+    BuildSettings, BuildTarget, SupportedToolchains, supported_toolchains, write_manifest_edition,
+};
+use crate::file::wasm::{WasmModule, is_cache_key, is_wasm_module};
+use crate::file::workspace::WorkspaceMetrics;
+use crate::file::{
+    WORKSPACE_PACKAGE_NAME, collect_workspace_dependencies, run_cargo, run_cargo_with_env,
+    setup_bench_env, setup_miri_env, setup_user_env, write_manifest_dependencies,
+};
+use crate::http::audit::record_audit;
+use crate::http::identity::{RequestIdentity, resolve_identity};
+use crate::http::kernel::run_kernel_block;
+use crate::models::error::ApiError;
+use crate::models::execution::{
+    ExecutionContext, ExecutionEvent, ExecutionPhase, ModeSettings, OutputStream, RunMode,
+};
+use crate::models::execution_result::{NewExecutionResult, code_hash, insert_execution_result};
+use crate::models::notebook::{Language, NotebookPermission, check_permission};
+use crate::models::state::AppState;
+use crate::schema::blocks;
+use crate::sec::input::RunInput;
+use crate::sec::sandbox::Termination;
+use crate::sec::{
+    format_violations, verify_code, verify_dependencies, verify_input, verify_kernel_code,
+    verify_miri_code,
+};
 
 pub async fn verify_request(
+    State(state): State<Arc<AppState>>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
     Json(payload): Json<CodeRequest>,
-) -> Json<CodeResponse> {
-    let addr = addr.ip();
-    let ip = headers
-        .get("x-forwarded-for")
-        .and_then(|h| h.to_str().ok())
-        .unwrap_or(&addr.to_string())
-        .to_string();
-
-    let user_agent = headers
-        .get("user-agent")
-        .and_then(|h| h.to_str().ok())
-        .unwrap_or("Unknown Agent")
-        .to_string();
+) -> Result<Json<CodeResponse>, ApiError> {
+    let identity = resolve_identity(addr, &headers).await;
 
     println!("--------------------------------------------------");
-    println!("LOG: Nova requisição de IP: {}", ip);
+    println!(
+        "LOG: Nova requisição de {} (IP: {})",
+        identity.owner.key(),
+        identity.client_ip
+    );
+
+    let mut response = run_code_request(
+        &state,
+        &identity,
+        &payload,
+        &ExecutionContext::default(),
+        "run",
+    )
+    .await?;
+    response.anonymous_session = identity.issued_session;
+
+    Ok(Json(response))
+}
+
+#[derive(Serialize)]
+pub struct RunMetrics {
+    scheduler: SchedulerMetrics,
+    limiter: LimiterMetrics,
+    cache: CacheStats,
+    workspaces: WorkspaceMetrics,
+    analyzers: AnalyzerMetrics,
+    jobs: JobMetrics,
+    kernels: KernelMetrics,
+}
+
+pub async fn run_metrics(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<RunMetrics>, ApiError> {
+    extract_admin_claims_from_header(&headers).await?;
+
+    Ok(Json(RunMetrics {
+        scheduler: state.scheduler.metrics(),
+        limiter: state.limiter.metrics(),
+        cache: state.build_cache.stats(),
+        workspaces: state.workspaces.metrics(),
+        analyzers: state.analyzers.metrics(),
+        jobs: state.jobs.metrics(),
+        kernels: state.kernels.metrics(),
+    }))
+}
+
+pub async fn run_toolchains() -> Json<SupportedToolchains> {
+    Json(supported_toolchains().await)
+}
+
+pub async fn wasm_module(
+    State(state): State<Arc<AppState>>,
+    PathParam(key): PathParam<String>,
+) -> Result<Response, ApiError> {
+    if !is_cache_key(&key) {
+        return Err(ApiError::InvalidData);
+    }
+
+    let bytes = state
+        .build_cache
+        .artifact(&key)
+        .await
+        .filter(|bytes| is_wasm_module(bytes))
+        .ok_or(ApiError::ModuleNotFound)?;
+
+    Ok((
+        [
+            (CONTENT_TYPE, "application/wasm"),
+            (CACHE_CONTROL, "public, max-age=31536000, immutable"),
+        ],
+        bytes,
+    )
+        .into_response())
+}
+
+pub async fn run_code_request(
+    state: &AppState,
+    identity: &RequestIdentity,
+    payload: &CodeRequest,
+    ctx: &ExecutionContext,
+    endpoint: &str,
+) -> Result<CodeResponse, ApiError> {
+    let outcome = execute_code_request(state, identity, payload, ctx).await;
+    record_audit(&state.pool, identity, endpoint, &payload.code, &outcome);
+    outcome
+}
+
+async fn execute_code_request(
+    state: &AppState,
+    identity: &RequestIdentity,
+    payload: &CodeRequest,
+    ctx: &ExecutionContext,
+) -> Result<CodeResponse, ApiError> {
+    let reservation = state.limiter.admit(identity)?;
+
+    if let Some(rejected) = verify_payload(payload).await {
+        if !rejected.violations.is_empty() {
+            state.limiter.record_violation(identity);
+        }
+        return Ok(rejected);
+    }
+
+    ctx.emit(ExecutionEvent::phase(ExecutionPhase::Queued));
+
+    let Some(_permit) = state.scheduler.acquire(identity.owner, ctx).await? else {
+        ctx.emit(ExecutionEvent::phase(ExecutionPhase::Cancelled));
+        return Ok(cancelled_response("".into()));
+    };
+    let _lease = state.workspaces.lease(identity.owner).await;
+
+    let started = Instant::now();
+    let (response, toolchain_version) = match payload.language {
+        Language::Python => (
+            run_python_block(identity, payload, ctx).await,
+            python_runtime()
+                .await
+                .map(|runtime| runtime.version.clone())
+                .unwrap_or_default(),
+        ),
+        _ => {
+            let mut response = match payload.mode {
+                RunMode::Kernel => run_kernel_block(state, identity, payload, ctx).await?,
+                _ => build_and_run(state, identity, payload, ctx).await,
+            };
+            let build = payload.build.for_mode(payload.mode).describe().await;
+            let toolchain = build.toolchain.clone();
+            response.build = Some(build);
+            (response, toolchain)
+        }
+    };
+    state
+        .limiter
+        .charge_build_time(reservation, started.elapsed());
+
+    let phase = match response.termination {
+        Some(Termination::Cancelled) => ExecutionPhase::Cancelled,
+        _ => ExecutionPhase::Exited,
+    };
+    ctx.emit(ExecutionEvent::phase(phase));
 
-    let safe_ip = ip.replace(|c: char| !c.is_alphanumeric(), "_");
+    if let (Some(block_id), Some(notebook_id), Some(user_id)) = (
+        payload.block_id,
+        payload.notebook_id,
+        identity.owner.user_id(),
+    ) && phase == ExecutionPhase::Exited
+    {
+        let result = NewExecutionResult {
+            block_id,
+            notebook_id,
+            user_id: Some(user_id),
+            stdout: response.stdout.clone(),
+            stderr: response.stderr.clone(),
+            exit_code: match response.termination {
+                Some(Termination::Exited { code }) => Some(code),
+                _ => None,
+            },
+            termination: response
+                .termination
+                .as_ref()
+                .and_then(|t| serde_json::to_value(t).ok()),
+            duration_ms: started.elapsed().as_millis() as i64,
+            toolchain_version,
+            code_hash: code_hash(&payload.code),
+        };
+        tokio::spawn(record_block_result(state.pool.clone(), result));
+    }
+
+    Ok(response)
+}
 
-    if let Err(e) = register_log(&payload.code, &safe_ip, &ip, &user_agent).await {
-        eprintln!("ERRO: Falha no log de arquivo: {}", e);
+// Only members who can edit the notebook add to a block's history.
+async fn record_block_result(pool: Pool<AsyncPgConnection>, result: NewExecutionResult) {
+    match check_permission(&pool, result.user_id, result.notebook_id).await {
+        Ok(NotebookPermission::OwnerOrTeam) => {}
+        _ => return,
     }
 
-    if let Err(msg) = verify_code(&payload.code) {
-        return Json(CodeResponse {
+    let mut conn = match pool.get().await {
+        Ok(conn) => conn,
+        Err(e) => {
+            eprintln!("ERRO: Falha ao obter conexão para salvar resultado: {}", e);
+            return;
+        }
+    };
+
+    let belongs = blocks::table
+        .filter(blocks::id.eq(result.block_id))
+        .filter(blocks::notebook_id.eq(result.notebook_id))
+        .count()
+        .get_result::<i64>(&mut conn)
+        .await;
+    if !matches!(belongs, Ok(1)) {
+        return;
+    }
+
+    if let Err(e) = insert_execution_result(&mut conn, &result).await {
+        eprintln!(
+            "ERRO: Falha ao salvar resultado do bloco {}: {}",
+            result.block_id, e
+        );
+    }
+}
+
+fn cancelled_response(stdout: String) -> CodeResponse {
+    CodeResponse {
+        stdout,
+        stderr: Termination::Cancelled.describe().unwrap_or_default(),
+        termination: Some(Termination::Cancelled),
+        ..Default::default()
+    }
+}
+
+async fn verify_payload(payload: &CodeRequest) -> Option<CodeResponse> {
+    let unsupported = match (payload.language, payload.mode) {
+        (Language::Typescript, _) => Some("Blocos TypeScript são executados no navegador."),
+        (Language::Python, RunMode::Test) => {
+            Some("O modo de testes está disponível apenas para Rust.")
+        }
+        (Language::Python, RunMode::Explore) => {
+            Some("O modo explorador está disponível apenas para Rust.")
+        }
+        (Language::Python, RunMode::Miri) => Some("O modo Miri está disponível apenas para Rust."),
+        (Language::Python, RunMode::Bench) => {
+            Some("O modo benchmark está disponível apenas para Rust.")
+        }
+        (Language::Python, RunMode::Kernel) => {
+            Some("O modo kernel está disponível apenas para Rust.")
+        }
+        (Language::Rust, mode) => unsupported_target(mode, &payload.build),
+        _ => None,
+    };
+    if let Some(message) = unsupported {
+        return Some(CodeResponse {
             stdout: "".into(),
-            stderr: msg,
+            stderr: message.into(),
+            ..Default::default()
         });
     }
 
-    let project_path = setup_user_env(&safe_ip).await;
+    // The code policy and build settings only make sense for Rust; Python is
+    // confined by the sandbox alone, which hides the server's files from it.
+    if payload.language == Language::Rust
+        && let Some(rejected) = verify_source(&payload.code, payload.mode)
+    {
+        return Some(rejected);
+    }
+
+    if let Err(message) = verify_input(&payload.input) {
+        return Some(CodeResponse {
+            stdout: "".into(),
+            stderr: message,
+            ..Default::default()
+        });
+    }
+
+    if payload.language == Language::Rust
+        && let Err(message) = payload.build.validate().await
+    {
+        return Some(CodeResponse {
+            stdout: "".into(),
+            stderr: message,
+            ..Default::default()
+        });
+    }
+
+    if payload.mode == RunMode::Bench {
+        return verify_bench(&payload.modes.bench);
+    }
+
+    // The kernel cannot add crates: that takes evcxr commands, which are
+    // refused.
+    if payload.mode == RunMode::Kernel && !extract_dependencies(&payload.code).is_empty() {
+        return Some(CodeResponse {
+            stdout: "".into(),
+            stderr:
+                "O modo kernel usa apenas a biblioteca padrão; dependências não estão disponíveis."
+                    .into(),
+            ..Default::default()
+        });
+    }
+
+    None
+}
+
+/// The variant to compare against is user code too, and goes through the
+/// same policy as the block itself.
+fn verify_bench(bench: &BenchSettings) -> Option<CodeResponse> {
+    if let Err(message) = bench.validate() {
+        return Some(CodeResponse {
+            stdout: "".into(),
+            stderr: message,
+            ..Default::default()
+        });
+    }
+
+    let compare = bench.compare.as_deref()?;
+    let mut rejected = verify_source(compare, RunMode::Bench)?;
+    rejected.stderr = format!("Variante B:\n{}", rejected.stderr);
+    Some(rejected)
+}
+
+/// WebAssembly builds are handed to the browser, so nothing that has to run
+/// them on the server can target it.
+fn unsupported_target(mode: RunMode, settings: &BuildSettings) -> Option<&'static str> {
+    if settings.target.is_native() {
+        return None;
+    }
+    match mode {
+        RunMode::Test => Some("O modo de testes não está disponível para WebAssembly."),
+        RunMode::Miri => Some("O modo Miri não está disponível para WebAssembly."),
+        RunMode::Bench => Some("O modo benchmark não está disponível para WebAssembly."),
+        RunMode::Kernel => Some("O modo kernel não está disponível para WebAssembly."),
+        RunMode::Run | RunMode::Explore => None,
+    }
+}
+
+/// Miri mode gets a looser policy: nothing it runs executes natively. Kernel
+/// blocks are function bodies rather than whole files.
+fn verify_source(code: &str, mode: RunMode) -> Option<CodeResponse> {
+    let checked = match mode {
+        RunMode::Miri => verify_miri_code(code),
+        RunMode::Kernel => verify_kernel_code(code),
+        _ => verify_code(code),
+    };
+    if let Err(violations) = checked {
+        return Some(CodeResponse {
+            stdout: "".into(),
+            stderr: format_violations(&violations),
+            violations,
+            ..Default::default()
+        });
+    }
+
+    if let Err(violations) = verify_dependencies(code) {
+        return Some(CodeResponse {
+            stdout: "".into(),
+            stderr: format_violations(&violations),
+            violations,
+            ..Default::default()
+        });
+    }
+
+    None
+}
+
+async fn build_and_run(
+    state: &AppState,
+    identity: &RequestIdentity,
+    payload: &CodeRequest,
+    ctx: &ExecutionContext,
+) -> CodeResponse {
+    let owner_key = identity.owner.key();
+
+    let project_path = match payload.mode {
+        RunMode::Miri => setup_miri_env(&owner_key, payload.notebook_id, false).await,
+        _ => setup_user_env(&owner_key, payload.notebook_id).await,
+    };
     let src_path = project_path.join("src");
 
     let module_name = extract_module_name(&payload.code);
@@ -55,110 +442,800 @@ pub async fn verify_request(
         None => ("main.rs".to_string(), true),
     };
 
+    if !is_main && payload.mode == RunMode::Bench {
+        return CodeResponse {
+            stdout: "".into(),
+            stderr: "O modo benchmark mede o bloco principal; execute-o a partir dele.".into(),
+            ..Default::default()
+        };
+    }
+
     let file_path = src_path.join(&file_name);
 
     if let Err(e) = tokio::fs::write(&file_path, &payload.code).await {
-        return Json(CodeResponse {
+        return CodeResponse {
             stdout: "".into(),
             stderr: format!("Erro ao salvar arquivo {}: {}", file_name, e),
-        });
+            ..Default::default()
+        };
     }
 
-    if !is_main {
-        let check_output = Command::new("cargo")
-            .current_dir(&project_path)
-            .arg("check")
-            .output()
-            .await;
+    let dependencies = collect_workspace_dependencies(&src_path).await;
+    if let Err(e) = write_manifest_dependencies(&project_path, &dependencies).await {
+        return CodeResponse {
+            stdout: "".into(),
+            stderr: format!("Erro ao configurar dependências: {}", e),
+            ..Default::default()
+        };
+    }
+
+    if let Err(e) = write_manifest_edition(&project_path, payload.build.edition).await {
+        return CodeResponse {
+            stdout: "".into(),
+            stderr: format!("Erro ao configurar a edição: {}", e),
+            ..Default::default()
+        };
+    }
+
+    if ctx.is_cancelled() {
+        return cancelled_response("".into());
+    }
+
+    if !is_main && matches!(payload.mode, RunMode::Run | RunMode::Miri) {
+        ctx.emit(ExecutionEvent::phase(ExecutionPhase::Compiling));
+        let check_started = Instant::now();
+        let check_output = run_cargo(
+            &project_path,
+            &["check", "--message-format=json"],
+            &payload.build,
+            ctx,
+        )
+        .await;
 
         return match check_output {
-            Ok(out) => Json(CodeResponse {
-                stdout: format!(
-                    "Módulo '{}' salvo.\nStdOut Check: {}",
-                    file_name,
-                    String::from_utf8_lossy(&out.stdout)
-                ),
-                stderr: String::from_utf8_lossy(&out.stderr).to_string(),
-            }),
-            Err(e) => Json(CodeResponse {
+            Ok(Some(out)) => {
+                let messages = parse_cargo_messages(&out.stdout, false);
+                CodeResponse {
+                    stdout: format!("Módulo '{}' salvo.", file_name),
+                    stderr: if messages.rendered.is_empty() {
+                        out.stderr
+                    } else {
+                        messages.rendered
+                    },
+                    diagnostics: messages.diagnostics,
+                    stats: Some(
+                        ExecutionStats::default().with_compile_time(Some(check_started.elapsed())),
+                    ),
+                    ..Default::default()
+                }
+            }
+            Ok(None) => cancelled_response("".into()),
+            Err(e) => CodeResponse {
                 stdout: "".into(),
                 stderr: format!("Erro ao verificar módulo: {}", e),
-            }),
+                ..Default::default()
+            },
         };
     }
 
-    eprintln!("LOG: Executando cargo build com JSON output...");
+    build_project(
+        state,
+        &project_path,
+        payload.mode,
+        &payload.modes,
+        &payload.input,
+        &payload.build,
+        ctx,
+    )
+    .await
+}
 
-    let compile_output = Command::new("cargo")
-        .current_dir(&project_path)
-        .arg("build")
-        .arg("--message-format=json")
-        .arg("-q")
-        .output()
-        .await;
+async fn run_python_block(
+    identity: &RequestIdentity,
+    payload: &CodeRequest,
+    ctx: &ExecutionContext,
+) -> CodeResponse {
+    if python_runtime().await.is_none() {
+        return CodeResponse {
+            stdout: "".into(),
+            stderr: "Execução de Python indisponível neste servidor.".into(),
+            ..Default::default()
+        };
+    }
 
-    match compile_output {
-        Ok(out) => {
-            let stdout_str = String::from_utf8_lossy(&out.stdout);
+    let workspace = setup_python_env(&identity.owner.key(), payload.notebook_id).await;
+    if ctx.is_cancelled() {
+        return cancelled_response("".into());
+    }
 
-            let mut formatted_errors = String::new();
-            let mut exe_path: Option<String> = None;
+    ctx.emit(ExecutionEvent::phase(ExecutionPhase::Running));
+    let run = run_python(&workspace, &payload.code, &payload.input, ctx).await;
 
-            for line in stdout_str.lines() {
-                if let Ok(val) = serde_json::from_str::<serde_json::Value>(line) {
-                    if let Some(message) = val.get("message") {
-                        if let Some(rendered) = message.get("rendered").and_then(|r| r.as_str()) {
-                            formatted_errors.push_str(rendered);
-                            formatted_errors.push('\n');
-                        }
-                    }
+    let mut stderr = run.stderr;
+    if let Some(msg) = run.termination.describe() {
+        stderr.push_str(&msg);
+    }
+
+    CodeResponse {
+        stdout: run.stdout,
+        stderr,
+        termination: Some(run.termination),
+        stats: Some(run.stats),
+        ..Default::default()
+    }
+}
+
+/// The kernel keeps state between single blocks; a whole notebook is one
+/// program and has nothing to keep.
+const KERNEL_BLOCKS_ONLY: &str =
+    "O modo kernel executa blocos individuais, não o notebook inteiro.";
+
+/// Compiles the crate at `project_path` and runs it, or its tests, reusing
+/// cached binaries and deterministic outputs where possible. Explorer mode
+/// only compiles, Miri mode interprets instead of running natively, and
+/// benchmark mode times an instrumented copy.
+async fn build_project(
+    state: &AppState,
+    project_path: &Path,
+    mode: RunMode,
+    modes: &ModeSettings,
+    input: &RunInput,
+    settings: &BuildSettings,
+    ctx: &ExecutionContext,
+) -> CodeResponse {
+    match mode {
+        RunMode::Test => return run_tests(project_path, input, settings, ctx).await,
+        RunMode::Explore => {
+            return explore_project(project_path, &modes.explore, settings, ctx).await;
+        }
+        RunMode::Miri => return run_under_miri(project_path, input, settings, ctx).await,
+        RunMode::Bench => {
+            return run_benchmark(
+                project_path,
+                &modes.bench,
+                input,
+                &settings.for_mode(mode),
+                ctx,
+            )
+            .await;
+        }
+        RunMode::Kernel => {
+            return CodeResponse {
+                stdout: "".into(),
+                stderr: KERNEL_BLOCKS_ONLY.into(),
+                ..Default::default()
+            };
+        }
+        RunMode::Run => {}
+    }
+
+    let cache = &state.build_cache;
+    let build_key = cache.build_key(project_path, settings).await;
+    let output_key = build_key
+        .as_deref()
+        .filter(|_| settings.target.is_native())
+        .filter(|_| input.stdin.is_none() && !ctx.is_interactive())
+        .map(|key| output_key(key, input));
+
+    let cached_binary = match &build_key {
+        Some(key) => cache.binary(key).await,
+        None => None,
+    };
+    let binary_hit = cached_binary.is_some();
 
-                    if line.contains(r#""executable""#) {
-                        if let Some(exec) = val.get("executable").and_then(|v| v.as_str()) {
-                            exe_path = Some(exec.to_string());
-                        }
+    if let (Some(key), Some(out_key), Some(binary)) = (&build_key, &output_key, &cached_binary)
+        && let Some(cached) = cache.cached_output(key, out_key).await
+    {
+        eprintln!("LOG: Saída reaproveitada do cache ({})", key);
+        ctx.emit(ExecutionEvent::phase(ExecutionPhase::Running));
+        for (stream, data) in [
+            (OutputStream::Stdout, &cached.stdout),
+            (OutputStream::Stderr, &cached.stderr),
+        ] {
+            if !data.is_empty() {
+                ctx.emit(ExecutionEvent::output(stream, data.clone()));
+            }
+        }
+
+        let mut stderr = binary.warnings.clone();
+        if let Some(msg) = cached.termination.describe() {
+            stderr.push_str(&msg);
+        }
+
+        return CodeResponse {
+            stdout: cached.stdout,
+            stderr,
+            diagnostics: binary.diagnostics.clone(),
+            stats: Some(ExecutionStats::from_termination(&cached.termination)),
+            termination: Some(cached.termination),
+            cache: Some(CacheInfo {
+                key: key.clone(),
+                binary_hit: true,
+                output_hit: true,
+            }),
+            ..Default::default()
+        };
+    }
+
+    ctx.emit(ExecutionEvent::phase(ExecutionPhase::Compiling));
+
+    let mut compile_time = None;
+    let (exe_path, warnings, diagnostics, _pin) = match cached_binary {
+        Some(binary) => {
+            eprintln!("LOG: Binário reaproveitado do cache: {}", binary.path);
+            (
+                binary.path,
+                binary.warnings,
+                binary.diagnostics,
+                Some(binary.pin),
+            )
+        }
+        None => {
+            let compile_started = Instant::now();
+            let compiled = compile_main(project_path, settings, ctx).await;
+            compile_time = Some(compile_started.elapsed());
+            match compiled {
+                Ok((path, messages)) => {
+                    if let Some(key) = &build_key {
+                        cache
+                            .store_binary(key, &path, &messages.rendered, &messages.diagnostics)
+                            .await;
                     }
+                    (path, messages.rendered, messages.diagnostics, None)
+                }
+                Err(mut response) => {
+                    response.stats =
+                        Some(ExecutionStats::default().with_compile_time(compile_time));
+                    return response;
                 }
             }
+        }
+    };
+
+    if !settings.target.is_native() {
+        return wasm_response(
+            cache,
+            settings.target,
+            build_key,
+            binary_hit,
+            warnings,
+            diagnostics,
+            compile_time,
+        )
+        .await;
+    }
+
+    ctx.emit(ExecutionEvent::phase(ExecutionPhase::Running));
+    let run = run_safe_bin(&exe_path, project_path, input, ctx).await;
+
+    if let (Some(key), Some(out_key)) = (&build_key, &output_key) {
+        cache.record_output(key, out_key, &run).await;
+    }
+
+    let mut stderr = warnings;
+    if let Some(msg) = run.termination.describe() {
+        stderr.push_str(&msg);
+    }
+
+    CodeResponse {
+        stdout: run.stdout,
+        stderr,
+        diagnostics,
+        stats: Some(run.stats.with_compile_time(compile_time)),
+        termination: Some(run.termination),
+        cache: build_key.map(|key| CacheInfo {
+            key,
+            binary_hit,
+            output_hit: false,
+        }),
+        ..Default::default()
+    }
+}
+
+/// Points the client at the cached module instead of running it.
+async fn wasm_response(
+    cache: &BuildCache,
+    target: BuildTarget,
+    build_key: Option<String>,
+    binary_hit: bool,
+    warnings: String,
+    diagnostics: Vec<Diagnostic>,
+    compile_time: Option<Duration>,
+) -> CodeResponse {
+    let size_bytes = match &build_key {
+        Some(key) => cache.artifact(key).await.map(|bytes| bytes.len() as u64),
+        None => None,
+    };
+    let (Some(key), Some(size_bytes)) = (build_key, size_bytes) else {
+        return CodeResponse {
+            stdout: "".into(),
+            stderr: format!(
+                "{}Erro ao armazenar o módulo WebAssembly compilado.",
+                warnings
+            ),
+            diagnostics,
+            stats: Some(ExecutionStats::default().with_compile_time(compile_time)),
+            ..Default::default()
+        };
+    };
+
+    CodeResponse {
+        stdout: "".into(),
+        stderr: warnings,
+        diagnostics,
+        stats: Some(ExecutionStats::default().with_compile_time(compile_time)),
+        wasm: Some(WasmModule::new(target, &key, size_bytes)),
+        cache: Some(CacheInfo {
+            key,
+            binary_hit,
+            output_hit: false,
+        }),
+        ..Default::default()
+    }
+}
 
-            if out.status.success() {
-                if let Some(path) = exe_path {
-                    eprintln!("LOG: Caminho do executável encontrado via JSON: {}", path);
-                    let (stdout, _) = run_safe_bin(&path).await;
+/// Builds the workspace binary, returning its path and the compiler
+/// warnings, or the response to send back when the build does not succeed.
+async fn compile_main(
+    project_path: &Path,
+    settings: &BuildSettings,
+    ctx: &ExecutionContext,
+) -> Result<(String, CompilerMessages), CodeResponse> {
+    eprintln!("LOG: Executando cargo build com JSON output...");
+
+    let compile_output = run_cargo(
+        project_path,
+        &["build", "--message-format=json", "-q"],
+        settings,
+        ctx,
+    )
+    .await;
 
-                    return Json(CodeResponse {
-                        stdout,
-                        stderr: formatted_errors,
-                    });
+    let out = match compile_output {
+        Ok(Some(out)) => out,
+        Ok(None) => return Err(cancelled_response("".into())),
+        Err(e) => {
+            return Err(CodeResponse {
+                stdout: "".into(),
+                stderr: format!("Erro ao invocar cargo: {}", e),
+                ..Default::default()
+            });
+        }
+    };
+
+    let mut messages = parse_cargo_messages(&out.stdout, false);
+
+    if out.success {
+        let path = match messages.executable.take() {
+            Some(path) => {
+                eprintln!("LOG: Caminho do executável encontrado via JSON: {}", path);
+                path
+            }
+            None => {
+                let fallback_name = if !settings.target.is_native() {
+                    format!("{}.wasm", WORKSPACE_PACKAGE_NAME)
+                } else if cfg!(windows) {
+                    format!("{}.exe", WORKSPACE_PACKAGE_NAME)
                 } else {
-                    let fallback_name = if cfg!(windows) {
-                        format!("app_{}.exe", safe_ip)
-                    } else {
-                        format!("app_{}", safe_ip)
-                    };
-                    let fallback_path = project_path.join("target/debug").join(fallback_name);
-                    let path_str = fallback_path.to_string_lossy().to_string();
-                    let (stdout, stderr) = run_safe_bin(&path_str).await;
-                    return Json(CodeResponse { stdout, stderr });
-                }
+                    WORKSPACE_PACKAGE_NAME.to_string()
+                };
+                let fallback_path = project_path
+                    .join("target")
+                    .join(settings.artifact_dir())
+                    .join(fallback_name);
+                fallback_path.to_string_lossy().to_string()
             }
+        };
+        return Ok((path, messages));
+    }
+
+    eprintln!("LOG: Compilação FALHOU.");
+    Err(compilation_failed(messages, out.stderr))
+}
+
+fn compilation_failed(messages: CompilerMessages, cargo_stderr: String) -> CodeResponse {
+    let final_stderr = if !messages.rendered.is_empty() {
+        messages.rendered
+    } else {
+        cargo_stderr
+    };
+
+    CodeResponse {
+        stdout: "".into(),
+        stderr: format!("Erro de Compilação:\n{}", final_stderr),
+        diagnostics: messages.diagnostics,
+        ..Default::default()
+    }
+}
+
+async fn run_tests(
+    project_path: &Path,
+    input: &RunInput,
+    settings: &BuildSettings,
+    ctx: &ExecutionContext,
+) -> CodeResponse {
+    ctx.emit(ExecutionEvent::phase(ExecutionPhase::Compiling));
+
+    let compile_started = Instant::now();
+    let compile_output = run_cargo(
+        project_path,
+        &["test", "--no-run", "--message-format=json", "-q"],
+        settings,
+        ctx,
+    )
+    .await;
+    let compile_time = Some(compile_started.elapsed());
+
+    let out = match compile_output {
+        Ok(Some(out)) => out,
+        Ok(None) => return cancelled_response("".into()),
+        Err(e) => {
+            return CodeResponse {
+                stdout: "".into(),
+                stderr: format!("Erro ao invocar cargo: {}", e),
+                ..Default::default()
+            };
+        }
+    };
+
+    let mut messages = parse_cargo_messages(&out.stdout, true);
+
+    let exe_path = match messages.executable.take() {
+        Some(path) if out.success => path,
+        _ => {
+            let mut response = compilation_failed(messages, out.stderr);
+            response.stats = Some(ExecutionStats::default().with_compile_time(compile_time));
+            return response;
+        }
+    };
+
+    ctx.emit(ExecutionEvent::phase(ExecutionPhase::Running));
+    let run = run_safe_bin(
+        &exe_path,
+        project_path,
+        &test_binary_input(input),
+        &ctx.detached(),
+    )
+    .await;
+
+    let interrupted = match run.termination {
+        Termination::Exited { .. } => None,
+        ref other => other.describe(),
+    };
+    let report = parse_test_output(&run.stdout, interrupted.clone());
+
+    let mut stderr = messages.rendered;
+    if let Some(msg) = interrupted {
+        stderr.push_str(&msg);
+    }
 
-            eprintln!("LOG: Compilação FALHOU.");
+    CodeResponse {
+        stdout: format!(
+            "Resultado dos testes: {} passaram; {} falharam; {} ignorados",
+            report.passed, report.failed, report.ignored
+        ),
+        stderr,
+        diagnostics: messages.diagnostics,
+        stats: Some(run.stats.with_compile_time(compile_time)),
+        termination: Some(run.termination),
+        tests: Some(report),
+        ..Default::default()
+    }
+}
+
+async fn explore_project(
+    project_path: &Path,
+    explore: &ExploreSettings,
+    settings: &BuildSettings,
+    ctx: &ExecutionContext,
+) -> CodeResponse {
+    ctx.emit(ExecutionEvent::phase(ExecutionPhase::Compiling));
 
-            let final_stderr = if !formatted_errors.is_empty() {
-                formatted_errors
-            } else {
-                String::from_utf8_lossy(&out.stderr).to_string()
+    let project_path = match tokio::fs::canonicalize(project_path).await {
+        Ok(path) => path,
+        Err(e) => {
+            return CodeResponse {
+                stdout: "".into(),
+                stderr: format!("Erro ao localizar o projeto: {}", e),
+                ..Default::default()
             };
+        }
+    };
+    let output_path = explore.output_path(&project_path);
+    if let Some(path) = &output_path {
+        let _ = tokio::fs::remove_file(path).await;
+    }
+    // Cargo skips rustc when nothing changed since the last run with the same
+    // flags, which would leave no output behind.
+    let main_path = project_path.join("src").join("main.rs");
+    let _ = tokio::task::spawn_blocking(move || {
+        std::fs::File::options()
+            .write(true)
+            .open(main_path)?
+            .set_modified(std::time::SystemTime::now())
+    })
+    .await;
+
+    let args = explore.cargo_args(output_path.as_deref());
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let compile_started = Instant::now();
+    let compile_output =
+        run_cargo_with_env(&project_path, &args, &explore.cargo_env(), settings, ctx).await;
+    let compile_time = Some(compile_started.elapsed());
 
-            Json(CodeResponse {
+    let out = match compile_output {
+        Ok(Some(out)) => out,
+        Ok(None) => return cancelled_response("".into()),
+        Err(e) => {
+            return CodeResponse {
                 stdout: "".into(),
-                stderr: format!("Erro de Compilação:\n{}", final_stderr),
-            })
+                stderr: format!("Erro ao invocar cargo: {}", e),
+                ..Default::default()
+            };
+        }
+    };
+
+    // Macro expansion prints the source on stdout, and its errors only as
+    // text on stderr.
+    let messages = match explore.emit {
+        EmitKind::Expanded => CompilerMessages::default(),
+        _ => parse_cargo_messages(&out.stdout, false),
+    };
+    if !out.success {
+        let mut response = compilation_failed(messages, out.stderr);
+        response.stats = Some(ExecutionStats::default().with_compile_time(compile_time));
+        return response;
+    }
+
+    let opt_level = explore.effective_opt_level(settings);
+    let explorer = match (explore.emit, &output_path) {
+        (EmitKind::Expanded, _) | (_, None) => {
+            ExplorerOutput::new(EmitKind::Expanded, opt_level, out.stdout)
+        }
+        (emit, Some(path)) => match tokio::fs::read_to_string(path).await {
+            Ok(code) if emit == EmitKind::Asm => ExplorerOutput::from_asm(
+                opt_level,
+                &code,
+                &project_path.join("src"),
+                explore.filter,
+            ),
+            Ok(code) => ExplorerOutput::new(emit, opt_level, code),
+            Err(e) => {
+                return CodeResponse {
+                    stdout: "".into(),
+                    stderr: format!("Erro ao ler a saída do compilador: {}", e),
+                    ..Default::default()
+                };
+            }
+        },
+    };
+
+    CodeResponse {
+        stdout: format!(
+            "{} gerado com opt-level={}.",
+            explorer.emit.label(),
+            opt_level.as_str()
+        ),
+        stderr: messages.rendered,
+        diagnostics: messages.diagnostics,
+        stats: Some(ExecutionStats::default().with_compile_time(compile_time)),
+        explorer: Some(explorer),
+        ..Default::default()
+    }
+}
+
+/// Builds the crate, and the variant to compare against, with a harness
+/// timing the measured function, then runs each once under the usual limits.
+async fn run_benchmark(
+    project_path: &Path,
+    bench: &BenchSettings,
+    input: &RunInput,
+    settings: &BuildSettings,
+    ctx: &ExecutionContext,
+) -> CodeResponse {
+    let source = match read_project_sources(project_path).await {
+        Ok(source) => source,
+        Err(e) => {
+            return CodeResponse {
+                stdout: "".into(),
+                stderr: format!("Erro ao ler o projeto: {}", e),
+                ..Default::default()
+            };
+        }
+    };
+
+    let mut variants = vec![("a", source.main.as_str())];
+    if let Some(compare) = &bench.compare {
+        variants.push(("b", compare));
+    }
+
+    ctx.emit(ExecutionEvent::phase(ExecutionPhase::Compiling));
+    let bench_path = setup_bench_env(project_path).await;
+    let compile_started = Instant::now();
+    let mut warnings = String::new();
+    let mut diagnostics = vec![];
+    let mut binaries = vec![];
+
+    for (name, main) in variants {
+        let label = format!("Variante {}", name.to_uppercase());
+        let built = build_bench_variant(&bench_path, name, main, &source, bench, settings, ctx);
+        match built.await {
+            Ok((binary, messages)) => {
+                if bench.compare.is_none() {
+                    warnings.push_str(&messages.rendered);
+                } else if !messages.rendered.is_empty() {
+                    warnings.push_str(&format!("{}:\n{}", label, messages.rendered));
+                }
+                diagnostics.extend(messages.diagnostics);
+                binaries.push((name, binary));
+            }
+            Err(mut response) => {
+                if bench.compare.is_some() && !response.stderr.is_empty() {
+                    response.stderr = format!("{}:\n{}", label, response.stderr);
+                }
+                response.stats = Some(
+                    ExecutionStats::default().with_compile_time(Some(compile_started.elapsed())),
+                );
+                return response;
+            }
         }
-        Err(e) => Json(CodeResponse {
+    }
+    let compile_time = Some(compile_started.elapsed());
+
+    ctx.emit(ExecutionEvent::phase(ExecutionPhase::Running));
+    let mut reports = vec![];
+    let mut failures = String::new();
+    let mut stats = ExecutionStats::default();
+    for (name, binary) in binaries {
+        let run = run_safe_bin(&binary, &bench_path, input, &ctx.detached()).await;
+        if run.termination == Termination::Cancelled {
+            return cancelled_response("".into());
+        }
+        if !matches!(run.termination, Termination::Exited { code: 0 }) {
+            failures.push_str(&format!(
+                "Variante {}:\n{}",
+                name.to_uppercase(),
+                run.stderr
+            ));
+            if let Some(msg) = run.termination.describe() {
+                failures.push_str(&msg);
+            }
+        }
+        let wall_time = run.stats.wall_time_ms.map(Duration::from_millis);
+        reports.push(
+            VariantReport::collect(
+                name,
+                &bench_path.join("scratch"),
+                run.termination,
+                wall_time,
+            )
+            .await,
+        );
+        stats = run.stats;
+    }
+
+    let report = BenchReport::new(bench, reports);
+    warnings.push_str(&failures);
+
+    CodeResponse {
+        stdout: report.describe(),
+        stderr: warnings,
+        diagnostics,
+        stats: Some(stats.with_compile_time(compile_time)),
+        bench: Some(report),
+        ..Default::default()
+    }
+}
+
+/// Writes one variant's instrumented crate and builds it, returning a copy
+/// of the binary that survives building the next variant.
+async fn build_bench_variant(
+    bench_path: &Path,
+    name: &str,
+    main: &str,
+    source: &ProjectSource,
+    bench: &BenchSettings,
+    settings: &BuildSettings,
+    ctx: &ExecutionContext,
+) -> Result<(String, CompilerMessages), CodeResponse> {
+    let failed = |stderr: String| CodeResponse {
+        stdout: "".into(),
+        stderr,
+        ..Default::default()
+    };
+
+    let instrumented = ProjectSource {
+        main: bench.instrument(main).map_err(failed)?,
+        modules: source.modules.clone(),
+    };
+    write_project_sources(bench_path, &instrumented)
+        .await
+        .map_err(|e| failed(format!("Erro ao montar o benchmark: {}", e)))?;
+
+    let dependencies = collect_workspace_dependencies(&bench_path.join("src")).await;
+    write_manifest_dependencies(bench_path, &dependencies)
+        .await
+        .map_err(|e| failed(format!("Erro ao configurar dependências: {}", e)))?;
+    write_manifest_edition(bench_path, settings.edition)
+        .await
+        .map_err(|e| failed(format!("Erro ao configurar a edição: {}", e)))?;
+
+    let (exe_path, messages) = compile_main(bench_path, settings, ctx).await?;
+    let binary = bench_path.join(format!("variant_{}", name));
+    tokio::fs::copy(&exe_path, &binary)
+        .await
+        .map_err(|e| failed(format!("Erro ao preparar o benchmark: {}", e)))?;
+
+    let binary = std::fs::canonicalize(&binary).unwrap_or(binary);
+    Ok((binary.to_string_lossy().to_string(), messages))
+}
+
+/// Checks the crate natively, which also resolves and downloads its
+/// dependencies, then interprets it under Miri in the sandbox.
+async fn run_under_miri(
+    project_path: &Path,
+    input: &RunInput,
+    settings: &BuildSettings,
+    ctx: &ExecutionContext,
+) -> CodeResponse {
+    let Some(runtime) = miri_runtime().await else {
+        return CodeResponse {
             stdout: "".into(),
-            stderr: format!("Erro ao invocar cargo: {}", e),
-        }),
+            stderr: "Miri indisponível neste servidor.".into(),
+            ..Default::default()
+        };
+    };
+
+    ctx.emit(ExecutionEvent::phase(ExecutionPhase::Compiling));
+    let compile_started = Instant::now();
+    let check_output = run_cargo(
+        project_path,
+        &["check", "--message-format=json"],
+        settings,
+        ctx,
+    )
+    .await;
+    let compile_time = Some(compile_started.elapsed());
+
+    let out = match check_output {
+        Ok(Some(out)) => out,
+        Ok(None) => return cancelled_response("".into()),
+        Err(e) => {
+            return CodeResponse {
+                stdout: "".into(),
+                stderr: format!("Erro ao invocar cargo: {}", e),
+                ..Default::default()
+            };
+        }
+    };
+
+    let messages = parse_cargo_messages(&out.stdout, false);
+    if !out.success {
+        let mut response = compilation_failed(messages, out.stderr);
+        response.stats = Some(ExecutionStats::default().with_compile_time(compile_time));
+        return response;
+    }
+
+    ctx.emit(ExecutionEvent::phase(ExecutionPhase::Running));
+    let miri = run_miri(runtime, project_path, input, ctx).await;
+
+    let mut stderr = messages.rendered;
+    stderr.push_str(&miri.output.stderr);
+    if let Some(msg) = miri.output.termination.describe() {
+        stderr.push_str(&msg);
+    }
+
+    let mut diagnostics = messages.diagnostics;
+    diagnostics.extend(miri.reports.iter().map(|r| r.diagnostic.clone()));
+
+    CodeResponse {
+        stdout: miri.output.stdout,
+        stderr,
+        diagnostics,
+        stats: Some(miri.output.stats.with_compile_time(compile_time)),
+        termination: Some(miri.output.termination),
+        miri: miri.reports,
+        ..Default::default()
     }
 }
@@ -0,0 +1,294 @@
+use axum::extract::{ConnectInfo, Json, State};
+use axum::http::HeaderMap;
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+use uuid::Uuid;
+
+use crate::CodeResponse;
+use crate::controllers::utils::get_conn;
+use crate::file::project::{
+    ProjectBlock, assemble_project, declare_modules, write_project_sources,
+};
+use crate::file::toolchain::{BuildSettings, write_manifest_edition};
+use crate::file::{
+    collect_workspace_dependencies, setup_miri_env, setup_project_env, write_manifest_dependencies,
+};
+use crate::http::audit::record_audit;
+use crate::http::identity::{RequestIdentity, resolve_identity};
+use crate::http::{
+    KERNEL_BLOCKS_ONLY, build_project, cancelled_response, unsupported_target, verify_bench,
+    verify_source,
+};
+use crate::models::error::ApiError;
+use crate::models::execution::{
+    ExecutionContext, ExecutionEvent, ExecutionPhase, ModeSettings, RunMode,
+};
+use crate::models::notebook::{
+    BlockType, Language, NotebookPermission, check_permission, find_blocks_by_notebook_id,
+    find_notebook_by_id,
+};
+use crate::models::state::AppState;
+use crate::sec::input::RunInput;
+use crate::sec::sandbox::Termination;
+use crate::sec::verify_input;
+
+#[derive(Deserialize)]
+pub struct ProjectRunRequest {
+    #[serde(default)]
+    notebook_id: Option<Uuid>,
+    /// Ordered blocks to build instead of the notebook's saved Rust blocks.
+    #[serde(default)]
+    blocks: Option<Vec<ProjectBlock>>,
+    #[serde(default)]
+    entry_block_id: Option<Uuid>,
+    #[serde(default)]
+    mode: RunMode,
+    #[serde(flatten)]
+    modes: ModeSettings,
+    #[serde(flatten)]
+    build: BuildSettings,
+    #[serde(flatten)]
+    input: RunInput,
+}
+
+pub async fn run_project_request(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<ProjectRunRequest>,
+) -> Result<Json<CodeResponse>, ApiError> {
+    let identity = resolve_identity(addr, &headers).await;
+
+    println!("--------------------------------------------------");
+    println!(
+        "LOG: Nova execução de projeto de {} (IP: {})",
+        identity.owner.key(),
+        identity.client_ip
+    );
+
+    let mut response = run_project_payload(
+        &state,
+        &identity,
+        &payload,
+        &ExecutionContext::default(),
+        "project",
+    )
+    .await?;
+    response.anonymous_session = identity.issued_session;
+
+    Ok(Json(response))
+}
+
+/// Loads the blocks to build, runs them and records the audit entry.
+pub async fn run_project_payload(
+    state: &AppState,
+    identity: &RequestIdentity,
+    payload: &ProjectRunRequest,
+    ctx: &ExecutionContext,
+    endpoint: &str,
+) -> Result<CodeResponse, ApiError> {
+    let blocks = match &payload.blocks {
+        Some(blocks) => blocks.clone(),
+        None => {
+            let notebook_id = payload.notebook_id.ok_or(ApiError::InvalidData)?;
+            load_rust_blocks(state, identity, notebook_id).await?
+        }
+    };
+
+    let outcome = run_project(state, identity, payload, &blocks, ctx).await;
+    record_audit(
+        &state.pool,
+        identity,
+        endpoint,
+        &joined_source(&blocks),
+        &outcome,
+    );
+    outcome
+}
+
+pub async fn load_rust_blocks(
+    state: &AppState,
+    identity: &RequestIdentity,
+    notebook_id: Uuid,
+) -> Result<Vec<ProjectBlock>, ApiError> {
+    let conn = &mut get_conn(&state.pool)
+        .await
+        .map_err(|e| ApiError::DatabaseConnection(e.1.0.to_string()))?;
+
+    let notebook = find_notebook_by_id(conn, &notebook_id).await?;
+    if !notebook.is_public {
+        let permission =
+            check_permission(&state.pool, identity.owner.user_id(), notebook_id).await?;
+        if permission != NotebookPermission::OwnerOrTeam {
+            return Err(ApiError::InvalidAuthorizationToken);
+        }
+    }
+
+    let blocks = find_blocks_by_notebook_id(conn, &notebook_id)
+        .await
+        .map_err(ApiError::Database)?;
+
+    Ok(blocks
+        .into_iter()
+        .filter(|b| b.block_type == BlockType::Code && b.language == Some(Language::Rust))
+        .map(|b| ProjectBlock {
+            id: Some(b.id),
+            code: b.content,
+        })
+        .collect())
+}
+
+fn joined_source(blocks: &[ProjectBlock]) -> String {
+    blocks
+        .iter()
+        .map(|b| b.code.as_str())
+        .collect::<Vec<&str>>()
+        .join("\n\n")
+}
+
+async fn run_project(
+    state: &AppState,
+    identity: &RequestIdentity,
+    payload: &ProjectRunRequest,
+    blocks: &[ProjectBlock],
+    ctx: &ExecutionContext,
+) -> Result<CodeResponse, ApiError> {
+    let reservation = state.limiter.admit(identity)?;
+
+    if payload.mode == RunMode::Kernel {
+        return Ok(CodeResponse {
+            stdout: "".into(),
+            stderr: KERNEL_BLOCKS_ONLY.into(),
+            ..Default::default()
+        });
+    }
+
+    if let Some(message) = unsupported_target(payload.mode, &payload.build) {
+        return Ok(CodeResponse {
+            stdout: "".into(),
+            stderr: message.into(),
+            ..Default::default()
+        });
+    }
+
+    for (index, block) in blocks.iter().enumerate() {
+        if let Some(mut rejected) = verify_source(&block.code, payload.mode) {
+            let label = match block.id {
+                Some(id) => format!("Bloco {} ({})", index + 1, id),
+                None => format!("Bloco {}", index + 1),
+            };
+            rejected.stderr = format!("{}:\n{}", label, rejected.stderr);
+            if !rejected.violations.is_empty() {
+                state.limiter.record_violation(identity);
+            }
+            return Ok(rejected);
+        }
+    }
+
+    if payload.mode == RunMode::Bench
+        && let Some(rejected) = verify_bench(&payload.modes.bench)
+    {
+        if !rejected.violations.is_empty() {
+            state.limiter.record_violation(identity);
+        }
+        return Ok(rejected);
+    }
+
+    if let Err(message) = verify_input(&payload.input) {
+        return Ok(CodeResponse {
+            stdout: "".into(),
+            stderr: message,
+            ..Default::default()
+        });
+    }
+
+    if let Err(message) = payload.build.validate().await {
+        return Ok(CodeResponse {
+            stdout: "".into(),
+            stderr: message,
+            ..Default::default()
+        });
+    }
+
+    let source = match assemble_project(blocks, payload.entry_block_id) {
+        Ok(source) => source,
+        Err(message) => {
+            return Ok(CodeResponse {
+                stdout: "".into(),
+                stderr: message,
+                ..Default::default()
+            });
+        }
+    };
+
+    // The variant replaces the entry block, so it needs the same module
+    // declarations.
+    let mut modes = payload.modes.clone();
+    if let Some(compare) = &mut modes.bench.compare {
+        *compare = declare_modules(compare, &source.modules);
+    }
+
+    ctx.emit(ExecutionEvent::phase(ExecutionPhase::Queued));
+
+    let Some(_permit) = state.scheduler.acquire(identity.owner, ctx).await? else {
+        ctx.emit(ExecutionEvent::phase(ExecutionPhase::Cancelled));
+        return Ok(cancelled_response("".into()));
+    };
+    let _lease = state.workspaces.lease(identity.owner).await;
+
+    let project_path = match payload.mode {
+        RunMode::Miri => setup_miri_env(&identity.owner.key(), payload.notebook_id, true).await,
+        _ => setup_project_env(&identity.owner.key(), payload.notebook_id).await,
+    };
+
+    if let Err(e) = write_project_sources(&project_path, &source).await {
+        return Ok(CodeResponse {
+            stdout: "".into(),
+            stderr: format!("Erro ao montar o projeto: {}", e),
+            ..Default::default()
+        });
+    }
+
+    let dependencies = collect_workspace_dependencies(&project_path.join("src")).await;
+    if let Err(e) = write_manifest_dependencies(&project_path, &dependencies).await {
+        return Ok(CodeResponse {
+            stdout: "".into(),
+            stderr: format!("Erro ao configurar dependências: {}", e),
+            ..Default::default()
+        });
+    }
+
+    if let Err(e) = write_manifest_edition(&project_path, payload.build.edition).await {
+        return Ok(CodeResponse {
+            stdout: "".into(),
+            stderr: format!("Erro ao configurar a edição: {}", e),
+            ..Default::default()
+        });
+    }
+
+    let started = Instant::now();
+    let mut response = build_project(
+        state,
+        &project_path,
+        payload.mode,
+        &modes,
+        &payload.input,
+        &payload.build,
+        ctx,
+    )
+    .await;
+    state
+        .limiter
+        .charge_build_time(reservation, started.elapsed());
+    response.build = Some(payload.build.for_mode(payload.mode).describe().await);
+
+    let phase = match response.termination {
+        Some(Termination::Cancelled) => ExecutionPhase::Cancelled,
+        _ => ExecutionPhase::Exited,
+    };
+    ctx.emit(ExecutionEvent::phase(phase));
+
+    Ok(response)
+}
@@ -0,0 +1,178 @@
+use axum::{
+    extract::{
+        ConnectInfo, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::HeaderMap,
+    response::IntoResponse,
+};
+use futures::{SinkExt, stream::StreamExt};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+use crate::CodeRequest;
+use crate::http::identity::{RequestIdentity, resolve_ws_identity};
+use crate::http::run_code_request;
+use crate::models::error::ApiError;
+use crate::models::execution::{ExecutionContext, ExecutionEvent};
+use crate::models::state::AppState;
+use crate::sec::input::load_input_limits;
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamClientMessage {
+    Run {
+        #[serde(flatten)]
+        request: Box<CodeRequest>,
+        /// Keeps the program's stdin open for `stdin` messages until
+        /// `close_stdin` is sent.
+        #[serde(default)]
+        interactive: bool,
+    },
+    Stdin {
+        data: String,
+    },
+    CloseStdin,
+    Cancel,
+}
+
+pub async fn stream_request(
+    ws: WebSocketUpgrade,
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let identity = resolve_ws_identity(addr, &headers).await;
+
+    ws.protocols(["access_token"])
+        .on_upgrade(move |socket| handle_stream(socket, state, identity))
+}
+
+async fn handle_stream(socket: WebSocket, state: Arc<AppState>, identity: RequestIdentity) {
+    let (mut sender, mut receiver) = socket.split();
+
+    let (payload, interactive) = loop {
+        match receiver.next().await {
+            Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                Ok(StreamClientMessage::Run {
+                    request,
+                    interactive,
+                }) => break (request, interactive),
+                Ok(_) => continue,
+                Err(e) => {
+                    let error = ExecutionEvent::error(format!("Mensagem inválida: {}", e));
+                    if let Ok(json) = serde_json::to_string(&error) {
+                        let _ = sender.send(Message::Text(json.into())).await;
+                    }
+                    let _ = sender.close().await;
+                    return;
+                }
+            },
+            Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+            Some(Ok(_)) => continue,
+        }
+    };
+
+    println!("--------------------------------------------------");
+    println!(
+        "LOG: Nova execução em streaming de {} (IP: {})",
+        identity.owner.key(),
+        identity.client_ip
+    );
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<ExecutionEvent>();
+    let mut ctx = ExecutionContext::streaming(tx);
+    let mut stdin_tx = None;
+    let stdin_limit = load_input_limits().max_stdin_bytes;
+    // The initial stdin counts towards the same limit.
+    let mut stdin_sent = payload.input.stdin.as_ref().map_or(0, String::len);
+    if interactive {
+        let (input_tx, input_rx) = mpsc::unbounded_channel::<String>();
+        ctx = ctx.with_stdin(input_rx);
+        stdin_tx = Some(input_tx);
+    }
+    let cancel = ctx.cancel.clone();
+
+    let run_task = tokio::spawn(async move {
+        match run_code_request(&state, &identity, &payload, &ctx, "stream").await {
+            Ok(mut response) => {
+                response.anonymous_session = identity.issued_session.clone();
+                ctx.emit(ExecutionEvent::Result {
+                    response: Box::new(response),
+                });
+            }
+            Err(ApiError::QueueFull { retry_after_secs }) => {
+                ctx.emit(ExecutionEvent::Error {
+                    message: "A fila de execução está cheia. Tente novamente em instantes."
+                        .to_string(),
+                    retry_after: Some(retry_after_secs),
+                });
+            }
+            Err(e) => ctx.emit(ExecutionEvent::Error {
+                message: e.to_string(),
+                retry_after: e.retry_after_secs(),
+            }),
+        }
+    });
+
+    loop {
+        tokio::select! {
+            event = rx.recv() => {
+                let Some(event) = event else {
+                    break;
+                };
+                let json = match serde_json::to_string(&event) {
+                    Ok(json) => json,
+                    Err(e) => {
+                        eprintln!("ERRO: Falha ao serializar evento de execução: {}", e);
+                        continue;
+                    }
+                };
+                if sender.send(Message::Text(json.into())).await.is_err() {
+                    cancel.cancel();
+                    break;
+                }
+            }
+            message = receiver.next() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(StreamClientMessage::Stdin { data }) => {
+                        let Some(input) = &stdin_tx else {
+                            continue;
+                        };
+                        stdin_sent += data.len();
+                        if stdin_sent > stdin_limit {
+                            stdin_tx = None;
+                            let error = ExecutionEvent::error(format!(
+                                "A entrada padrão excede o limite de {} bytes; stdin foi fechado.",
+                                stdin_limit
+                            ));
+                            if let Ok(json) = serde_json::to_string(&error) {
+                                let _ = sender.send(Message::Text(json.into())).await;
+                            }
+                            continue;
+                        }
+                        let _ = input.send(data);
+                    }
+                    Ok(StreamClientMessage::CloseStdin) => {
+                        stdin_tx = None;
+                    }
+                    Ok(StreamClientMessage::Cancel) => {
+                        println!("LOG: Cliente solicitou cancelamento da execução.");
+                        cancel.cancel();
+                    }
+                    Ok(StreamClientMessage::Run { .. }) | Err(_) => {}
+                },
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => {
+                    cancel.cancel();
+                    break;
+                }
+                Some(Ok(_)) => {}
+            }
+        }
+    }
+
+    let _ = run_task.await;
+    let _ = sender.close().await;
+}
@@ -1,8 +1,23 @@
 use serde::{Deserialize, Serialize};
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use uuid::Uuid;
 
-use crate::controllers::utils::auto_delete_files;
+use crate::controllers::kernel::KernelView;
+use crate::file::bench::BenchReport;
+use crate::file::cache::CacheInfo;
+use crate::file::diagnostics::Diagnostic;
+use crate::file::explorer::ExplorerOutput;
+use crate::file::miri::MiriReport;
+use crate::file::stats::ExecutionStats;
+use crate::file::testing::TestReport;
+use crate::file::toolchain::{BuildInfo, BuildSettings};
+use crate::file::wasm::WasmModule;
+use crate::models::execution::{ModeSettings, RunMode};
+use crate::models::notebook::Language;
+use crate::sec::input::RunInput;
+use crate::sec::policy::PolicyViolation;
+use crate::sec::sandbox::Termination;
 
 pub mod controllers;
 pub mod file;
@@ -15,12 +30,57 @@ pub mod sec;
 #[derive(Deserialize)]
 pub struct CodeRequest {
     code: String,
+    #[serde(default)]
+    notebook_id: Option<Uuid>,
+    /// Saved block being run; its result is kept in the notebook's history.
+    #[serde(default)]
+    block_id: Option<Uuid>,
+    #[serde(default)]
+    language: Language,
+    #[serde(default)]
+    mode: RunMode,
+    #[serde(flatten)]
+    modes: ModeSettings,
+    /// Compiler settings; ignored for Python.
+    #[serde(flatten)]
+    build: BuildSettings,
+    #[serde(flatten)]
+    input: RunInput,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Default)]
 pub struct CodeResponse {
     stdout: String,
     stderr: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    violations: Vec<PolicyViolation>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    diagnostics: Vec<Diagnostic>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    termination: Option<Termination>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stats: Option<ExecutionStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tests: Option<TestReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    explorer: Option<ExplorerOutput>,
+    /// Errors Miri stopped the program with.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    miri: Vec<MiriReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bench: Option<BenchReport>,
+    /// The kernel that ran the block, as it is after the run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kernel: Option<KernelView>,
+    /// Set instead of running the program when building for WebAssembly.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wasm: Option<WasmModule>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    build: Option<BuildInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache: Option<CacheInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    anonymous_session: Option<String>,
 }
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 4)]
@@ -29,8 +89,6 @@ async fn main() {
         .install_default()
         .expect("Falha ao instalar provedor de criptografia rustls");
 
-    tokio::spawn(auto_delete_files());
-
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
@@ -0,0 +1,244 @@
+use crate::controllers::utils::get_parsed_var_from_env;
+use crate::schema::{audit_code_bodies, execution_audit};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::pooled_connection::deadpool::Pool;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use uuid::Uuid;
+
+const DEFAULT_SEARCH_LIMIT: i64 = 100;
+const MAX_SEARCH_LIMIT: i64 = 1000;
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How an execution request ended, as far as abuse review is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditVerdict {
+    Completed,
+    CompileError,
+    PolicyViolation,
+    SandboxViolation,
+    InvalidRequest,
+    Throttled,
+    Cancelled,
+    Failed,
+}
+
+impl AuditVerdict {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditVerdict::Completed => "completed",
+            AuditVerdict::CompileError => "compile_error",
+            AuditVerdict::PolicyViolation => "policy_violation",
+            AuditVerdict::SandboxViolation => "sandbox_violation",
+            AuditVerdict::InvalidRequest => "invalid_request",
+            AuditVerdict::Throttled => "throttled",
+            AuditVerdict::Cancelled => "cancelled",
+            AuditVerdict::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Queryable, Selectable, Identifiable, Serialize, Debug)]
+#[diesel(table_name = execution_audit)]
+pub struct AuditRecord {
+    pub id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub anonymous_session: Option<Uuid>,
+    pub client_ip: String,
+    pub user_agent: String,
+    pub endpoint: String,
+    pub code_hash: String,
+    pub verdict: String,
+    pub violations: serde_json::Value,
+    pub termination: Option<serde_json::Value>,
+    pub stats: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = execution_audit)]
+pub struct NewAuditRecord {
+    pub user_id: Option<Uuid>,
+    pub anonymous_session: Option<Uuid>,
+    pub client_ip: String,
+    pub user_agent: String,
+    pub endpoint: String,
+    pub code_hash: String,
+    pub verdict: String,
+    pub violations: serde_json::Value,
+    pub termination: Option<serde_json::Value>,
+    pub stats: Option<serde_json::Value>,
+}
+
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = audit_code_bodies)]
+pub struct AuditCodeBody {
+    pub code_hash: String,
+    pub code: String,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AuditSearchQuery {
+    pub user_id: Option<Uuid>,
+    pub session: Option<Uuid>,
+    pub ip: Option<String>,
+    pub verdict: Option<AuditVerdict>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// Stores the record, adding the code body only if no earlier record
+/// submitted the same code.
+pub async fn insert_audit_record(
+    conn: &mut AsyncPgConnection,
+    record: &NewAuditRecord,
+    code: &str,
+) -> Result<(), String> {
+    if let Err(e) = diesel::insert_into(audit_code_bodies::table)
+        .values((
+            audit_code_bodies::code_hash.eq(&record.code_hash),
+            audit_code_bodies::code.eq(code),
+        ))
+        .on_conflict(audit_code_bodies::code_hash)
+        .do_update()
+        .set(audit_code_bodies::last_seen_at.eq(diesel::dsl::now))
+        .execute(conn)
+        .await
+    {
+        return Err(e.to_string());
+    }
+
+    match diesel::insert_into(execution_audit::table)
+        .values(record)
+        .execute(conn)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub async fn search_audit_records(
+    conn: &mut AsyncPgConnection,
+    query: &AuditSearchQuery,
+) -> Result<Vec<AuditRecord>, String> {
+    let mut sql = execution_audit::table.into_boxed();
+
+    if let Some(user_id) = query.user_id {
+        sql = sql.filter(execution_audit::user_id.eq(user_id));
+    }
+    if let Some(session) = query.session {
+        sql = sql.filter(execution_audit::anonymous_session.eq(session));
+    }
+    if let Some(ip) = &query.ip {
+        sql = sql.filter(execution_audit::client_ip.eq(ip.trim().to_string()));
+    }
+    if let Some(verdict) = query.verdict {
+        sql = sql.filter(execution_audit::verdict.eq(verdict.as_str()));
+    }
+    if let Some(from) = query.from {
+        sql = sql.filter(execution_audit::created_at.ge(from));
+    }
+    if let Some(to) = query.to {
+        sql = sql.filter(execution_audit::created_at.lt(to));
+    }
+
+    match sql
+        .order(execution_audit::created_at.desc())
+        .limit(
+            query
+                .limit
+                .unwrap_or(DEFAULT_SEARCH_LIMIT)
+                .clamp(1, MAX_SEARCH_LIMIT),
+        )
+        .offset(query.offset.unwrap_or(0).max(0))
+        .select(AuditRecord::as_select())
+        .load::<AuditRecord>(conn)
+        .await
+    {
+        Ok(items) => Ok(items),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub async fn find_audit_code(
+    conn: &mut AsyncPgConnection,
+    param_code_hash: &str,
+) -> Result<Option<AuditCodeBody>, String> {
+    match audit_code_bodies::table
+        .find(param_code_hash)
+        .select(AuditCodeBody::as_select())
+        .first::<AuditCodeBody>(conn)
+        .await
+        .optional()
+    {
+        Ok(item) => Ok(item),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Deletes records older than `cutoff`, then the code bodies no remaining
+/// record refers to and nobody submitted since. Returns how many of each
+/// were removed.
+pub async fn purge_audit_records(
+    conn: &mut AsyncPgConnection,
+    cutoff: DateTime<Utc>,
+) -> Result<(usize, usize), String> {
+    let records =
+        match diesel::delete(execution_audit::table.filter(execution_audit::created_at.lt(cutoff)))
+            .execute(conn)
+            .await
+        {
+            Ok(count) => count,
+            Err(e) => return Err(e.to_string()),
+        };
+
+    let referenced = execution_audit::table
+        .select(execution_audit::code_hash)
+        .distinct();
+    let bodies = match diesel::delete(
+        audit_code_bodies::table
+            .filter(audit_code_bodies::last_seen_at.lt(cutoff))
+            .filter(audit_code_bodies::code_hash.ne_all(referenced)),
+    )
+    .execute(conn)
+    .await
+    {
+        Ok(count) => count,
+        Err(e) => return Err(e.to_string()),
+    };
+
+    Ok((records, bodies))
+}
+
+/// Deletes audit records older than `AUDIT_RETENTION_DAYS`, once an hour.
+pub async fn audit_retention_job(pool: Pool<AsyncPgConnection>) {
+    let retention_days = get_parsed_var_from_env::<i64>("AUDIT_RETENTION_DAYS", 90);
+    if retention_days <= 0 {
+        println!("LOG: Retenção de auditoria desativada.");
+        return;
+    }
+
+    loop {
+        let cutoff = Utc::now() - chrono::Duration::days(retention_days);
+        match pool.get().await {
+            Ok(mut conn) => match purge_audit_records(&mut conn, cutoff).await {
+                Ok((records, bodies)) if records + bodies > 0 => println!(
+                    "LOG: [Auditoria] {} registros e {} códigos expirados removidos",
+                    records, bodies
+                ),
+                Ok(_) => {}
+                Err(e) => eprintln!("ERRO: [Auditoria] Falha na limpeza: {}", e),
+            },
+            Err(e) => eprintln!("ERRO: [Auditoria] Falha ao obter conexão: {}", e),
+        }
+
+        tokio::time::sleep(RETENTION_INTERVAL).await;
+    }
+}
@@ -2,12 +2,18 @@ use axum::{
     Json,
     response::{IntoResponse, Response},
 };
+use hyper::HeaderMap;
 use hyper::StatusCode;
+use hyper::header::{HeaderName, RETRY_AFTER};
 use serde::Serialize;
 use serde_json::json;
 use thiserror::Error;
 use utoipa::ToSchema;
 
+pub const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+pub const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+pub const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
 #[derive(Error, Debug, Serialize, ToSchema)]
 pub enum ApiError {
     #[error("Error processing your request: {0}")]
@@ -63,10 +69,53 @@ pub enum ApiError {
 
     #[error("Error sending the e-mail")]
     SendingEmail,
+
+    #[error("Administrator privileges are required")]
+    AdminRequired,
+
+    #[error("WebAssembly module not found, build it again")]
+    ModuleNotFound,
+
+    #[error("Job not found")]
+    JobNotFound,
+
+    #[error("Too many jobs in progress, at most {limit} at a time")]
+    TooManyJobs { limit: usize },
+
+    #[error("The notebook has no running kernel")]
+    KernelNotFound,
+
+    #[error("The kernel could not be started")]
+    KernelUnavailable,
+
+    #[error("The execution queue is full, retry in {retry_after_secs} seconds")]
+    QueueFull { retry_after_secs: u64 },
+
+    #[error("Too many language servers are running, retry in {retry_after_secs} seconds")]
+    AnalyzersBusy { retry_after_secs: u64 },
+
+    #[error("Too many kernels are running, retry in {retry_after_secs} seconds")]
+    KernelsBusy { retry_after_secs: u64 },
+
+    #[error("Too many executions, retry in {retry_after_secs} seconds")]
+    RateLimited { limit: u32, retry_after_secs: u64 },
+
+    #[error(
+        "Daily build quota of {limit_minutes} minutes used up, retry in {retry_after_secs} seconds"
+    )]
+    BuildQuotaExceeded {
+        limit_minutes: u64,
+        retry_after_secs: u64,
+    },
+
+    #[error(
+        "Temporarily blocked after repeated policy violations, retry in {retry_after_secs} seconds"
+    )]
+    TemporarilyBanned { retry_after_secs: u64 },
 }
 
 impl ApiError {
-    fn error_code(&self) -> &'static str {
+    pub fn error_code(&self) -> &'static str {
         match self {
             ApiError::Request(_) => "BAD_REQUEST",
             ApiError::DatabaseConnection(_) => "DATABASE_CONNECTION_ERROR",
@@ -85,6 +134,35 @@ impl ApiError {
             ApiError::MissingEnv(_) => "MISSING_ENV_VAR",
             ApiError::PasswordsDoNotMatch => "PASSWORDS_DO_NOT_MATCH",
             ApiError::SendingEmail => "ERROR_SENDING_EMAIL",
+            ApiError::AdminRequired => "ADMIN_REQUIRED",
+            ApiError::ModuleNotFound => "MODULE_NOT_FOUND",
+            ApiError::JobNotFound => "JOB_NOT_FOUND",
+            ApiError::TooManyJobs { .. } => "TOO_MANY_JOBS",
+            ApiError::KernelNotFound => "KERNEL_NOT_FOUND",
+            ApiError::KernelUnavailable => "KERNEL_UNAVAILABLE",
+            ApiError::QueueFull { .. } => "QUEUE_FULL",
+            ApiError::AnalyzersBusy { .. } => "ANALYZERS_BUSY",
+            ApiError::KernelsBusy { .. } => "KERNELS_BUSY",
+            ApiError::RateLimited { .. } => "RATE_LIMITED",
+            ApiError::BuildQuotaExceeded { .. } => "BUILD_QUOTA_EXCEEDED",
+            ApiError::TemporarilyBanned { .. } => "TEMPORARILY_BANNED",
+        }
+    }
+
+    /// Seconds until a throttled request may be retried.
+    pub fn retry_after_secs(&self) -> Option<u64> {
+        match self {
+            ApiError::QueueFull { retry_after_secs }
+            | ApiError::AnalyzersBusy { retry_after_secs }
+            | ApiError::KernelsBusy { retry_after_secs }
+            | ApiError::RateLimited {
+                retry_after_secs, ..
+            }
+            | ApiError::BuildQuotaExceeded {
+                retry_after_secs, ..
+            }
+            | ApiError::TemporarilyBanned { retry_after_secs } => Some(*retry_after_secs),
+            _ => None,
         }
     }
 
@@ -93,6 +171,21 @@ impl ApiError {
             ApiError::WrongProvider(provider) => json!({ "provider": provider }),
             ApiError::MissingEnv(env) => json!({ "env_var": env }),
             ApiError::Request(detail) => json!({ "detail": detail }),
+            ApiError::QueueFull { retry_after_secs }
+            | ApiError::AnalyzersBusy { retry_after_secs }
+            | ApiError::KernelsBusy { retry_after_secs }
+            | ApiError::TemporarilyBanned { retry_after_secs } => {
+                json!({ "retry_after": retry_after_secs })
+            }
+            ApiError::RateLimited {
+                limit,
+                retry_after_secs,
+            } => json!({ "limit_per_minute": limit, "retry_after": retry_after_secs }),
+            ApiError::BuildQuotaExceeded {
+                limit_minutes,
+                retry_after_secs,
+            } => json!({ "limit_minutes": limit_minutes, "retry_after": retry_after_secs }),
+            ApiError::TooManyJobs { limit } => json!({ "limit": limit }),
             _ => json!({}),
         }
     }
@@ -103,6 +196,45 @@ impl IntoResponse for ApiError {
         let details = self.error_details();
         let error_code = self.error_code();
 
+        if let ApiError::QueueFull { retry_after_secs }
+        | ApiError::AnalyzersBusy { retry_after_secs }
+        | ApiError::KernelsBusy { retry_after_secs } = self
+        {
+            let body = json!({
+                "code": error_code,
+                "message": self.to_string(),
+                "details": details
+            });
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                [(RETRY_AFTER, retry_after_secs.to_string())],
+                Json(body),
+            )
+                .into_response();
+        }
+
+        if let Some(retry_after_secs) = self.retry_after_secs() {
+            let mut headers = HeaderMap::new();
+            headers.insert(RETRY_AFTER, retry_after_secs.into());
+            headers.insert(RATELIMIT_RESET, retry_after_secs.into());
+            headers.insert(RATELIMIT_REMAINING, 0.into());
+            match self {
+                ApiError::RateLimited { limit, .. } => {
+                    headers.insert(RATELIMIT_LIMIT, limit.into());
+                }
+                ApiError::BuildQuotaExceeded { limit_minutes, .. } => {
+                    headers.insert(RATELIMIT_LIMIT, limit_minutes.into());
+                }
+                _ => {}
+            }
+            let body = json!({
+                "code": error_code,
+                "message": self.to_string(),
+                "details": details
+            });
+            return (StatusCode::TOO_MANY_REQUESTS, headers, Json(body)).into_response();
+        }
+
         let (status, message) = match self {
             ApiError::Database(_) | ApiError::DatabaseConnection(_) | ApiError::CreateToken(_) => {
                 (StatusCode::INTERNAL_SERVER_ERROR, self.to_string())
@@ -121,11 +253,17 @@ impl IntoResponse for ApiError {
                 StatusCode::FORBIDDEN,
                 "User account is inactive".to_string(),
             ),
+            ApiError::AdminRequired => (StatusCode::FORBIDDEN, self.to_string()),
             ApiError::WrongProvider(p) => {
                 (StatusCode::BAD_REQUEST, format!("Please log in with {}", p))
             }
 
-            ApiError::UserNotFound => (StatusCode::NOT_FOUND, self.to_string()),
+            ApiError::UserNotFound
+            | ApiError::ModuleNotFound
+            | ApiError::JobNotFound
+            | ApiError::KernelNotFound => (StatusCode::NOT_FOUND, self.to_string()),
+            ApiError::KernelUnavailable => (StatusCode::SERVICE_UNAVAILABLE, self.to_string()),
+            ApiError::TooManyJobs { .. } => (StatusCode::TOO_MANY_REQUESTS, self.to_string()),
 
             _ => (
                 StatusCode::INTERNAL_SERVER_ERROR,
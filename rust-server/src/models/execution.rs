@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::CodeResponse;
+use crate::file::bench::BenchSettings;
+use crate::file::explorer::ExploreSettings;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunMode {
+    #[default]
+    Run,
+    Test,
+    /// Shows what the compiler generates instead of running.
+    Explore,
+    /// Interprets the program under Miri, which reports undefined behaviour.
+    Miri,
+    /// Times the program, or one of its functions, over many iterations.
+    Bench,
+    /// Evaluates the block in the notebook's kernel, which keeps variables,
+    /// functions and imports for the blocks run after it.
+    Kernel,
+}
+
+/// Settings only read by some run modes.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ModeSettings {
+    /// What to show in explorer mode.
+    #[serde(default)]
+    pub explore: ExploreSettings,
+    #[serde(default)]
+    pub bench: BenchSettings,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionPhase {
+    Queued,
+    Compiling,
+    Running,
+    Exited,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExecutionEvent {
+    Phase {
+        phase: ExecutionPhase,
+        at: i64,
+    },
+    QueuePosition {
+        position: usize,
+        at: i64,
+    },
+    CargoProgress {
+        message: String,
+        at: i64,
+    },
+    Output {
+        stream: OutputStream,
+        data: String,
+        at: i64,
+    },
+    Result {
+        response: Box<CodeResponse>,
+    },
+    Error {
+        message: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        retry_after: Option<u64>,
+    },
+}
+
+impl ExecutionEvent {
+    pub fn phase(phase: ExecutionPhase) -> Self {
+        ExecutionEvent::Phase {
+            phase,
+            at: now_millis(),
+        }
+    }
+
+    pub fn queue_position(position: usize) -> Self {
+        ExecutionEvent::QueuePosition {
+            position,
+            at: now_millis(),
+        }
+    }
+
+    pub fn error(message: String) -> Self {
+        ExecutionEvent::Error {
+            message,
+            retry_after: None,
+        }
+    }
+
+    pub fn cargo_progress(message: String) -> Self {
+        ExecutionEvent::CargoProgress {
+            message,
+            at: now_millis(),
+        }
+    }
+
+    pub fn output(stream: OutputStream, data: String) -> Self {
+        ExecutionEvent::Output {
+            stream,
+            data,
+            at: now_millis(),
+        }
+    }
+}
+
+fn now_millis() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+/// Carries the optional live event channel, interactive stdin and the
+/// cancellation signal of a single code run through the build and execution
+/// pipeline.
+#[derive(Clone, Default)]
+pub struct ExecutionContext {
+    events: Option<mpsc::UnboundedSender<ExecutionEvent>>,
+    stdin: Option<Arc<Mutex<Option<mpsc::UnboundedReceiver<String>>>>>,
+    pub cancel: CancellationToken,
+}
+
+impl ExecutionContext {
+    pub fn streaming(events: mpsc::UnboundedSender<ExecutionEvent>) -> Self {
+        Self {
+            events: Some(events),
+            stdin: None,
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    pub fn with_stdin(mut self, stdin: mpsc::UnboundedReceiver<String>) -> Self {
+        self.stdin = Some(Arc::new(Mutex::new(Some(stdin))));
+        self
+    }
+
+    /// Hands the interactive stdin channel to the program being run; only the
+    /// first caller gets it.
+    pub fn take_stdin(&self) -> Option<mpsc::UnboundedReceiver<String>> {
+        self.stdin.as_ref()?.lock().ok()?.take()
+    }
+
+    /// Same cancellation, but nothing is forwarded to the client.
+    pub fn detached(&self) -> Self {
+        Self {
+            events: None,
+            stdin: None,
+            cancel: self.cancel.clone(),
+        }
+    }
+
+    pub fn is_interactive(&self) -> bool {
+        self.stdin.is_some()
+    }
+
+    pub fn is_streaming(&self) -> bool {
+        self.events.is_some()
+    }
+
+    pub fn emit(&self, event: ExecutionEvent) {
+        if let Some(events) = &self.events {
+            let _ = events.send(event);
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+}
@@ -0,0 +1,184 @@
+use crate::controllers::utils::get_parsed_var_from_env;
+use crate::schema::execution_results;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(Queryable, Selectable, Identifiable, Debug)]
+#[diesel(table_name = execution_results)]
+pub struct ExecutionResult {
+    pub id: Uuid,
+    pub block_id: Uuid,
+    pub notebook_id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_code: Option<i32>,
+    pub termination: Option<serde_json::Value>,
+    pub duration_ms: i64,
+    pub toolchain_version: String,
+    pub code_hash: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = execution_results)]
+pub struct NewExecutionResult {
+    pub block_id: Uuid,
+    pub notebook_id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_code: Option<i32>,
+    pub termination: Option<serde_json::Value>,
+    pub duration_ms: i64,
+    pub toolchain_version: String,
+    pub code_hash: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ExecutionResultResponse {
+    pub id: Uuid,
+    pub stdout: String,
+    pub stderr: String,
+    #[serde(rename = "exitCode")]
+    pub exit_code: Option<i32>,
+    pub termination: Option<serde_json::Value>,
+    #[serde(rename = "durationMs")]
+    pub duration_ms: i64,
+    #[serde(rename = "toolchainVersion")]
+    pub toolchain_version: String,
+    #[serde(rename = "codeHash")]
+    pub code_hash: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    /// The block's code changed after this run.
+    pub stale: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ResultHistoryQuery {
+    pub limit: Option<i64>,
+}
+
+impl ExecutionResult {
+    pub fn into_response(self, current_hash: &str) -> ExecutionResultResponse {
+        ExecutionResultResponse {
+            stale: self.code_hash != current_hash,
+            id: self.id,
+            stdout: self.stdout,
+            stderr: self.stderr,
+            exit_code: self.exit_code,
+            termination: self.termination,
+            duration_ms: self.duration_ms,
+            toolchain_version: self.toolchain_version,
+            code_hash: self.code_hash,
+            created_at: self.created_at,
+        }
+    }
+}
+
+pub fn code_hash(code: &str) -> String {
+    hex::encode(Sha256::digest(code.as_bytes()))
+}
+
+/// Stores a run and drops the block's oldest results beyond
+/// `EXECUTION_HISTORY_LIMIT`.
+pub async fn insert_execution_result(
+    conn: &mut AsyncPgConnection,
+    result: &NewExecutionResult,
+) -> Result<(), String> {
+    let limit = get_parsed_var_from_env::<i64>("EXECUTION_HISTORY_LIMIT", 50).max(1);
+
+    if let Err(e) = diesel::insert_into(execution_results::table)
+        .values(result)
+        .execute(conn)
+        .await
+    {
+        return Err(e.to_string());
+    }
+
+    let pruned: Vec<Uuid> = match execution_results::table
+        .filter(execution_results::block_id.eq(result.block_id))
+        .order(execution_results::created_at.desc())
+        .select(execution_results::id)
+        .offset(limit)
+        .load(conn)
+        .await
+    {
+        Ok(ids) => ids,
+        Err(e) => return Err(e.to_string()),
+    };
+
+    if pruned.is_empty() {
+        return Ok(());
+    }
+
+    match diesel::delete(execution_results::table.filter(execution_results::id.eq_any(pruned)))
+        .execute(conn)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub async fn find_latest_results_by_notebook(
+    conn: &mut AsyncPgConnection,
+    param_nb_id: &Uuid,
+) -> Result<HashMap<Uuid, ExecutionResult>, String> {
+    match execution_results::table
+        .filter(execution_results::notebook_id.eq(param_nb_id))
+        .distinct_on(execution_results::block_id)
+        .order((
+            execution_results::block_id,
+            execution_results::created_at.desc(),
+        ))
+        .select(ExecutionResult::as_select())
+        .load::<ExecutionResult>(conn)
+        .await
+    {
+        Ok(items) => Ok(items.into_iter().map(|r| (r.block_id, r)).collect()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub async fn find_results_by_block(
+    conn: &mut AsyncPgConnection,
+    param_nb_id: &Uuid,
+    param_block_id: &Uuid,
+    limit: i64,
+) -> Result<Vec<ExecutionResult>, String> {
+    match execution_results::table
+        .filter(execution_results::notebook_id.eq(param_nb_id))
+        .filter(execution_results::block_id.eq(param_block_id))
+        .order(execution_results::created_at.desc())
+        .limit(limit)
+        .select(ExecutionResult::as_select())
+        .load::<ExecutionResult>(conn)
+        .await
+    {
+        Ok(items) => Ok(items),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Results of blocks that no longer exist would fail the deferred foreign
+/// key when a notebook save commits.
+pub async fn delete_orphaned_results(
+    conn: &mut AsyncPgConnection,
+    param_nb_id: Uuid,
+    kept_blocks: &[Uuid],
+) -> Result<usize, diesel::result::Error> {
+    diesel::delete(
+        execution_results::table
+            .filter(execution_results::notebook_id.eq(param_nb_id))
+            .filter(execution_results::block_id.ne_all(kept_blocks)),
+    )
+    .execute(conn)
+    .await
+}
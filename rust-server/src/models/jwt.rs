@@ -10,3 +10,9 @@ pub struct Claims {
     pub email: String,
     pub exp: usize,
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AnonymousClaims {
+    pub sid: Uuid,
+    pub exp: usize,
+}
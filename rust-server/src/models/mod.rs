@@ -1,4 +1,7 @@
+pub mod audit;
 pub mod error;
+pub mod execution;
+pub mod execution_result;
 pub mod jwt;
 pub mod notebook;
 pub mod oauth;
@@ -13,6 +13,9 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use uuid::Uuid;
 
+use crate::models::execution_result::{
+    ExecutionResultResponse, code_hash, delete_orphaned_results, find_latest_results_by_notebook,
+};
 use crate::schema::{blocks, notebooks};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, DbEnum, Serialize, Deserialize)]
@@ -24,10 +27,11 @@ pub enum BlockType {
     Component,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, DbEnum, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, DbEnum, Serialize, Deserialize)]
 #[ExistingTypePath = "crate::schema::sql_types::LanguageEnum"]
 #[serde(rename_all = "lowercase")]
 pub enum Language {
+    #[default]
     Rust,
     Typescript,
     Python,
@@ -122,6 +126,8 @@ pub struct BlockResponse {
     pub content: String,
     pub language: Option<Language>,
     pub metadata: Option<BlockMetadata>,
+    #[serde(rename = "lastResult", skip_serializing_if = "Option::is_none")]
+    pub last_result: Option<ExecutionResultResponse>,
 }
 
 #[derive(Insertable)]
@@ -328,6 +334,23 @@ pub async fn find_blocks_by_notebook_id(
     }
 }
 
+pub async fn find_block_by_id(
+    conn: &mut AsyncPgConnection,
+    param_nb_id: &Uuid,
+    param_block_id: &Uuid,
+) -> Result<Option<Block>, String> {
+    match blocks_dsl::blocks
+        .filter(blocks_dsl::id.eq(param_block_id))
+        .filter(blocks_dsl::notebook_id.eq(param_nb_id))
+        .first::<Block>(conn)
+        .await
+        .optional()
+    {
+        Ok(block) => Ok(block),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
 pub async fn sync_notebook_content(
     conn: &mut AsyncPgConnection,
     nb_id: Uuid,
@@ -360,6 +383,9 @@ pub async fn sync_notebook_content(
                         .await?;
                 }
 
+                let kept_blocks: Vec<Uuid> = new_blocks.iter().map(|b| b.id).collect();
+                delete_orphaned_results(conn, nb_id, &kept_blocks).await?;
+
                 Ok(())
             })
         })
@@ -393,6 +419,11 @@ pub async fn get_notebook_with_blocks(
         Err(e) => return Err(format!("Erro ao buscar blocos: {}", e)),
     };
 
+    let mut latest_results = match find_latest_results_by_notebook(conn, param_id).await {
+        Ok(results) => results,
+        Err(e) => return Err(format!("Erro ao buscar resultados de execução: {}", e)),
+    };
+
     let api_blocks: Vec<BlockResponse> = db_blocks
         .into_iter()
         .map(|b| {
@@ -406,6 +437,10 @@ pub async fn get_notebook_with_blocks(
                         }
                     });
 
+            let last_result = latest_results
+                .remove(&b.id)
+                .map(|result| result.into_response(&code_hash(&b.content)));
+
             BlockResponse {
                 id: b.id,
                 title: b.title,
@@ -413,6 +448,7 @@ pub async fn get_notebook_with_blocks(
                 content: b.content,
                 language: b.language,
                 metadata: parsed_metadata,
+                last_result,
             }
         })
         .collect();
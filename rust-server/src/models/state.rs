@@ -1,11 +1,26 @@
+use crate::controllers::analyzer::AnalyzerPool;
+use crate::controllers::jobs::JobRegistry;
+use crate::controllers::kernel::KernelManager;
+use crate::controllers::limiter::RateLimiter;
+use crate::controllers::scheduler::Scheduler;
 use crate::controllers::sync::{PresenceRegistry, SyncRegistry};
+use crate::file::cache::BuildCache;
+use crate::file::workspace::WorkspaceManager;
 use axum::extract::FromRef;
 use diesel_async::{AsyncPgConnection, pooled_connection::deadpool::Pool};
+use std::sync::Arc;
 
 pub struct AppState {
     pub pool: Pool<AsyncPgConnection>,
     pub sync_registry: SyncRegistry,
     pub presence_registry: PresenceRegistry,
+    pub scheduler: Arc<Scheduler>,
+    pub limiter: RateLimiter,
+    pub build_cache: Arc<BuildCache>,
+    pub workspaces: Arc<WorkspaceManager>,
+    pub analyzers: Arc<AnalyzerPool>,
+    pub jobs: Arc<JobRegistry>,
+    pub kernels: Arc<KernelManager>,
 }
 
 impl FromRef<AppState> for Pool<AsyncPgConnection> {
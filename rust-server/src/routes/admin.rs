@@ -0,0 +1,16 @@
+use std::sync::Arc;
+
+use axum::routing::{delete, get};
+use utoipa_axum::router::OpenApiRouter;
+
+use crate::{
+    controllers::admin::{api_get_audit_code, api_purge_build_cache, api_search_audit},
+    models::state::AppState,
+};
+
+pub async fn admin_routes() -> OpenApiRouter<Arc<AppState>> {
+    OpenApiRouter::new()
+        .route("/run-cache", delete(api_purge_build_cache))
+        .route("/audit", get(api_search_audit))
+        .route("/audit/code/{code_hash}", get(api_get_audit_code))
+}
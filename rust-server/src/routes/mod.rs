@@ -1,7 +1,18 @@
+use crate::controllers::analyzer::{AnalyzerConfig, AnalyzerPool};
+use crate::controllers::jobs::{JobConfig, JobRegistry};
+use crate::controllers::kernel::{KernelConfig, KernelManager};
+use crate::controllers::limiter::{LimiterConfig, RateLimiter};
+use crate::controllers::scheduler::{Scheduler, SchedulerConfig};
 use crate::controllers::sync::{PresenceRegistry, SyncRegistry};
 use crate::controllers::utils::{get_database_url_from_env, get_frontend_url_from_env};
-use crate::models::error::ApiError;
+use crate::file::cache::{BuildCache, CacheConfig};
+use crate::file::miri::miri_runtime;
+use crate::file::workspace::{WorkspaceConfig, WorkspaceManager};
+use crate::http::identity::ANONYMOUS_SESSION_HEADER;
+use crate::models::audit::audit_retention_job;
+use crate::models::error::{ApiError, RATELIMIT_LIMIT, RATELIMIT_REMAINING, RATELIMIT_RESET};
 use crate::models::state::AppState;
+use crate::routes::admin::admin_routes;
 use crate::routes::notebook::notebook_routes;
 use crate::routes::run_rust::run_rust_routes;
 use crate::routes::team::team_routes;
@@ -20,7 +31,7 @@ use diesel_async::{AsyncPgConnection, pooled_connection::deadpool::Pool};
 use futures_util::FutureExt;
 use futures_util::future::BoxFuture;
 use hyper::StatusCode;
-use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE, HeaderName, RETRY_AFTER};
 use rustls::ClientConfig;
 use rustls_platform_verifier::ConfigVerifierExt;
 use std::collections::HashMap;
@@ -30,6 +41,7 @@ use tower_http::cors::{Any, CorsLayer};
 use tower_http::services::ServeDir;
 use utoipa_axum::router::OpenApiRouter;
 
+pub mod admin;
 pub mod docs;
 pub mod notebook;
 pub mod run_rust;
@@ -75,11 +87,33 @@ pub async fn init_routes() -> Router {
         let mgr =
             AsyncDieselConnectionManager::<AsyncPgConnection>::new_with_config(db_url, config);
         let pool = Pool::builder(mgr).max_size(10).build().unwrap();
+        tokio::spawn(audit_retention_job(pool.clone()));
+
+        let workspaces = Arc::new(WorkspaceManager::new(WorkspaceConfig::from_env()));
+        tokio::spawn(workspaces.clone().run());
+
+        let jobs = Arc::new(JobRegistry::new(JobConfig::from_env()));
+        tokio::spawn(jobs.clone().run());
+
+        let kernels = Arc::new(KernelManager::new(KernelConfig::from_env()));
+        tokio::spawn(kernels.clone().run());
+
+        // Building Miri's sysroot takes a while; do it before the first run.
+        tokio::spawn(async {
+            miri_runtime().await;
+        });
 
         let app_state = Arc::new(AppState {
             presence_registry,
             pool,
             sync_registry,
+            scheduler: Arc::new(Scheduler::new(SchedulerConfig::from_env())),
+            limiter: RateLimiter::new(LimiterConfig::from_env()),
+            build_cache: Arc::new(BuildCache::new(CacheConfig::from_env())),
+            workspaces,
+            analyzers: Arc::new(AnalyzerPool::new(AnalyzerConfig::from_env())),
+            jobs,
+            kernels,
         });
 
         let app = OpenApiRouter::<Arc<AppState>>::new()
@@ -92,6 +126,7 @@ pub async fn init_routes() -> Router {
             .nest("/api/user", user_routes().await.into())
             .nest("/api/notebook", notebook_routes().await.into())
             .nest("/api/team", team_routes().await.into())
+            .nest("/api/admin", admin_routes().await.into())
             //.merge(SwaggerUi::new("/docs").url("/api-docs/openapi.json", get_api_docs()))
             .with_state(app_state)
             .layer(DefaultBodyLimit::max(1024 * 1024 * 100))
@@ -99,7 +134,17 @@ pub async fn init_routes() -> Router {
                 CorsLayer::new()
                     .allow_origin(vec![frontend_url.parse::<HeaderValue>().unwrap()])
                     .allow_methods(Any)
-                    .allow_headers(vec![AUTHORIZATION, CONTENT_TYPE]),
+                    .allow_headers(vec![
+                        AUTHORIZATION,
+                        CONTENT_TYPE,
+                        HeaderName::from_static(ANONYMOUS_SESSION_HEADER),
+                    ])
+                    .expose_headers(vec![
+                        RETRY_AFTER,
+                        RATELIMIT_LIMIT,
+                        RATELIMIT_REMAINING,
+                        RATELIMIT_RESET,
+                    ]),
             );
     }
     Router::new()
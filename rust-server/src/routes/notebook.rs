@@ -6,10 +6,10 @@ use utoipa_axum::router::OpenApiRouter;
 use crate::{
     controllers::{
         notebook::{
-            api_clone_notebook, api_create_notebook, api_delete_notebook, api_get_notebooks,
-            api_get_public_notebooks, api_get_single_notebook, api_get_single_notebook_with_blocks,
-            api_rename_notebook, api_save_notebook_content, api_search_notebooks,
-            api_update_notebook_visibility,
+            api_clone_notebook, api_create_notebook, api_delete_notebook, api_get_block_results,
+            api_get_notebooks, api_get_public_notebooks, api_get_single_notebook,
+            api_get_single_notebook_with_blocks, api_rename_notebook, api_save_notebook_content,
+            api_search_notebooks, api_update_notebook_visibility,
         },
         user::api_get_user_notebook_permissions,
         websocket::{websocket_handler, websocket_presence_handler},
@@ -25,6 +25,10 @@ pub async fn notebook_routes() -> OpenApiRouter<Arc<AppState>> {
         .route("/{id}", get(api_get_single_notebook))
         .route("/{id}/full", get(api_get_single_notebook_with_blocks))
         .route("/{id}/content", put(api_save_notebook_content))
+        .route(
+            "/{id}/blocks/{block_id}/results",
+            get(api_get_block_results),
+        )
         .route("/{id}/clone", post(api_clone_notebook))
         .route("/{id}/visibility", patch(api_update_notebook_visibility))
         .route("/{id}/permissions", get(api_get_user_notebook_permissions))
@@ -1,12 +1,37 @@
 use std::sync::Arc;
 
-use axum::routing::post;
+use axum::routing::{get, post};
 use utoipa_axum::router::OpenApiRouter;
 
-use crate::{http::verify_request, models::state::AppState};
+use crate::{
+    http::{
+        jobs::{cancel_job, job_events, job_status, list_jobs, submit_job, submit_project_job},
+        kernel::{interrupt_kernel, kernel_status, restart_kernel, shutdown_kernel},
+        lsp::lsp_request,
+        project::run_project_request,
+        run_metrics, run_toolchains,
+        stream::stream_request,
+        verify_request, wasm_module,
+    },
+    models::state::AppState,
+};
 
 pub async fn run_rust_routes() -> OpenApiRouter<Arc<AppState>> {
-    let routes = OpenApiRouter::new().route("/run", post(verify_request));
+    let routes = OpenApiRouter::new()
+        .route("/run", post(verify_request))
+        .route("/run/project", post(run_project_request))
+        .route("/run/ws", get(stream_request))
+        .route("/run/lsp", get(lsp_request))
+        .route("/run/metrics", get(run_metrics))
+        .route("/run/toolchains", get(run_toolchains))
+        .route("/run/wasm/{key}", get(wasm_module))
+        .route("/run/jobs", post(submit_job).get(list_jobs))
+        .route("/run/project/jobs", post(submit_project_job))
+        .route("/run/jobs/{id}", get(job_status).delete(cancel_job))
+        .route("/run/jobs/{id}/events", get(job_events))
+        .route("/run/kernel", get(kernel_status).delete(shutdown_kernel))
+        .route("/run/kernel/restart", post(restart_kernel))
+        .route("/run/kernel/interrupt", post(interrupt_kernel));
 
     routes
 }
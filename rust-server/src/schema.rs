@@ -18,6 +18,15 @@ pub mod sql_types {
     pub struct UserRole;
 }
 
+diesel::table! {
+    audit_code_bodies (code_hash) {
+        #[max_length = 64]
+        code_hash -> Varchar,
+        code -> Text,
+        last_seen_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use super::sql_types::BlockTypeEnum;
@@ -35,6 +44,45 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    execution_audit (id) {
+        id -> Uuid,
+        user_id -> Nullable<Uuid>,
+        anonymous_session -> Nullable<Uuid>,
+        #[max_length = 45]
+        client_ip -> Varchar,
+        user_agent -> Text,
+        #[max_length = 16]
+        endpoint -> Varchar,
+        #[max_length = 64]
+        code_hash -> Varchar,
+        #[max_length = 32]
+        verdict -> Varchar,
+        violations -> Jsonb,
+        termination -> Nullable<Jsonb>,
+        stats -> Nullable<Jsonb>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    execution_results (id) {
+        id -> Uuid,
+        block_id -> Uuid,
+        notebook_id -> Uuid,
+        user_id -> Nullable<Uuid>,
+        stdout -> Text,
+        stderr -> Text,
+        exit_code -> Nullable<Int4>,
+        termination -> Nullable<Jsonb>,
+        duration_ms -> Int8,
+        toolchain_version -> Text,
+        #[max_length = 64]
+        code_hash -> Varchar,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     notebooks (id) {
         id -> Uuid,
@@ -128,6 +176,11 @@ diesel::table! {
 }
 
 diesel::joinable!(blocks -> notebooks (notebook_id));
+diesel::joinable!(execution_audit -> audit_code_bodies (code_hash));
+diesel::joinable!(execution_audit -> users (user_id));
+diesel::joinable!(execution_results -> blocks (block_id));
+diesel::joinable!(execution_results -> notebooks (notebook_id));
+diesel::joinable!(execution_results -> users (user_id));
 diesel::joinable!(notebooks -> teams (team_id));
 diesel::joinable!(notebooks -> users (user_id));
 diesel::joinable!(team_invitations -> team_roles (role_id));
@@ -138,7 +191,10 @@ diesel::joinable!(team_members -> users (user_id));
 diesel::joinable!(team_roles -> teams (team_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    audit_code_bodies,
     blocks,
+    execution_audit,
+    execution_results,
     notebooks,
     team_invitations,
     team_members,
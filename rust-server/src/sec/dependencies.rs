@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+use crate::controllers::utils::{DependencyAnnotation, get_var_from_env};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AllowedCrate {
+    pub versions: Vec<String>,
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DependencyAllowlist {
+    #[serde(default)]
+    pub crates: BTreeMap<String, AllowedCrate>,
+    #[serde(skip)]
+    pub vendor_dir: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrateDependency {
+    pub name: String,
+    pub version: String,
+    pub features: Vec<String>,
+}
+
+impl DependencyAllowlist {
+    pub fn from_env() -> Self {
+        let vendor_dir = get_var_from_env("CRATE_VENDOR_DIR").ok();
+
+        let mut allowlist = match get_var_from_env("CRATE_ALLOWLIST_FILE") {
+            Ok(path) => match std::fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|raw| {
+                    serde_json::from_str::<DependencyAllowlist>(&raw).map_err(|e| e.to_string())
+                }) {
+                Ok(list) => {
+                    println!(
+                        "LOG: Lista de crates permitidas carregada de {} ({} crates)",
+                        path,
+                        list.crates.len()
+                    );
+                    list
+                }
+                Err(e) => {
+                    eprintln!("ERRO: Falha ao carregar lista de crates em {}: {}", path, e);
+                    Self::default()
+                }
+            },
+            Err(_) => Self::default(),
+        };
+
+        if vendor_dir.is_none() && !allowlist.crates.is_empty() {
+            eprintln!("AVISO: CRATE_VENDOR_DIR não definido, dependências externas desativadas.");
+            allowlist.crates.clear();
+        }
+        allowlist.vendor_dir = vendor_dir;
+        allowlist
+    }
+
+    pub fn resolve(&self, requested: &DependencyAnnotation) -> Result<CrateDependency, String> {
+        let allowed = self.crates.get(&requested.name).ok_or_else(|| {
+            format!(
+                "Segurança: A crate '{}' não está na lista de dependências permitidas.",
+                requested.name
+            )
+        })?;
+
+        let version = match &requested.version {
+            Some(v) if allowed.versions.contains(v) => v.clone(),
+            Some(v) => {
+                return Err(format!(
+                    "A versão '{}' da crate '{}' não está disponível. Versões permitidas: {}",
+                    v,
+                    requested.name,
+                    allowed.versions.join(", ")
+                ));
+            }
+            None => match allowed.versions.first() {
+                Some(v) => v.clone(),
+                None => {
+                    return Err(format!(
+                        "A crate '{}' não possui versões disponíveis.",
+                        requested.name
+                    ));
+                }
+            },
+        };
+
+        if let Some(feature) = requested
+            .features
+            .iter()
+            .find(|f| !allowed.features.contains(f))
+        {
+            return Err(format!(
+                "A feature '{}' da crate '{}' não é permitida.",
+                feature, requested.name
+            ));
+        }
+
+        Ok(CrateDependency {
+            name: requested.name.clone(),
+            version,
+            features: requested.features.clone(),
+        })
+    }
+}
+
+pub fn load_dependency_allowlist() -> &'static DependencyAllowlist {
+    static ALLOWLIST: OnceLock<DependencyAllowlist> = OnceLock::new();
+    ALLOWLIST.get_or_init(DependencyAllowlist::from_env)
+}
+
+pub fn merge_dependencies(deps: Vec<CrateDependency>) -> Vec<CrateDependency> {
+    let mut merged: BTreeMap<String, CrateDependency> = BTreeMap::new();
+
+    for dep in deps {
+        match merged.get_mut(&dep.name) {
+            Some(existing) => {
+                existing.version = dep.version;
+                for feature in dep.features {
+                    if !existing.features.contains(&feature) {
+                        existing.features.push(feature);
+                    }
+                }
+            }
+            None => {
+                merged.insert(dep.name.clone(), dep);
+            }
+        }
+    }
+
+    merged.into_values().collect()
+}
@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+use crate::controllers::utils::{get_parsed_var_from_env, get_var_from_env};
+
+const DEFAULT_ENV_ALLOWLIST: &str = "RUST_BACKTRACE,RUST_LOG,LANG,LC_ALL,TZ,NO_COLOR,APP_*";
+
+#[derive(Debug, Clone)]
+pub struct InputLimits {
+    pub max_stdin_bytes: usize,
+    pub max_args: usize,
+    pub max_arg_bytes: usize,
+    pub max_env_vars: usize,
+    pub max_env_value_bytes: usize,
+    /// Variable names the program may receive; a trailing `*` matches a prefix.
+    pub env_allowlist: Vec<String>,
+}
+
+impl InputLimits {
+    pub fn from_env() -> Self {
+        let env_allowlist = get_var_from_env("RUN_ENV_ALLOWLIST")
+            .unwrap_or_else(|_| DEFAULT_ENV_ALLOWLIST.to_string())
+            .split(',')
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .collect();
+
+        Self {
+            max_stdin_bytes: get_parsed_var_from_env::<usize>("RUN_MAX_STDIN_KB", 64) * 1024,
+            max_args: get_parsed_var_from_env("RUN_MAX_ARGS", 32),
+            max_arg_bytes: get_parsed_var_from_env("RUN_MAX_ARG_BYTES", 1024),
+            max_env_vars: get_parsed_var_from_env("RUN_MAX_ENV_VARS", 16),
+            max_env_value_bytes: get_parsed_var_from_env("RUN_MAX_ENV_VALUE_BYTES", 1024),
+            env_allowlist,
+        }
+    }
+
+    fn env_allowed(&self, name: &str) -> bool {
+        self.env_allowlist
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == pattern,
+            })
+    }
+}
+
+pub fn load_input_limits() -> &'static InputLimits {
+    static LIMITS: OnceLock<InputLimits> = OnceLock::new();
+    LIMITS.get_or_init(InputLimits::from_env)
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RunInput {
+    #[serde(default)]
+    pub stdin: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+impl RunInput {
+    pub fn validate(&self, limits: &InputLimits) -> Result<(), String> {
+        if let Some(stdin) = &self.stdin
+            && stdin.len() > limits.max_stdin_bytes
+        {
+            return Err(format!(
+                "A entrada padrão excede o limite de {} bytes.",
+                limits.max_stdin_bytes
+            ));
+        }
+
+        if self.args.len() > limits.max_args {
+            return Err(format!(
+                "Número de argumentos excede o limite de {}.",
+                limits.max_args
+            ));
+        }
+        for arg in &self.args {
+            if arg.len() > limits.max_arg_bytes {
+                return Err(format!(
+                    "Um argumento excede o limite de {} bytes.",
+                    limits.max_arg_bytes
+                ));
+            }
+            if arg.contains('\0') {
+                return Err("Argumentos não podem conter o caractere nulo.".to_string());
+            }
+        }
+
+        if self.env.len() > limits.max_env_vars {
+            return Err(format!(
+                "Número de variáveis de ambiente excede o limite de {}.",
+                limits.max_env_vars
+            ));
+        }
+        for (name, value) in &self.env {
+            if !limits.env_allowed(name) {
+                return Err(format!(
+                    "Segurança: A variável de ambiente '{}' não é permitida.",
+                    name
+                ));
+            }
+            if value.len() > limits.max_env_value_bytes {
+                return Err(format!(
+                    "O valor de '{}' excede o limite de {} bytes.",
+                    name, limits.max_env_value_bytes
+                ));
+            }
+            if name.contains(['=', '\0']) || value.contains('\0') {
+                return Err(format!("Variável de ambiente inválida: '{}'.", name));
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,39 +1,181 @@
-pub fn verify_code(code: &str) -> Result<(), String> {
-    let forbidden = [
-        "std::fs",
-        "std::io",
-        "std::path",
-        "std::env",
-        "std::net",
-        "std::socket",
-        "std::process",
-        "std::thread",
-        "std::sync",
-        "Command::new",
-        "std::ffi",
-        "std::os",
-        "libc",
-        "winapi",
-        "std::ptr",
-        "std::mem",
-        "std::intrinsics",
-        "std::time::Instant",
-        "std::time::SystemTime",
-        "std::alloc",
-        "include_str!",
-        "include_bytes!",
-        "include!",
-        "unsafe",
-    ];
-
-    for word in forbidden {
-        if code.contains(word) {
-            eprintln!(
-                "LOG: Código rejeitado por conter palavra proibida: {}",
-                word
-            );
-            return Err(format!("Segurança: O uso de '{}' não é permitido.", word));
+use crate::controllers::utils::extract_dependencies;
+use crate::sec::dependencies::{CrateDependency, load_dependency_allowlist};
+use crate::sec::input::{RunInput, load_input_limits};
+use crate::sec::policy::{CodePolicy, PolicyViolation, SourceSpan, load_miri_policy, load_policy};
+
+pub mod dependencies;
+pub mod input;
+pub mod policy;
+pub mod sandbox;
+
+pub fn verify_code(code: &str) -> Result<(), Vec<PolicyViolation>> {
+    check_policy(load_policy(), code)
+}
+
+/// Checks code that only ever runs under Miri, which allows `unsafe`.
+pub fn verify_miri_code(code: &str) -> Result<(), Vec<PolicyViolation>> {
+    check_policy(load_miri_policy(), code)
+}
+
+/// Kernel blocks are statements and items, as in a function body, so they
+/// are checked inside one. Lines starting with `:` would be evcxr commands,
+/// which can add dependencies or change the build, and lines starting with
+/// `!` shell commands; both are refused.
+pub fn verify_kernel_code(code: &str) -> Result<(), Vec<PolicyViolation>> {
+    const WRAPPER: &str = "fn __kernel_block() { ";
+
+    let mut violations: Vec<PolicyViolation> = code
+        .lines()
+        .enumerate()
+        // evcxr runs any line whose first byte is `!` through `sh -c`, even
+        // one that also reads as a Rust expression such as `!cat / etc`.
+        .filter(|(_, line)| line.trim_start().starts_with(':') || line.starts_with('!'))
+        .map(|(index, line)| PolicyViolation {
+            rule_id: "kernel-command".to_string(),
+            message: "Comandos do kernel não são permitidos.".to_string(),
+            span: SourceSpan {
+                line_start: index + 1,
+                column_start: 1,
+                line_end: index + 1,
+                column_end: line.len() + 1,
+            },
+        })
+        .collect();
+
+    // The kernel reads these as line breaks, so they would hide a command
+    // from the check above.
+    for (index, line) in code.lines().enumerate() {
+        for (column, separator) in line.match_indices(['\u{2028}', '\u{2029}']) {
+            violations.push(PolicyViolation {
+                rule_id: "kernel-line-separator".to_string(),
+                message: "Separadores de linha Unicode não são permitidos no kernel.".to_string(),
+                span: SourceSpan {
+                    line_start: index + 1,
+                    column_start: column + 1,
+                    line_end: index + 1,
+                    column_end: column + separator.len() + 1,
+                },
+            });
+        }
+    }
+
+    if let Err(found) = check_policy(load_policy(), &format!("{}{}\n}}", WRAPPER, code)) {
+        // Only the first line shares its line with the wrapper.
+        violations.extend(found.into_iter().map(|mut v| {
+            if v.span.line_start == 1 {
+                v.span.column_start = v.span.column_start.saturating_sub(WRAPPER.len()).max(1);
+            }
+            if v.span.line_end == 1 {
+                v.span.column_end = v.span.column_end.saturating_sub(WRAPPER.len()).max(1);
+            }
+            v
+        }));
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations)
+    }
+}
+
+fn check_policy(policy: &CodePolicy, code: &str) -> Result<(), Vec<PolicyViolation>> {
+    let violations = policy.check(code);
+
+    if violations.is_empty() {
+        return Ok(());
+    }
+
+    for v in &violations {
+        eprintln!(
+            "LOG: Código rejeitado pela regra '{}' em {}:{}",
+            v.rule_id, v.span.line_start, v.span.column_start
+        );
+    }
+    Err(violations)
+}
+
+pub fn verify_dependencies(code: &str) -> Result<Vec<CrateDependency>, Vec<PolicyViolation>> {
+    let allowlist = load_dependency_allowlist();
+    let mut resolved = vec![];
+    let mut violations = vec![];
+
+    for annotation in extract_dependencies(code) {
+        match allowlist.resolve(&annotation) {
+            Ok(dep) => resolved.push(dep),
+            Err(message) => {
+                let line_len = code.lines().nth(annotation.line - 1).map_or(0, |l| l.len());
+                violations.push(PolicyViolation {
+                    rule_id: "dependency".to_string(),
+                    message,
+                    span: SourceSpan {
+                        line_start: annotation.line,
+                        column_start: 1,
+                        line_end: annotation.line,
+                        column_end: line_len + 1,
+                    },
+                });
+            }
+        }
+    }
+
+    if violations.is_empty() {
+        Ok(resolved)
+    } else {
+        Err(violations)
+    }
+}
+
+pub fn verify_input(input: &RunInput) -> Result<(), String> {
+    input.validate(load_input_limits()).inspect_err(|e| {
+        eprintln!("LOG: Entrada de execução rejeitada: {}", e);
+    })
+}
+
+pub fn format_violations(violations: &[PolicyViolation]) -> String {
+    violations
+        .iter()
+        .map(|v| {
+            format!(
+                "[{}] linha {}, coluna {}: {}",
+                v.rule_id, v.span.line_start, v.span.column_start, v.message
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kernel_rules(code: &str) -> Vec<String> {
+        match verify_kernel_code(code) {
+            Ok(()) => vec![],
+            Err(violations) => violations.into_iter().map(|v| v.rule_id).collect(),
         }
     }
-    Ok(())
+
+    #[test]
+    fn refuses_kernel_commands() {
+        assert_eq!(kernel_rules(":dep rand = \"0.8\""), ["kernel-command"]);
+        assert_eq!(kernel_rules("let x = 1;\n  :shell ls"), ["kernel-command"]);
+        assert_eq!(kernel_rules("!cat /etc/passwd"), ["kernel-command"]);
+        assert_eq!(kernel_rules("let a = 1;\n!ls"), ["kernel-command"]);
+    }
+
+    #[test]
+    fn refuses_unicode_line_separators() {
+        assert_eq!(
+            kernel_rules("1;\u{2028}:dep foo = { path = \"/\" }"),
+            ["kernel-line-separator"]
+        );
+        assert_eq!(kernel_rules("1;\u{2029}!ls"), ["kernel-line-separator"]);
+    }
+
+    #[test]
+    fn accepts_plain_blocks() {
+        assert!(kernel_rules("let done = false;\nif !done { println!(\"{}\", 1); }").is_empty());
+        assert!(kernel_rules("let x = 1;\n    !true").is_empty());
+    }
 }
@@ -0,0 +1,901 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+use proc_macro2::{Delimiter, Span, TokenStream, TokenTree};
+use serde::{Deserialize, Serialize};
+use syn::visit::{self, Visit};
+
+use crate::controllers::utils::get_var_from_env;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyRule {
+    pub id: String,
+    pub pattern: String,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodePolicy {
+    #[serde(default)]
+    pub deny_paths: Vec<PolicyRule>,
+    #[serde(default)]
+    pub allow_paths: Vec<String>,
+    #[serde(default)]
+    pub deny_macros: Vec<PolicyRule>,
+    #[serde(default)]
+    pub deny_attributes: Vec<PolicyRule>,
+    #[serde(default = "default_true")]
+    pub deny_unsafe: bool,
+    #[serde(default = "default_true")]
+    pub deny_extern_blocks: bool,
+    /// Rule ids lifted in Miri mode, where nothing runs natively; `unsafe`
+    /// stands for `deny_unsafe`.
+    #[serde(default = "default_miri_allowed_rules")]
+    pub miri_allowed_rules: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub struct SourceSpan {
+    pub line_start: usize,
+    pub column_start: usize,
+    pub line_end: usize,
+    pub column_end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct PolicyViolation {
+    pub rule_id: String,
+    pub message: String,
+    pub span: SourceSpan,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_miri_allowed_rules() -> Vec<String> {
+    ["unsafe", "ptr", "mem", "alloc"]
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+fn rule(id: &str, pattern: &str) -> PolicyRule {
+    PolicyRule {
+        id: id.to_string(),
+        pattern: pattern.to_string(),
+        message: None,
+    }
+}
+
+impl Default for CodePolicy {
+    fn default() -> Self {
+        Self {
+            deny_paths: vec![
+                rule("fs", "std::fs"),
+                rule("io", "std::io"),
+                rule("path", "std::path"),
+                rule("env", "std::env"),
+                rule("net", "std::net"),
+                rule("process", "std::process"),
+                rule("thread", "std::thread"),
+                rule("sync", "std::sync"),
+                rule("ffi", "std::ffi"),
+                rule("os", "std::os"),
+                rule("ptr", "std::ptr"),
+                rule("mem", "std::mem"),
+                rule("intrinsics", "std::intrinsics"),
+                rule("arch", "std::arch"),
+                rule("time-instant", "std::time::Instant"),
+                rule("time-system", "std::time::SystemTime"),
+                rule("alloc", "std::alloc"),
+                rule("libc", "libc"),
+                rule("winapi", "winapi"),
+            ],
+            allow_paths: [
+                "std::io::stdin",
+                "std::io::Stdin",
+                "std::io::StdinLock",
+                "std::io::stdout",
+                "std::io::Stdout",
+                "std::io::StdoutLock",
+                "std::io::stderr",
+                "std::io::Stderr",
+                "std::io::Read",
+                "std::io::BufRead",
+                "std::io::Write",
+                "std::io::Lines",
+                "std::io::Result",
+                "std::io::Error",
+                "std::io::ErrorKind",
+                "std::io::prelude",
+                "std::env::args",
+                "std::env::Args",
+                "std::env::var",
+                "std::env::vars",
+                "std::env::VarError",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+            deny_macros: vec![
+                rule("include-str", "include_str"),
+                rule("include-bytes", "include_bytes"),
+                rule("include", "include"),
+                rule("env-macro", "env"),
+                rule("option-env", "option_env"),
+                rule("asm", "asm"),
+                rule("global-asm", "global_asm"),
+            ],
+            deny_attributes: vec![
+                rule("link", "link"),
+                rule("link-name", "link_name"),
+                rule("link-section", "link_section"),
+                rule("no-mangle", "no_mangle"),
+                rule("export-name", "export_name"),
+                rule("used", "used"),
+                rule("mod-path", "path"),
+            ],
+            deny_unsafe: true,
+            deny_extern_blocks: true,
+            miri_allowed_rules: default_miri_allowed_rules(),
+        }
+    }
+}
+
+impl CodePolicy {
+    pub fn from_env() -> Self {
+        let path = match get_var_from_env("CODE_POLICY_FILE") {
+            Ok(p) => p,
+            Err(_) => return Self::default(),
+        };
+
+        match std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|raw| serde_json::from_str::<CodePolicy>(&raw).map_err(|e| e.to_string()))
+        {
+            Ok(policy) => {
+                println!("LOG: Política de código carregada de {}", path);
+                policy
+            }
+            Err(e) => {
+                eprintln!(
+                    "ERRO: Falha ao carregar política de código em {}: {}. Usando a política padrão.",
+                    path, e
+                );
+                Self::default()
+            }
+        }
+    }
+
+    /// This policy with the rules in `miri_allowed_rules` lifted.
+    pub fn for_miri(&self) -> Self {
+        let lifted = &self.miri_allowed_rules;
+        let mut policy = self.clone();
+        policy.deny_paths.retain(|r| !lifted.contains(&r.id));
+        policy.deny_macros.retain(|r| !lifted.contains(&r.id));
+        policy.deny_attributes.retain(|r| !lifted.contains(&r.id));
+        if lifted.iter().any(|id| id == "unsafe") {
+            policy.deny_unsafe = false;
+        }
+        policy
+    }
+
+    pub fn check(&self, code: &str) -> Vec<PolicyViolation> {
+        let mut analyzer = PolicyAnalyzer::new(self);
+
+        match syn::parse_file(code) {
+            Ok(file) => {
+                analyzer.collect_aliases(&file);
+                analyzer.visit_file(&file);
+            }
+            Err(_) => {
+                if let Ok(tokens) = code.parse::<TokenStream>() {
+                    analyzer.scan_tokens(tokens);
+                }
+            }
+        }
+
+        analyzer.violations
+    }
+
+    fn denied_path(&self, path: &str) -> Option<&PolicyRule> {
+        let deny = self
+            .deny_paths
+            .iter()
+            .filter(|r| path_matches(path, &r.pattern))
+            .max_by_key(|r| segment_count(&r.pattern))?;
+
+        let allowed = self
+            .allow_paths
+            .iter()
+            .filter(|p| path_matches(path, p))
+            .map(|p| segment_count(p))
+            .max();
+
+        match allowed {
+            Some(len) if len > segment_count(&deny.pattern) => None,
+            _ => Some(deny),
+        }
+    }
+
+    // Importing a denied module is tolerated when items below it are allowed:
+    // every path reached through the import is resolved and checked on use.
+    fn denied_import(&self, path: &str) -> Option<&PolicyRule> {
+        let rule = self.denied_path(path)?;
+        let exposes_allowed = self.allow_paths.iter().any(|p| {
+            p.strip_prefix(path)
+                .is_some_and(|rest| rest.starts_with("::"))
+        });
+
+        if exposes_allowed { None } else { Some(rule) }
+    }
+}
+
+pub fn load_policy() -> &'static CodePolicy {
+    static POLICY: OnceLock<CodePolicy> = OnceLock::new();
+    POLICY.get_or_init(CodePolicy::from_env)
+}
+
+pub fn load_miri_policy() -> &'static CodePolicy {
+    static POLICY: OnceLock<CodePolicy> = OnceLock::new();
+    POLICY.get_or_init(|| load_policy().for_miri())
+}
+
+/// Standard library macros named like a module, which one import brings in
+/// together.
+const MODULE_NAMED_MACROS: &[&str] = &["env"];
+
+fn segment_count(path: &str) -> usize {
+    path.split("::").count()
+}
+
+fn path_matches(path: &str, pattern: &str) -> bool {
+    path == pattern
+        || path
+            .strip_prefix(pattern)
+            .is_some_and(|rest| rest.starts_with("::"))
+}
+
+fn canonical_root(root: &str) -> &str {
+    match root {
+        "core" | "alloc" => "std",
+        other => other,
+    }
+}
+
+fn to_source_span(start: Span, end: Span) -> SourceSpan {
+    let (s, e) = (start.start(), end.end());
+    SourceSpan {
+        line_start: s.line,
+        column_start: s.column + 1,
+        line_end: e.line,
+        column_end: e.column + 1,
+    }
+}
+
+struct PolicyAnalyzer<'a> {
+    policy: &'a CodePolicy,
+    aliases: HashMap<String, String>,
+    glob_prefixes: Vec<String>,
+    seen: HashSet<PolicyViolation>,
+    violations: Vec<PolicyViolation>,
+}
+
+impl<'a> PolicyAnalyzer<'a> {
+    fn new(policy: &'a CodePolicy) -> Self {
+        Self {
+            policy,
+            aliases: HashMap::new(),
+            glob_prefixes: vec![],
+            seen: HashSet::new(),
+            violations: vec![],
+        }
+    }
+
+    fn report(&mut self, rule: &PolicyRule, default_message: String, span: SourceSpan) {
+        let violation = PolicyViolation {
+            rule_id: rule.id.clone(),
+            message: rule.message.clone().unwrap_or(default_message),
+            span,
+        };
+        if self.seen.insert(violation.clone()) {
+            self.violations.push(violation);
+        }
+    }
+
+    fn report_builtin(&mut self, rule_id: &str, message: &str, span: SourceSpan) {
+        let violation = PolicyViolation {
+            rule_id: rule_id.to_string(),
+            message: message.to_string(),
+            span,
+        };
+        if self.seen.insert(violation.clone()) {
+            self.violations.push(violation);
+        }
+    }
+
+    fn resolve(&self, segments: &[String]) -> Vec<String> {
+        let Some(first) = segments.first() else {
+            return vec![];
+        };
+
+        let mut resolved = match self.aliases.get(first) {
+            Some(target) => {
+                let mut path = target.clone();
+                for seg in &segments[1..] {
+                    path.push_str("::");
+                    path.push_str(seg);
+                }
+                vec![path]
+            }
+            None => vec![segments.join("::")],
+        };
+
+        if !self.aliases.contains_key(first) {
+            for prefix in &self.glob_prefixes {
+                resolved.push(format!("{}::{}", prefix, segments.join("::")));
+            }
+        }
+
+        resolved
+            .into_iter()
+            .map(|p| {
+                let p = p.trim_start_matches("::");
+                match p.split_once("::") {
+                    Some((root, rest)) => format!("{}::{}", canonical_root(root), rest),
+                    None => canonical_root(p).to_string(),
+                }
+            })
+            .collect()
+    }
+
+    fn check_path(&mut self, segments: &[String], span: SourceSpan) {
+        let policy = self.policy;
+        for path in self.resolve(segments) {
+            if let Some(rule) = policy.denied_path(&path) {
+                self.report(
+                    rule,
+                    format!("Segurança: O uso de '{}' não é permitido.", rule.pattern),
+                    span,
+                );
+            }
+        }
+    }
+
+    fn check_import(&mut self, segments: &[String], span: SourceSpan) {
+        let policy = self.policy;
+        for path in self.resolve(segments) {
+            if let Some(rule) = policy.denied_import(&path) {
+                self.report(
+                    rule,
+                    format!("Segurança: O uso de '{}' não é permitido.", rule.pattern),
+                    span,
+                );
+            }
+        }
+    }
+
+    // Macros are matched by name, after resolving the path through imports,
+    // so a renamed `include_str!` is still caught.
+    fn check_macro(&mut self, segments: &[String], span: SourceSpan) {
+        let policy = self.policy;
+        let mut names: Vec<String> = segments.last().into_iter().cloned().collect();
+        for path in self.resolve(segments) {
+            if let Some(name) = path.rsplit("::").next() {
+                names.push(name.to_string());
+            }
+        }
+
+        for name in names {
+            if let Some(rule) = policy.deny_macros.iter().find(|r| r.pattern == name) {
+                self.report(
+                    rule,
+                    format!("Segurança: A macro '{}!' não é permitida.", name),
+                    span,
+                );
+            }
+        }
+    }
+
+    fn check_unsafe(&mut self, span: SourceSpan) {
+        if self.policy.deny_unsafe {
+            self.report_builtin(
+                "unsafe",
+                "Segurança: O uso de 'unsafe' não é permitido.",
+                span,
+            );
+        }
+    }
+
+    // Edition 2024 writes attributes such as `no_mangle` as
+    // `#[unsafe(no_mangle)]`: the wrapper counts as `unsafe` and the
+    // attribute inside is checked like any other.
+    fn check_meta(&mut self, meta: &syn::Meta) {
+        let path = meta.path();
+        if path.is_ident("unsafe")
+            && let syn::Meta::List(list) = meta
+        {
+            self.check_unsafe(syn_path_span(path));
+            match list.parse_args::<syn::Meta>() {
+                Ok(inner) => self.check_meta(&inner),
+                Err(_) => self.scan_tokens(list.tokens.clone()),
+            }
+            return;
+        }
+
+        let policy = self.policy;
+        if let Some(name) = path.segments.last().map(|s| s.ident.to_string())
+            && let Some(rule) = policy.deny_attributes.iter().find(|r| r.pattern == name)
+        {
+            self.report(
+                rule,
+                format!("Segurança: O atributo '#[{}]' não é permitido.", name),
+                syn_path_span(path),
+            );
+        }
+
+        match meta {
+            syn::Meta::Path(_) => {}
+            syn::Meta::List(list) => self.scan_tokens(list.tokens.clone()),
+            syn::Meta::NameValue(name_value) => self.visit_expr(&name_value.value),
+        }
+    }
+
+    fn collect_aliases(&mut self, file: &syn::File) {
+        struct AliasCollector<'b, 'a>(&'b mut PolicyAnalyzer<'a>);
+
+        impl<'ast> Visit<'ast> for AliasCollector<'_, '_> {
+            fn visit_item_use(&mut self, node: &'ast syn::ItemUse) {
+                let prefix = if node.leading_colon.is_some() {
+                    vec![String::new()]
+                } else {
+                    vec![]
+                };
+                self.0.register_use_tree(&node.tree, prefix);
+            }
+
+            fn visit_item_extern_crate(&mut self, node: &'ast syn::ItemExternCrate) {
+                if let Some((_, alias)) = &node.rename {
+                    self.0
+                        .aliases
+                        .insert(alias.to_string(), node.ident.to_string());
+                }
+            }
+        }
+
+        AliasCollector(self).visit_file(file);
+    }
+
+    fn register_use_tree(&mut self, tree: &syn::UseTree, prefix: Vec<String>) {
+        match tree {
+            syn::UseTree::Path(p) => {
+                let mut next = prefix;
+                next.push(p.ident.to_string());
+                self.register_use_tree(&p.tree, next);
+            }
+            syn::UseTree::Name(n) => {
+                let name = n.ident.to_string();
+                if name == "self" {
+                    if let Some(last) = prefix.last() {
+                        self.aliases.insert(last.clone(), prefix.join("::"));
+                    }
+                } else {
+                    let mut full = prefix;
+                    full.push(name.clone());
+                    if full.len() > 1 {
+                        self.aliases.insert(name, full.join("::"));
+                    }
+                }
+            }
+            syn::UseTree::Rename(r) => {
+                let target = if r.ident == "self" {
+                    prefix.join("::")
+                } else {
+                    let mut full = prefix;
+                    full.push(r.ident.to_string());
+                    full.join("::")
+                };
+                self.aliases.insert(r.rename.to_string(), target);
+            }
+            syn::UseTree::Glob(_) => {
+                if !prefix.is_empty() {
+                    self.glob_prefixes.push(prefix.join("::"));
+                }
+            }
+            syn::UseTree::Group(g) => {
+                for item in &g.items {
+                    self.register_use_tree(item, prefix.clone());
+                }
+            }
+        }
+    }
+
+    // A denied macro imported from the standard library is refused when the
+    // import renames it, or when nothing else shares its name: `use std::env`
+    // also brings in the module, and `env!` itself is still caught by name.
+    fn check_macro_import(&mut self, segments: &[String], renamed: bool, span: SourceSpan) {
+        let policy = self.policy;
+        for path in self.resolve(segments) {
+            let Some(name) = path
+                .strip_prefix("std::")
+                .and_then(|p| p.rsplit("::").next())
+            else {
+                continue;
+            };
+            if !renamed && MODULE_NAMED_MACROS.contains(&name) {
+                continue;
+            }
+            if let Some(rule) = policy.deny_macros.iter().find(|r| r.pattern == name) {
+                self.report(
+                    rule,
+                    format!("Segurança: A macro '{}!' não é permitida.", name),
+                    span,
+                );
+            }
+        }
+    }
+
+    fn check_use_tree(&mut self, tree: &syn::UseTree, prefix: Vec<String>, start: Span) {
+        match tree {
+            syn::UseTree::Path(p) => {
+                let mut next = prefix;
+                next.push(p.ident.to_string());
+                self.check_use_tree(&p.tree, next, start);
+            }
+            syn::UseTree::Name(n) => {
+                let mut full = prefix;
+                if n.ident != "self" {
+                    full.push(n.ident.to_string());
+                }
+                let span = to_source_span(start, n.ident.span());
+                self.check_import(&full, span);
+                self.check_macro_import(&full, false, span);
+            }
+            syn::UseTree::Rename(r) => {
+                let mut full = prefix;
+                if r.ident != "self" {
+                    full.push(r.ident.to_string());
+                }
+                let span = to_source_span(start, r.ident.span());
+                self.check_import(&full, span);
+                self.check_macro_import(&full, true, span);
+            }
+            syn::UseTree::Glob(g) => {
+                self.check_import(&prefix, to_source_span(start, g.star_token.span));
+            }
+            syn::UseTree::Group(g) => {
+                for item in &g.items {
+                    self.check_use_tree(item, prefix.clone(), start);
+                }
+            }
+        }
+    }
+
+    fn scan_tokens(&mut self, tokens: TokenStream) {
+        let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+        let mut i = 0;
+
+        while i < tokens.len() {
+            match &tokens[i] {
+                TokenTree::Group(g) => {
+                    self.scan_tokens(g.stream());
+                    i += 1;
+                }
+                TokenTree::Ident(ident) if ident == "unsafe" => {
+                    self.check_unsafe(to_source_span(ident.span(), ident.span()));
+                    i += 1;
+                }
+                TokenTree::Ident(ident) if ident == "extern" => {
+                    let is_block = tokens[i + 1..].iter().take(2).any(
+                        |t| matches!(t, TokenTree::Group(g) if g.delimiter() == Delimiter::Brace),
+                    );
+                    if is_block && self.policy.deny_extern_blocks {
+                        self.report_builtin(
+                            "extern-block",
+                            "Segurança: Blocos 'extern' não são permitidos.",
+                            to_source_span(ident.span(), ident.span()),
+                        );
+                    }
+                    i += 1;
+                }
+                TokenTree::Ident(_) | TokenTree::Punct(_) => {
+                    let (segments, start, end, next) = read_token_path(&tokens, i);
+                    if segments.is_empty() {
+                        i = next.max(i + 1);
+                        continue;
+                    }
+
+                    let span = to_source_span(start, end);
+                    let is_macro =
+                        matches!(tokens.get(next), Some(TokenTree::Punct(p)) if p.as_char() == '!');
+                    if is_macro {
+                        self.check_macro(&segments, span);
+                    } else {
+                        // Imports are not tracked here, so a denied macro
+                        // is refused wherever its path is spelled out.
+                        self.check_macro_import(&segments, false, span);
+                    }
+                    self.check_path(&segments, span);
+                    i = next;
+                }
+                TokenTree::Literal(_) => i += 1,
+            }
+        }
+    }
+}
+
+fn read_token_path(tokens: &[TokenTree], from: usize) -> (Vec<String>, Span, Span, usize) {
+    let mut segments = vec![];
+    let mut i = from;
+    let start = tokens[from].span();
+    let mut end = start;
+
+    if is_path_sep(tokens, i) {
+        segments.push(String::new());
+        i += 2;
+    }
+
+    while let Some(TokenTree::Ident(ident)) = tokens.get(i) {
+        segments.push(ident.to_string());
+        end = ident.span();
+        i += 1;
+
+        if is_path_sep(tokens, i) {
+            i += 2;
+        } else {
+            break;
+        }
+    }
+
+    if segments.iter().all(|s| s.is_empty()) {
+        return (vec![], start, end, i);
+    }
+
+    (segments, start, end, i)
+}
+
+fn is_path_sep(tokens: &[TokenTree], i: usize) -> bool {
+    matches!(
+        (tokens.get(i), tokens.get(i + 1)),
+        (Some(TokenTree::Punct(a)), Some(TokenTree::Punct(b)))
+            if a.as_char() == ':' && b.as_char() == ':'
+    )
+}
+
+fn syn_path_segments(path: &syn::Path) -> Vec<String> {
+    let mut segments = vec![];
+    if path.leading_colon.is_some() {
+        segments.push(String::new());
+    }
+    segments.extend(path.segments.iter().map(|s| s.ident.to_string()));
+    segments
+}
+
+fn syn_path_span(path: &syn::Path) -> SourceSpan {
+    let start = match &path.leading_colon {
+        Some(colon) => colon.spans[0],
+        None => path
+            .segments
+            .first()
+            .map(|s| s.ident.span())
+            .unwrap_or_else(Span::call_site),
+    };
+    let end = path
+        .segments
+        .last()
+        .map(|s| s.ident.span())
+        .unwrap_or(start);
+    to_source_span(start, end)
+}
+
+impl<'ast> Visit<'ast> for PolicyAnalyzer<'_> {
+    fn visit_item_use(&mut self, node: &'ast syn::ItemUse) {
+        let prefix = if node.leading_colon.is_some() {
+            vec![String::new()]
+        } else {
+            vec![]
+        };
+        self.check_use_tree(&node.tree, prefix, node.use_token.span);
+        visit::visit_item_use(self, node);
+    }
+
+    fn visit_item_extern_crate(&mut self, node: &'ast syn::ItemExternCrate) {
+        let span = to_source_span(node.extern_token.span, node.ident.span());
+        self.check_path(&[node.ident.to_string()], span);
+        visit::visit_item_extern_crate(self, node);
+    }
+
+    fn visit_path(&mut self, node: &'ast syn::Path) {
+        self.check_path(&syn_path_segments(node), syn_path_span(node));
+        visit::visit_path(self, node);
+    }
+
+    fn visit_macro(&mut self, node: &'ast syn::Macro) {
+        self.check_macro(&syn_path_segments(&node.path), syn_path_span(&node.path));
+        self.scan_tokens(node.tokens.clone());
+        visit::visit_macro(self, node);
+    }
+
+    fn visit_attribute(&mut self, node: &'ast syn::Attribute) {
+        self.check_meta(&node.meta);
+    }
+
+    fn visit_item_foreign_mod(&mut self, node: &'ast syn::ItemForeignMod) {
+        if self.policy.deny_extern_blocks {
+            let span = to_source_span(node.abi.extern_token.span, node.abi.extern_token.span);
+            self.report_builtin(
+                "extern-block",
+                "Segurança: Blocos 'extern' não são permitidos.",
+                span,
+            );
+        }
+        if let Some(unsafety) = &node.unsafety {
+            self.check_unsafe(to_source_span(unsafety.span, unsafety.span));
+        }
+        visit::visit_item_foreign_mod(self, node);
+    }
+
+    fn visit_expr_unsafe(&mut self, node: &'ast syn::ExprUnsafe) {
+        let span = node.unsafe_token.span;
+        self.check_unsafe(to_source_span(span, span));
+        visit::visit_expr_unsafe(self, node);
+    }
+
+    fn visit_signature(&mut self, node: &'ast syn::Signature) {
+        if let Some(unsafety) = &node.unsafety {
+            self.check_unsafe(to_source_span(unsafety.span, unsafety.span));
+        }
+        visit::visit_signature(self, node);
+    }
+
+    fn visit_item_impl(&mut self, node: &'ast syn::ItemImpl) {
+        if let Some(unsafety) = &node.unsafety {
+            self.check_unsafe(to_source_span(unsafety.span, unsafety.span));
+        }
+        visit::visit_item_impl(self, node);
+    }
+
+    fn visit_item_trait(&mut self, node: &'ast syn::ItemTrait) {
+        if let Some(unsafety) = &node.unsafety {
+            self.check_unsafe(to_source_span(unsafety.span, unsafety.span));
+        }
+        visit::visit_item_trait(self, node);
+    }
+
+    fn visit_type_bare_fn(&mut self, node: &'ast syn::TypeBareFn) {
+        if let Some(unsafety) = &node.unsafety {
+            self.check_unsafe(to_source_span(unsafety.span, unsafety.span));
+        }
+        visit::visit_type_bare_fn(self, node);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(code: &str) -> Vec<String> {
+        let mut ids: Vec<String> = CodePolicy::default()
+            .check(code)
+            .into_iter()
+            .map(|v| v.rule_id)
+            .collect();
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+
+    #[test]
+    fn denies_paths_through_aliases_and_globs() {
+        assert_eq!(
+            rules("use std::fs as f;\nfn main() { f::read(\"x\"); }"),
+            ["fs"]
+        );
+        assert_eq!(rules("use std::*;\nfn main() { fs::read(\"x\"); }"), ["fs"]);
+        assert_eq!(
+            rules("use std::process::{self as p};\nfn main() { p::exit(0); }"),
+            ["process"]
+        );
+        assert_eq!(
+            rules("extern crate std as s;\nfn main() { s::net::TcpStream::connect(\"x\"); }"),
+            ["net"]
+        );
+    }
+
+    #[test]
+    fn treats_core_and_alloc_as_std() {
+        assert_eq!(
+            rules("fn main() { let _ = core::mem::size_of::<u8>(); }"),
+            ["mem"]
+        );
+        assert_eq!(rules("use alloc::alloc::alloc;\nfn main() {}"), ["alloc"]);
+        assert_eq!(rules("use ::core::ptr;\nfn main() {}"), ["ptr"]);
+    }
+
+    #[test]
+    fn allows_listed_items_below_denied_modules() {
+        let code = "use std::io::{self, Read};\n\
+                    use std::env;\n\
+                    fn main() {\n\
+                        let mut s = String::new();\n\
+                        io::stdin().read_to_string(&mut s).unwrap();\n\
+                        let _ = env::args();\n\
+                    }";
+        assert!(rules(code).is_empty());
+        assert_eq!(rules("fn main() { std::io::copy(); }"), ["io"]);
+    }
+
+    #[test]
+    fn denies_macros_by_resolved_name() {
+        assert_eq!(
+            rules("fn main() { print!(\"{}\", include_str!(\"/x\")); }"),
+            ["include-str"]
+        );
+        assert_eq!(
+            rules("use std::include_str as inc;\nfn main() { print!(\"{}\", inc!(\"/x\")); }"),
+            ["include-str"]
+        );
+        assert_eq!(
+            rules("fn main() { let _ = std::include_bytes!(\"/x\"); }"),
+            ["include-bytes"]
+        );
+        assert_eq!(rules("use core::include;\nfn main() {}"), ["include"]);
+        assert_eq!(rules("use std::option_env;\nfn main() {}"), ["option-env"]);
+        assert_eq!(
+            rules("use std::env as e;\nfn main() { e!(\"HOME\"); }"),
+            ["env", "env-macro"]
+        );
+        assert_eq!(rules("fn main() { env!(\"HOME\"); }"), ["env-macro"]);
+    }
+
+    #[test]
+    fn checks_macros_in_unparsable_code() {
+        assert_eq!(
+            rules("use std::include_str as inc;\nlet x = inc!(\"/x\")"),
+            ["include-str"]
+        );
+    }
+
+    #[test]
+    fn unwraps_unsafe_attributes() {
+        assert_eq!(
+            rules("#[unsafe(no_mangle)]\npub fn f() {}\nfn main() {}"),
+            ["no-mangle", "unsafe"]
+        );
+        assert_eq!(
+            rules("#[unsafe(export_name = \"x\")]\npub fn f() {}\nfn main() {}"),
+            ["export-name", "unsafe"]
+        );
+        assert_eq!(
+            rules("#[unsafe(link_section = \".x\")]\nstatic X: u8 = 0;\nfn main() {}"),
+            ["link-section", "unsafe"]
+        );
+        assert_eq!(
+            rules("#[no_mangle]\npub fn f() {}\nfn main() {}"),
+            ["no-mangle"]
+        );
+    }
+
+    #[test]
+    fn checks_attribute_values() {
+        assert_eq!(
+            rules("#[doc = include_str!(\"/x\")]\nfn main() {}"),
+            ["include-str"]
+        );
+        assert_eq!(
+            rules("#[cfg_attr(all(), doc = include_str!(\"/x\"))]\nfn main() {}"),
+            ["include-str"]
+        );
+        assert!(rules("#[derive(Debug)]\nstruct S;\n#[doc = \"ok\"]\nfn main() {}").is_empty());
+    }
+
+    #[test]
+    fn miri_policy_lifts_unsafe() {
+        let policy = CodePolicy::default().for_miri();
+        let code = "fn main() { let x = 1; let _ = unsafe { std::ptr::read(&x) }; }";
+        assert!(policy.check(code).is_empty());
+        assert_eq!(rules(code), ["ptr", "unsafe"]);
+    }
+}
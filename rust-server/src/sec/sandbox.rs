@@ -0,0 +1,847 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::controllers::utils::{get_parsed_var_from_env, get_var_from_env};
+use crate::sec::dependencies::load_dependency_allowlist;
+
+#[derive(Debug, Clone)]
+pub struct SandboxConfig {
+    pub memory_bytes: u64,
+    pub cpu_seconds: u64,
+    pub max_processes: u64,
+    pub max_file_size_bytes: u64,
+    pub max_open_files: u64,
+    pub wall_timeout_secs: u64,
+    /// Bytes kept from each of stdout and stderr; the rest is discarded.
+    pub max_output_bytes: usize,
+    pub require_isolation: bool,
+    /// Lets tools that pass descriptors between their own processes run,
+    /// such as cargo; the pair cannot reach outside the sandbox.
+    pub allow_socketpair: bool,
+    /// Directories covered by an empty read-only filesystem, such as the
+    /// server's own directory with its configuration and every workspace.
+    pub hidden_dirs: Vec<PathBuf>,
+    /// Paths below `hidden_dirs` mounted back, read-only, for the programs
+    /// that need them. The scratch directory and the program itself are
+    /// always mounted back.
+    pub exposed_paths: Vec<PathBuf>,
+}
+
+impl SandboxConfig {
+    pub fn from_env() -> Self {
+        Self {
+            memory_bytes: get_parsed_var_from_env::<u64>("SANDBOX_MEMORY_MB", 256) * 1024 * 1024,
+            cpu_seconds: get_parsed_var_from_env("SANDBOX_CPU_SECONDS", 5),
+            max_processes: get_parsed_var_from_env("SANDBOX_MAX_PROCESSES", 64),
+            max_file_size_bytes: get_parsed_var_from_env::<u64>("SANDBOX_MAX_FILE_SIZE_KB", 1024)
+                * 1024,
+            max_open_files: get_parsed_var_from_env("SANDBOX_MAX_OPEN_FILES", 64),
+            wall_timeout_secs: get_parsed_var_from_env("SANDBOX_TIMEOUT_SECONDS", 5),
+            max_output_bytes: get_parsed_var_from_env::<usize>("SANDBOX_MAX_OUTPUT_KB", 64) * 1024,
+            require_isolation: get_parsed_var_from_env("SANDBOX_REQUIRE_ISOLATION", true),
+            allow_socketpair: false,
+            hidden_dirs: match get_var_from_env("SANDBOX_HIDDEN_DIRS")
+                .ok()
+                .map(|dirs| {
+                    std::env::split_paths(&dirs)
+                        .filter(|dir| !dir.as_os_str().is_empty())
+                        .collect::<Vec<_>>()
+                })
+                .filter(|dirs| !dirs.is_empty())
+            {
+                Some(dirs) => dirs,
+                // The server's directory holds its configuration and every
+                // workspace, the home directory its credentials and toolchains.
+                None => [
+                    std::env::current_dir().ok(),
+                    get_var_from_env("HOME").ok().map(PathBuf::from),
+                ]
+                .into_iter()
+                .flatten()
+                .collect(),
+            },
+            exposed_paths: load_dependency_allowlist()
+                .vendor_dir
+                .iter()
+                .map(PathBuf::from)
+                .collect(),
+        }
+    }
+
+    /// Whether `path` lies in one of the hidden directories.
+    pub fn hides(&self, path: &Path) -> bool {
+        let Ok(path) = std::fs::canonicalize(path) else {
+            return false;
+        };
+        self.hidden_dirs
+            .iter()
+            .filter_map(|dir| std::fs::canonicalize(dir).ok())
+            .any(|dir| dir.parent().is_some() && path.starts_with(dir))
+    }
+
+    /// Also mounts `paths` back inside the hidden directories.
+    pub fn exposing<P: AsRef<Path>>(mut self, paths: impl IntoIterator<Item = P>) -> Self {
+        self.exposed_paths
+            .extend(paths.into_iter().map(|p| p.as_ref().to_path_buf()));
+        self
+    }
+}
+
+pub fn load_sandbox_config() -> &'static SandboxConfig {
+    static CONFIG: OnceLock<SandboxConfig> = OnceLock::new();
+    CONFIG.get_or_init(SandboxConfig::from_env)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum Termination {
+    Exited { code: i32 },
+    Signaled { signal: i32 },
+    Timeout,
+    CpuTimeLimit,
+    MemoryLimit,
+    FileSizeLimit,
+    SyscallViolation,
+    SandboxFailure { detail: String },
+    Cancelled,
+}
+
+impl Termination {
+    /// Decided by the exit status: the program writes its own stderr, so the
+    /// text only tells which limit made it fail, never that it failed.
+    /// `cpu_limit_reached` is whether the program used up its CPU time.
+    pub fn from_status(status: &ExitStatus, stderr: &str, cpu_limit_reached: bool) -> Self {
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+
+            if let Some(signal) = status.signal() {
+                return match signal {
+                    libc::SIGXCPU => Termination::CpuTimeLimit,
+                    // As PID 1 of its namespace the program ignores SIGXCPU, so the
+                    // CPU limit shows up as the SIGKILL sent at the hard limit.
+                    libc::SIGKILL if cpu_limit_reached => Termination::CpuTimeLimit,
+                    libc::SIGXFSZ => Termination::FileSizeLimit,
+                    libc::SIGSYS => Termination::SyscallViolation,
+                    // A refused allocation aborts; as PID 1 the program ignores
+                    // SIGABRT and abort() falls back to a fault.
+                    libc::SIGABRT | libc::SIGSEGV | libc::SIGILL | libc::SIGTRAP
+                        if stderr.contains("memory allocation of") =>
+                    {
+                        Termination::MemoryLimit
+                    }
+                    other => Termination::Signaled { signal: other },
+                };
+            }
+        }
+
+        let code = status.code().unwrap_or(-1);
+        // The file size limit makes writes fail instead of killing the program.
+        if code != 0 && (stderr.contains("FileTooLarge") || stderr.contains("File too large")) {
+            return Termination::FileSizeLimit;
+        }
+        Termination::Exited { code }
+    }
+
+    pub fn is_violation(&self) -> bool {
+        matches!(
+            self,
+            Termination::Timeout
+                | Termination::CpuTimeLimit
+                | Termination::MemoryLimit
+                | Termination::FileSizeLimit
+                | Termination::SyscallViolation
+        )
+    }
+
+    pub fn describe(&self) -> Option<String> {
+        match self {
+            Termination::Exited { .. } => None,
+            Termination::Signaled { signal } => {
+                Some(format!("Processo encerrado pelo sinal {}.", signal))
+            }
+            Termination::Timeout => Some("Erro: Tempo limite de execução excedido.".into()),
+            Termination::CpuTimeLimit => Some("Erro: Limite de tempo de CPU excedido.".into()),
+            Termination::MemoryLimit => Some("Erro: Limite de memória excedido.".into()),
+            Termination::FileSizeLimit => {
+                Some("Erro: Limite de tamanho de arquivo excedido.".into())
+            }
+            Termination::SyscallViolation => {
+                Some("Segurança: O programa tentou uma chamada de sistema proibida.".into())
+            }
+            Termination::SandboxFailure { detail } => {
+                Some(format!("Erro interno ao isolar a execução: {}", detail))
+            }
+            Termination::Cancelled => Some("Execução cancelada pelo usuário.".into()),
+        }
+    }
+}
+
+pub fn apply_sandbox(
+    cmd: &mut Command,
+    config: &SandboxConfig,
+    scratch_dir: &Path,
+) -> io::Result<()> {
+    cmd.env_clear()
+        .env("PATH", "/usr/local/bin:/usr/bin:/bin")
+        .env("HOME", scratch_dir)
+        .env("TMPDIR", scratch_dir)
+        .current_dir(scratch_dir);
+
+    #[cfg(target_os = "linux")]
+    {
+        let program = PathBuf::from(cmd.as_std().get_program());
+        let mut plan = linux::SandboxPlan::new(config, scratch_dir, &program)?;
+        unsafe {
+            cmd.pre_exec(move || plan.enter());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        if config.require_isolation {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "isolamento de processos só está disponível no Linux",
+            ));
+        }
+        eprintln!("AVISO: Executando sem sandbox de sistema operacional.");
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::ffi::{CStr, CString};
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+
+    use seccompiler::{
+        BpfProgram, SeccompAction, SeccompCmpArgLen, SeccompCmpOp, SeccompCondition, SeccompFilter,
+        SeccompRule, TargetArch,
+    };
+
+    use super::SandboxConfig;
+
+    const SANDBOX_INNER_ID: u32 = 1000;
+
+    const NAMESPACES: libc::c_int = libc::CLONE_NEWUSER
+        | libc::CLONE_NEWNS
+        | libc::CLONE_NEWNET
+        | libc::CLONE_NEWPID
+        | libc::CLONE_NEWIPC
+        | libc::CLONE_NEWUTS;
+
+    const DENIED_SYSCALLS: &[libc::c_long] = &[
+        libc::SYS_socket,
+        libc::SYS_socketpair,
+        libc::SYS_connect,
+        libc::SYS_bind,
+        libc::SYS_listen,
+        libc::SYS_accept,
+        libc::SYS_accept4,
+        libc::SYS_ptrace,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+        libc::SYS_mount,
+        libc::SYS_umount2,
+        libc::SYS_pivot_root,
+        libc::SYS_chroot,
+        libc::SYS_unshare,
+        libc::SYS_setns,
+        libc::SYS_kexec_load,
+        libc::SYS_kexec_file_load,
+        libc::SYS_reboot,
+        libc::SYS_init_module,
+        libc::SYS_finit_module,
+        libc::SYS_delete_module,
+        libc::SYS_bpf,
+        libc::SYS_perf_event_open,
+        libc::SYS_keyctl,
+        libc::SYS_add_key,
+        libc::SYS_request_key,
+        libc::SYS_personality,
+        libc::SYS_userfaultfd,
+        libc::SYS_io_uring_setup,
+        libc::SYS_io_uring_enter,
+        libc::SYS_io_uring_register,
+        libc::SYS_swapon,
+        libc::SYS_swapoff,
+        libc::SYS_acct,
+        libc::SYS_syslog,
+        libc::SYS_open_tree,
+        libc::SYS_move_mount,
+        libc::SYS_fsopen,
+        libc::SYS_fsconfig,
+        libc::SYS_fsmount,
+        libc::SYS_fspick,
+        libc::SYS_mount_setattr,
+        libc::SYS_open_by_handle_at,
+    ];
+
+    /// `clone` flags that would create namespaces, where the program could
+    /// become root again.
+    const DENIED_CLONE_FLAGS: &[libc::c_int] = &[
+        libc::CLONE_NEWUSER,
+        libc::CLONE_NEWNS,
+        libc::CLONE_NEWNET,
+        libc::CLONE_NEWPID,
+        libc::CLONE_NEWIPC,
+        libc::CLONE_NEWUTS,
+        libc::CLONE_NEWCGROUP,
+        CLONE_NEWTIME,
+    ];
+
+    const CLONE_NEWTIME: libc::c_int = 0x80;
+
+    const HIDDEN_DIR_OPTIONS: &CStr = c"mode=0755,size=64k";
+
+    /// A path mounted back over the empty filesystem hiding it.
+    struct ExposedPath {
+        path: CString,
+        /// Directories between the hidden one and `path`, created in order
+        /// to hold the mount.
+        parents: Vec<CString>,
+        is_dir: bool,
+        writable: bool,
+    }
+
+    struct HiddenDir {
+        path: CString,
+        exposed: Vec<ExposedPath>,
+    }
+
+    pub struct SandboxPlan {
+        uid_map: Vec<u8>,
+        gid_map: Vec<u8>,
+        scratch: CString,
+        mount_points: Vec<CString>,
+        hidden: Vec<HiddenDir>,
+        /// One descriptor per exposed path, opened before it is hidden.
+        exposed_fds: Vec<libc::c_int>,
+        limits: Vec<(libc::c_int, libc::rlim_t, libc::rlim_t)>,
+        filters: Vec<BpfProgram>,
+        require_isolation: bool,
+    }
+
+    impl SandboxPlan {
+        pub fn new(config: &SandboxConfig, scratch_dir: &Path, program: &Path) -> io::Result<Self> {
+            let scratch = std::fs::canonicalize(scratch_dir)?;
+            let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+
+            let mut exposed = vec![(scratch.clone(), true)];
+            exposed.extend(
+                config
+                    .exposed_paths
+                    .iter()
+                    .map(PathBuf::as_path)
+                    .chain(program.is_absolute().then_some(program))
+                    .filter_map(|path| std::fs::canonicalize(path).ok())
+                    .map(|path| (path, false)),
+            );
+            let hidden = plan_hidden_dirs(&config.hidden_dirs, exposed)?;
+            let exposed_count = hidden.iter().map(|dir| dir.exposed.len()).sum();
+
+            Ok(Self {
+                uid_map: format!("{} {} 1\n", SANDBOX_INNER_ID, uid).into_bytes(),
+                gid_map: format!("{} {} 1\n", SANDBOX_INNER_ID, gid).into_bytes(),
+                scratch: CString::new(scratch.as_os_str().as_bytes())?,
+                mount_points: read_mount_points()?,
+                hidden,
+                exposed_fds: vec![-1; exposed_count],
+                limits: vec![
+                    (
+                        libc::RLIMIT_AS as libc::c_int,
+                        config.memory_bytes,
+                        config.memory_bytes,
+                    ),
+                    (
+                        libc::RLIMIT_CPU as libc::c_int,
+                        config.cpu_seconds,
+                        config.cpu_seconds + 1,
+                    ),
+                    (
+                        libc::RLIMIT_NPROC as libc::c_int,
+                        config.max_processes,
+                        config.max_processes,
+                    ),
+                    (
+                        libc::RLIMIT_FSIZE as libc::c_int,
+                        config.max_file_size_bytes,
+                        config.max_file_size_bytes,
+                    ),
+                    (
+                        libc::RLIMIT_NOFILE as libc::c_int,
+                        config.max_open_files,
+                        config.max_open_files,
+                    ),
+                    (libc::RLIMIT_CORE as libc::c_int, 0, 0),
+                ],
+                filters: build_filters(config.allow_socketpair)?,
+                require_isolation: config.require_isolation,
+            })
+        }
+
+        // Runs in the forked child, before exec: only async-signal-safe calls here.
+        pub fn enter(&mut self) -> io::Result<()> {
+            if unsafe { libc::unshare(NAMESPACES) } == 0 {
+                write_file(c"/proc/self/setgroups", b"deny")?;
+                write_file(c"/proc/self/uid_map", &self.uid_map)?;
+                write_file(c"/proc/self/gid_map", &self.gid_map)?;
+                self.setup_mounts()?;
+                fork_into_pid_namespace()?;
+            } else if self.require_isolation {
+                return Err(io::Error::last_os_error());
+            }
+
+            self.confine()
+        }
+
+        fn setup_mounts(&mut self) -> io::Result<()> {
+            let none: *const libc::c_char = std::ptr::null();
+
+            check(unsafe {
+                libc::mount(
+                    none,
+                    c"/".as_ptr(),
+                    none,
+                    libc::MS_REC | libc::MS_PRIVATE,
+                    std::ptr::null(),
+                )
+            })?;
+
+            check(unsafe {
+                libc::mount(
+                    self.scratch.as_ptr(),
+                    self.scratch.as_ptr(),
+                    none,
+                    libc::MS_BIND,
+                    std::ptr::null(),
+                )
+            })?;
+
+            for mount_point in &self.mount_points {
+                let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+                if unsafe { libc::statvfs(mount_point.as_ptr(), &mut stat) } != 0 {
+                    continue;
+                }
+
+                let flags =
+                    libc::MS_REMOUNT | libc::MS_BIND | libc::MS_RDONLY | locked_flags(stat.f_flag);
+                let rc = unsafe {
+                    libc::mount(none, mount_point.as_ptr(), none, flags, std::ptr::null())
+                };
+
+                if rc != 0 && mount_point.as_bytes() == b"/" {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+
+            self.hide_dirs()
+        }
+
+        // Covers each hidden directory with an empty tmpfs and mounts the
+        // exposed paths back through descriptors opened while still visible.
+        fn hide_dirs(&mut self) -> io::Result<()> {
+            let none: *const libc::c_char = std::ptr::null();
+            let exposed = self.hidden.iter().flat_map(|dir| &dir.exposed);
+
+            for (fd, path) in self.exposed_fds.iter_mut().zip(exposed) {
+                *fd = unsafe { libc::open(path.path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+                if *fd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+
+            let mut fds = self.exposed_fds.iter();
+            for dir in &self.hidden {
+                check(unsafe {
+                    libc::mount(
+                        c"tmpfs".as_ptr(),
+                        dir.path.as_ptr(),
+                        c"tmpfs".as_ptr(),
+                        libc::MS_NOSUID | libc::MS_NODEV,
+                        HIDDEN_DIR_OPTIONS.as_ptr().cast(),
+                    )
+                })?;
+
+                for (path, &fd) in dir.exposed.iter().zip(fds.by_ref()) {
+                    mount_exposed(path, fd)?;
+                }
+
+                check(unsafe {
+                    libc::mount(
+                        none,
+                        dir.path.as_ptr(),
+                        none,
+                        libc::MS_REMOUNT
+                            | libc::MS_BIND
+                            | libc::MS_RDONLY
+                            | libc::MS_NOSUID
+                            | libc::MS_NODEV,
+                        std::ptr::null(),
+                    )
+                })?;
+            }
+
+            for &fd in &self.exposed_fds {
+                unsafe { libc::close(fd) };
+            }
+            Ok(())
+        }
+
+        fn confine(&self) -> io::Result<()> {
+            check(unsafe { libc::chdir(self.scratch.as_ptr()) })?;
+
+            for &(resource, soft, hard) in &self.limits {
+                let limit = libc::rlimit {
+                    rlim_cur: soft,
+                    rlim_max: hard,
+                };
+                check(unsafe { libc::setrlimit(resource as _, &limit) })?;
+            }
+
+            for filter in &self.filters {
+                seccompiler::apply_filter(filter)
+                    .map_err(|_| io::Error::from_raw_os_error(libc::EPERM))?;
+            }
+            Ok(())
+        }
+    }
+
+    // Groups the exposed paths under the hidden directory holding them, each
+    // after the paths containing it. Paths outside every hidden directory
+    // stay visible as they are.
+    fn plan_hidden_dirs(
+        hidden_dirs: &[PathBuf],
+        mut exposed: Vec<(PathBuf, bool)>,
+    ) -> io::Result<Vec<HiddenDir>> {
+        let mut dirs: Vec<PathBuf> = hidden_dirs
+            .iter()
+            .filter_map(|dir| std::fs::canonicalize(dir).ok())
+            // Hiding `/` would leave nothing to run.
+            .filter(|dir| dir.parent().is_some())
+            .collect();
+        dirs.sort();
+        dirs.dedup();
+        let nested: Vec<bool> = dirs
+            .iter()
+            .map(|dir| {
+                dirs.iter()
+                    .any(|other| other != dir && dir.starts_with(other))
+            })
+            .collect();
+
+        // A path exposed twice keeps its writable mount.
+        exposed.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+        exposed.dedup_by(|a, b| a.0 == b.0);
+
+        dirs.iter()
+            .zip(nested)
+            .filter(|(_, nested)| !nested)
+            .map(|(dir, _)| {
+                let exposed = exposed
+                    .iter()
+                    .filter(|(path, _)| path.starts_with(dir))
+                    .map(|(path, writable)| {
+                        let mut parents = path
+                            .ancestors()
+                            .skip(1)
+                            .take_while(|parent| *parent != dir.as_path())
+                            .map(|parent| CString::new(parent.as_os_str().as_bytes()))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        parents.reverse();
+
+                        Ok(ExposedPath {
+                            path: CString::new(path.as_os_str().as_bytes())?,
+                            parents,
+                            is_dir: path.is_dir(),
+                            writable: *writable,
+                        })
+                    })
+                    .collect::<io::Result<Vec<_>>>()?;
+
+                Ok(HiddenDir {
+                    path: CString::new(dir.as_os_str().as_bytes())?,
+                    exposed,
+                })
+            })
+            .collect()
+    }
+
+    fn mount_exposed(exposed: &ExposedPath, fd: libc::c_int) -> io::Result<()> {
+        let none: *const libc::c_char = std::ptr::null();
+
+        for parent in &exposed.parents {
+            make_dir(parent)?;
+        }
+        if exposed.is_dir {
+            make_dir(&exposed.path)?;
+        } else {
+            let file = unsafe {
+                libc::open(
+                    exposed.path.as_ptr(),
+                    libc::O_RDONLY | libc::O_CREAT | libc::O_CLOEXEC,
+                    0o444,
+                )
+            };
+            if file >= 0 {
+                unsafe { libc::close(file) };
+            }
+        }
+
+        let mut buf = [0u8; 32];
+        check(unsafe {
+            libc::mount(
+                fd_path(fd, &mut buf).as_ptr(),
+                exposed.path.as_ptr(),
+                none,
+                libc::MS_BIND | libc::MS_REC,
+                std::ptr::null(),
+            )
+        })?;
+        if exposed.writable {
+            return Ok(());
+        }
+
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        check(unsafe { libc::statvfs(exposed.path.as_ptr(), &mut stat) })?;
+        check(unsafe {
+            libc::mount(
+                none,
+                exposed.path.as_ptr(),
+                none,
+                libc::MS_REMOUNT | libc::MS_BIND | libc::MS_RDONLY | locked_flags(stat.f_flag),
+                std::ptr::null(),
+            )
+        })
+    }
+
+    fn make_dir(path: &CStr) -> io::Result<()> {
+        if unsafe { libc::mkdir(path.as_ptr(), 0o755) } != 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(libc::EEXIST) {
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    // `/proc/self/fd/<fd>`, written without allocating.
+    fn fd_path(fd: libc::c_int, buf: &mut [u8; 32]) -> &CStr {
+        const PREFIX: &[u8] = b"/proc/self/fd/";
+        buf[..PREFIX.len()].copy_from_slice(PREFIX);
+
+        let mut digits = [0u8; 10];
+        let mut len = 0;
+        let mut n = fd.unsigned_abs();
+        loop {
+            digits[len] = b'0' + (n % 10) as u8;
+            len += 1;
+            n /= 10;
+            if n == 0 {
+                break;
+            }
+        }
+
+        let mut end = PREFIX.len();
+        for &digit in digits[..len].iter().rev() {
+            buf[end] = digit;
+            end += 1;
+        }
+        buf[end] = 0;
+        unsafe { CStr::from_bytes_with_nul_unchecked(&buf[..=end]) }
+    }
+
+    // The caller stays behind as a reaper that mirrors the exit status of the
+    // sandboxed process, which becomes PID 1 of the new PID namespace.
+    fn fork_into_pid_namespace() -> io::Result<()> {
+        let pid = unsafe { libc::fork() };
+        if pid < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        if pid == 0 {
+            unsafe {
+                libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL);
+                libc::mount(
+                    c"proc".as_ptr(),
+                    c"/proc".as_ptr(),
+                    c"proc".as_ptr(),
+                    libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+                    std::ptr::null(),
+                );
+            }
+            return Ok(());
+        }
+
+        // The reaper never reaches exec, so it must drop the inherited
+        // descriptors itself: one of them is the close-on-exec pipe the parent
+        // blocks on to learn whether exec succeeded.
+        unsafe {
+            if libc::syscall(libc::SYS_close_range, 3u32, u32::MAX, 0u32) != 0 {
+                for fd in 3..1024 {
+                    libc::close(fd);
+                }
+            }
+        }
+
+        let mut status: libc::c_int = 0;
+        loop {
+            let rc = unsafe { libc::waitpid(pid, &mut status, 0) };
+            if rc == pid {
+                break;
+            }
+            if rc < 0 && io::Error::last_os_error().raw_os_error() != Some(libc::EINTR) {
+                unsafe { libc::_exit(1) };
+            }
+        }
+
+        unsafe {
+            let no_core = libc::rlimit {
+                rlim_cur: 0,
+                rlim_max: 0,
+            };
+            libc::setrlimit(libc::RLIMIT_CORE, &no_core);
+
+            if libc::WIFEXITED(status) {
+                libc::_exit(libc::WEXITSTATUS(status));
+            }
+            if libc::WIFSIGNALED(status) {
+                let signal = libc::WTERMSIG(status);
+                libc::signal(signal, libc::SIG_DFL);
+                libc::kill(libc::getpid(), signal);
+            }
+            libc::_exit(1)
+        }
+    }
+
+    // The second filter hides `clone3`, whose flags it cannot read, so the C
+    // library falls back to `clone` and the first filter checks them there.
+    fn build_filters(allow_socketpair: bool) -> io::Result<Vec<BpfProgram>> {
+        let arch = TargetArch::try_from(std::env::consts::ARCH)
+            .map_err(|e| io::Error::new(io::ErrorKind::Unsupported, e.to_string()))?;
+
+        let mut rules: std::collections::BTreeMap<_, _> = DENIED_SYSCALLS
+            .iter()
+            .filter(|&&syscall| !(allow_socketpair && syscall == libc::SYS_socketpair))
+            .map(|&syscall| (syscall, vec![]))
+            .collect();
+        rules.insert(
+            libc::SYS_clone,
+            DENIED_CLONE_FLAGS
+                .iter()
+                .map(|&flag| {
+                    SeccompCondition::new(
+                        0,
+                        SeccompCmpArgLen::Qword,
+                        SeccompCmpOp::MaskedEq(flag as u64),
+                        flag as u64,
+                    )
+                    .and_then(|condition| SeccompRule::new(vec![condition]))
+                })
+                .collect::<Result<_, _>>()
+                .map_err(|e| io::Error::other(e.to_string()))?,
+        );
+
+        let denied = SeccompFilter::new(
+            rules,
+            SeccompAction::Allow,
+            SeccompAction::KillProcess,
+            arch,
+        );
+        let hidden = SeccompFilter::new(
+            [(libc::SYS_clone3, vec![])].into(),
+            SeccompAction::Allow,
+            SeccompAction::Errno(libc::ENOSYS as u32),
+            arch,
+        );
+
+        [denied, hidden]
+            .into_iter()
+            .map(|filter| filter.and_then(BpfProgram::try_from))
+            .collect::<Result<_, _>>()
+            .map_err(|e| io::Error::other(e.to_string()))
+    }
+
+    fn read_mount_points() -> io::Result<Vec<CString>> {
+        let mountinfo = std::fs::read_to_string("/proc/self/mountinfo")?;
+
+        let mut points: Vec<CString> = mountinfo
+            .lines()
+            .filter_map(|line| line.split(' ').nth(4))
+            .filter_map(|raw| CString::new(unescape_mount_point(raw)).ok())
+            .collect();
+        points.sort();
+        points.dedup();
+
+        Ok(points)
+    }
+
+    fn unescape_mount_point(raw: &str) -> Vec<u8> {
+        let bytes = raw.as_bytes();
+        let mut out = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+
+        while i < bytes.len() {
+            if bytes[i] == b'\\'
+                && i + 3 < bytes.len()
+                && let Ok(code) = u8::from_str_radix(&raw[i + 1..i + 4], 8)
+            {
+                out.push(code);
+                i += 4;
+                continue;
+            }
+            out.push(bytes[i]);
+            i += 1;
+        }
+
+        out
+    }
+
+    fn locked_flags(f_flag: libc::c_ulong) -> libc::c_ulong {
+        let mut flags = 0;
+        for (st, ms) in [
+            (libc::ST_NOSUID, libc::MS_NOSUID),
+            (libc::ST_NODEV, libc::MS_NODEV),
+            (libc::ST_NOEXEC, libc::MS_NOEXEC),
+            (libc::ST_NOATIME, libc::MS_NOATIME),
+            (libc::ST_NODIRATIME, libc::MS_NODIRATIME),
+            (libc::ST_RELATIME, libc::MS_RELATIME),
+        ] {
+            if f_flag & st != 0 {
+                flags |= ms;
+            }
+        }
+        flags
+    }
+
+    fn write_file(path: &CStr, content: &[u8]) -> io::Result<()> {
+        let fd = unsafe { libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let written = unsafe { libc::write(fd, content.as_ptr().cast(), content.len()) };
+        unsafe { libc::close(fd) };
+
+        if written < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn check(rc: libc::c_int) -> io::Result<()> {
+        if rc != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}